@@ -0,0 +1,116 @@
+//! Measures the time the cascade takes, and how many elements share the style of a sibling, on:
+//!
+//! - a generated document of about 6,000 elements and a stylesheet of 5,000 rules, the size of a
+//!   CSS framework,
+//! - a generated list of 5,000 identical items,
+//! - ``example/test.html``.
+//!
+//! Run with ``cargo bench --bench style``.
+
+extern crate naglfar;
+
+use naglfar::dom::{Node, StylesheetSource};
+use naglfar::{css, html, layout};
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const SECTIONS: usize = 200;
+const ITEMS: usize = 10;
+const RULES: usize = 5000;
+const LIST_ITEMS: usize = 5000;
+const ITERATIONS: u32 = 5;
+
+fn framework_document() -> String {
+    let mut html = String::from("<html><body>");
+    for section in 0..SECTIONS {
+        html += &format!(
+            "<div id='s{0}' class='section c{0}'><ul class='list'>",
+            section
+        );
+        for item in 0..ITEMS {
+            html += &format!(
+                "<li class='item c{}'><a class='link' href='#'>link</a> <span>text</span></li>",
+                section * ITEMS + item
+            );
+        }
+        html += "</ul></div>";
+    }
+    html + "</body></html>"
+}
+
+// Mostly rules for elements that aren't there, as with a framework, in the usual shapes.
+fn framework_stylesheet() -> String {
+    (0..RULES)
+        .map(|i| match i % 5 {
+            0 => format!(".c{} {{ color: #{:06x} }}\n", i, i),
+            1 => format!("#s{} {{ padding: {}px }}\n", i, i % 10),
+            2 => format!(".section .c{} a {{ margin: 0 {}px }}\n", i, i % 10),
+            3 => format!("ul.x{} > li:first-child {{ border: 1px solid }}\n", i),
+            _ => format!(
+                "article .c{}, nav li.c{} span {{ font-size: {}px }}\n",
+                i,
+                i,
+                i % 30
+            ),
+        })
+        .collect()
+}
+
+fn list_document() -> String {
+    let items: String = (0..LIST_ITEMS)
+        .map(|i| format!("<li class='item'><a href='#{0}'>{0}</a></li>", i))
+        .collect();
+    format!("<html><body><ul class='list'>{}</ul></body></html>", items)
+}
+
+const LIST_STYLESHEET: &str = ".list { padding: 0 } .item { margin: 2px; color: #333 } \
+                               .item a { text-decoration: none } li:first-child { margin: 0 }";
+
+// The stylesheets of ``<style>`` elements and of links to local files.
+fn stylesheets_of(dom: &Node) -> Vec<css::Stylesheet> {
+    dom.find_stylesheets()
+        .into_iter()
+        .filter_map(|stylesheet| match stylesheet.source {
+            StylesheetSource::Link(path) => fs::read_to_string(path).ok(),
+            StylesheetSource::Style(css) => Some(css),
+        })
+        .map(css::parse)
+        .collect()
+}
+
+fn bench(name: &str, dom: &Node, stylesheets: &[css::Stylesheet]) {
+    let mut times = vec![];
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        layout::style_tree(dom, &[], stylesheets, 800.0, 600.0);
+        times.push(start.elapsed());
+    }
+    let millis = |time: Duration| time.as_secs_f64() * 1000.0;
+    let total: Duration = times.iter().sum();
+    let counts = layout::style_sharing_counts();
+    println!(
+        "{}: {:.1} ms on average, {:.1} ms at best over {} runs; \
+         {} of {} elements shared a style ({:.1}%)",
+        name,
+        millis(total / ITERATIONS),
+        millis(*times.iter().min().unwrap()),
+        ITERATIONS,
+        counts.shared,
+        counts.elements,
+        counts.shared as f64 * 100.0 / counts.elements as f64
+    );
+}
+
+fn main() {
+    let dom = html::parse(framework_document(), PathBuf::from("framework.html"));
+    bench("framework", &dom, &[css::parse(framework_stylesheet())]);
+
+    let dom = html::parse(list_document(), PathBuf::from("list.html"));
+    bench("list", &dom, &[css::parse(LIST_STYLESHEET.to_string())]);
+
+    let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/example/test.html"));
+    let dom = html::parse(fs::read_to_string(&path).unwrap(), path);
+    bench("example/test.html", &dom, &stylesheets_of(&dom));
+}
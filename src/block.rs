@@ -192,9 +192,6 @@ impl LayoutBox {
         let cb_width = containing_block.content.width.to_f64_px();
         let d = &mut self.dimensions;
 
-        // margin, border, and padding have initial value 0.
-        let zero = Value::Length(0.0, Unit::Px);
-
         d.margin.top = Au::from_f64_px(margin.0.maybe_percent_to_px(cb_width).unwrap_or(0f64));
         d.margin.bottom = Au::from_f64_px(margin.2.maybe_percent_to_px(cb_width).unwrap_or(0f64));
 
@@ -212,9 +209,7 @@ impl LayoutBox {
         d.padding.top = Au::from_f64_px(padding.0.maybe_percent_to_px(cb_width).unwrap());
         d.padding.bottom = Au::from_f64_px(padding.2.maybe_percent_to_px(cb_width).unwrap());
 
-        self.z_index = self.property.lookup("z-index", "z-index", &vec![zero])[0]
-            .clone()
-            .to_num() as i32;
+        self.z_index = self.property.z_index();
 
         d.content.x = d.margin.left + d.border.left + d.padding.left;
 
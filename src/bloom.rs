@@ -0,0 +1,112 @@
+//! A counting Bloom filter of the tag names, ids and classes of the ancestors of the element
+//! being styled.
+//!
+//! Selector matching walks up the tree for every descendant combinator, which is the most
+//! expensive part of the cascade. Before that walk, ``rule_index`` asks the filter whether the
+//! names a selector requires of the ancestors are there at all. The filter can answer yes for a
+//! name that isn't there, but never no for one that is.
+
+use dom::ElementData;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// Each name sets two counters, picked by two slices of its hash.
+const KEY_BITS: u32 = 12;
+const KEY_MASK: u32 = (1 << KEY_BITS) - 1;
+
+pub struct AncestorFilter {
+    counters: Vec<u8>,
+}
+
+/// Hashes a tag name, id or class for ``AncestorFilter``.
+pub fn hash(name: &str) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+impl AncestorFilter {
+    pub fn new() -> AncestorFilter {
+        AncestorFilter {
+            counters: vec![0; 1 << KEY_BITS],
+        }
+    }
+
+    /// Adds an element entering the chain of ancestors, before its children are styled.
+    pub fn push(&mut self, elem: &ElementData) {
+        for hash in element_hashes(elem) {
+            for key in keys(hash) {
+                let counter = &mut self.counters[key];
+                *counter = counter.saturating_add(1);
+            }
+        }
+    }
+
+    /// Removes an element added by ``push``, after its children are styled.
+    pub fn pop(&mut self, elem: &ElementData) {
+        for hash in element_hashes(elem) {
+            for key in keys(hash) {
+                let counter = &mut self.counters[key];
+                // A saturated counter has lost count, so it stays set.
+                if *counter != u8::MAX {
+                    *counter -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn might_contain(&self, hash: u32) -> bool {
+        keys(hash).iter().all(|&key| self.counters[key] != 0)
+    }
+}
+
+impl Default for AncestorFilter {
+    fn default() -> AncestorFilter {
+        AncestorFilter::new()
+    }
+}
+
+fn keys(hash: u32) -> [usize; 2] {
+    [
+        (hash & KEY_MASK) as usize,
+        ((hash >> KEY_BITS) & KEY_MASK) as usize,
+    ]
+}
+
+fn element_hashes(elem: &ElementData) -> Vec<u32> {
+    let mut hashes = vec![hash(&elem.tag_name)];
+    hashes.extend(elem.id().map(|id| hash(id)));
+    hashes.extend(elem.classes().into_iter().map(hash));
+    hashes
+}
+
+#[test]
+fn test_push_pop() {
+    use dom::{Node, NodeType};
+    use std::collections::HashMap;
+
+    let node = Node::elem(
+        "div".to_string(),
+        [("id", "a"), ("class", "b c")]
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>(),
+        vec![],
+    );
+    let elem = match node.data {
+        NodeType::Element(ref elem) => elem,
+        NodeType::Text(_) => unreachable!(),
+    };
+    let mut filter = AncestorFilter::new();
+    assert!(!filter.might_contain(hash("div")));
+    filter.push(elem);
+    filter.push(elem);
+    for name in &["div", "a", "b", "c"] {
+        assert!(filter.might_contain(hash(name)));
+    }
+    filter.pop(elem);
+    assert!(filter.might_contain(hash("div")));
+    filter.pop(elem);
+    assert!(filter.counters.iter().all(|&counter| counter == 0));
+}
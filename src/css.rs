@@ -1,16 +1,96 @@
-use std::{fmt, collections::HashSet};
+use std::{collections::HashSet, fmt, slice};
 
-use html::remove_comments;
+use css_tokenizer::{Token, Tokenizer};
+use parse_error::ParseError;
+use properties;
+use style::DEFAULT_FONT_SIZE;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stylesheet {
+    pub imports: Vec<Import>,
+    pub font_faces: Vec<FontFace>,
     pub rules: Vec<Rule>,
 }
 
+/// An ``@import`` rule. The imported rules come before the importing stylesheet's own rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// As written, relative to the importing stylesheet.
+    pub url: String,
+    pub media: MediaQueryList,
+}
+
+/// An ``@font-face`` rule, which names a font file that the stylesheet's ``font-family`` can use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFace {
+    pub family: String,
+    /// In order of preference. Files in a ``format()`` other than TrueType and OpenType are left
+    /// out: pango can't read WOFF.
+    pub sources: Vec<FontFaceSource>,
+    /// The ``font-weight`` of the face, ``normal`` by default. Of a range, only the lower bound is
+    /// kept.
+    pub weight: Value,
+    /// The ``font-style`` of the face, ``normal`` by default.
+    pub style: Value,
+    /// The media query lists of the ``@media`` rules the face is in, as for ``Rule::media``.
+    pub media: Vec<MediaQueryList>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontFaceSource {
+    /// A font file, as written, relative to the stylesheet.
+    Url(String),
+    /// ``local()``, the full name of an installed font.
+    Local(String),
+}
+
+// The ``format()`` of the ``@font-face`` sources that can be used.
+const FONT_FORMATS: &[&str] = &["truetype", "opentype", "collection"];
+
+/// Where a stylesheet comes from. Normal declarations of a later origin override those of an
+/// earlier one; ``!important`` declarations reverse the order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+}
+
+/// A media query list such as ``screen, print``. It matches when any of its queries does, or
+/// when it is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList(pub Vec<MediaQuery>);
+
+/// A media query such as ``not print and (min-width: 600px)``: a media type and media features
+/// that all have to match, or the opposite of that if ``negated``.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub negated: bool,
+    /// Lowercase; ``all`` when the query doesn't name one.
+    pub media_type: String,
+    pub features: Vec<MediaFeature>,
+}
+
+/// A media feature test. Lengths are in px; ``(width: 600px)`` is both a minimum and a maximum.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    MinWidth(f64),
+    MaxWidth(f64),
+    MinHeight(f64),
+    MaxHeight(f64),
+    /// ``portrait`` or ``landscape``.
+    Orientation(String),
+    /// ``light`` or ``dark``.
+    PrefersColorScheme(String),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    /// The media query lists of the ``@media`` rules the rule is in, all of which have to match
+    /// for the rule to apply.
+    pub media: Vec<MediaQueryList>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,6 +98,16 @@ pub enum Selector {
     Simple(SimpleSelector),
     Descendant(SimpleSelector, Box<Selector>),
     Child(SimpleSelector, Box<Selector>),
+    NextSibling(SimpleSelector, Box<Selector>),
+    SubsequentSibling(SimpleSelector, Box<Selector>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,12 +115,61 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: HashSet<String>,
+    pub attributes: Vec<AttrSelector>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    /// Pseudo-elements aren't generated yet, so a selector with one matches nothing.
+    pub pseudo_element: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttrSelector {
+    pub name: String,
+    pub operator: AttrOperator,
+    /// Empty for ``AttrOperator::Exists``.
+    pub value: String,
+    /// Set by the ``i`` flag, as in ``[type="a" i]``.
+    pub case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrOperator {
+    Exists,    // [attr]
+    Equals,    // [attr=value]
+    Includes,  // [attr~=value]
+    DashMatch, // [attr|=value]
+    Prefix,    // [attr^=value]
+    Suffix,    // [attr$=value]
+    Substring, // [attr*=value]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    // The elements at positions ``a * n + b`` for n >= 0, counting from 1.
+    NthChild(i32, i32),
+    NthLastChild(i32, i32),
+    NthOfType(i32, i32),
+    NthLastOfType(i32, i32),
+    Not(Vec<SimpleSelector>),
+    /// ``:link`` and ``:any-link``.
+    Link,
+    /// Pseudo-classes such as ``:hover`` and ``:visited`` that depend on state not tracked
+    /// yet. They never match.
+    Unsupported(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
     pub name: String,
     pub values: Vec<Value>,
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -39,14 +178,76 @@ pub enum Value {
     Length(f64, Unit),
     Num(f64),
     Color(Color),
+    Calc(Calc),
+    String(String),
+    Url(String),
+    /// The ``,`` between values, as in ``font-family: a, b``.
+    Comma,
+    /// The ``/`` between values, as in ``font: 12px/1.5 a``.
+    Slash,
+    /// The value of a custom property, or of a declaration that uses ``var()``. It is parsed
+    /// once the variables are substituted, see ``substitute_vars``.
+    Unparsed(Vec<Token>),
+    /// A longhand of a shorthand that uses ``var()``, with the name and the value of the
+    /// shorthand. The shorthand is expanded once the variables are substituted.
+    PendingSubstitution(String, Vec<Token>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
     Pt,
+    Pc,
+    In,
+    Cm,
+    Mm,
     Percent,
     Em,
+    Rem,
+    Ex,
+    Ch,
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+}
+
+/// What lengths in units relative to the viewport and to fonts are resolved against, see
+/// ``Value::resolve_lengths``. All sizes are in px.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    pub viewport_width: f64,
+    pub viewport_height: f64,
+    pub root_font_size: f64,
+    /// The font size, for ``em``.
+    pub font_size: f64,
+    /// The x-height of the font, for ``ex``.
+    pub x_height: f64,
+    /// The advance of "0" in the font, for ``ch``.
+    pub zero_width: f64,
+}
+
+/// A ``calc()``, ``min()``, ``max()`` or ``clamp()`` expression, which can mix units.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Calc {
+    Length(f64, Unit),
+    Num(f64),
+    Sum(Box<Calc>, Box<Calc>),
+    Difference(Box<Calc>, Box<Calc>),
+    Product(Box<Calc>, Box<Calc>),
+    /// The divisor is always a number.
+    Quotient(Box<Calc>, Box<Calc>),
+    Min(Vec<Calc>),
+    Max(Vec<Calc>),
+    /// ``clamp(min, value, max)``
+    Clamp(Box<Calc>, Box<Calc>, Box<Calc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CalcType {
+    Number,
+    /// Lengths and percentages, which always resolve to lengths.
+    Length,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -66,9 +267,16 @@ pub enum TextDecoration {
     // Blink,
 }
 
-macro_rules! color { ($name:ident, $r:expr, $g:expr, $b:expr) => {
-    pub const $name: Color = Color { r: $r, g: $g, b: $b, a: 0xff };
-}}
+macro_rules! color {
+    ($name:ident, $r:expr, $g:expr, $b:expr) => {
+        pub const $name: Color = Color {
+            r: $r,
+            g: $g,
+            b: $b,
+            a: 0xff,
+        };
+    };
+}
 
 color!(BLACK, 0x00, 0x00, 0x00);
 color!(SILVER, 0xc0, 0xc0, 0xc0);
@@ -89,15 +297,173 @@ color!(AQUA, 0x00, 0xff, 0xff);
 
 impl Copy for Color {}
 
+pub const TRANSPARENT: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0,
+};
+
+// Sorted by name for binary search.
+// ref. https://www.w3.org/TR/css-color-4/#named-colors
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
 impl Value {
     pub fn to_px(&self) -> Option<f64> {
         match *self {
             Value::Length(f, Unit::Px) | Value::Num(f) => Some(f),
             Value::Length(f, Unit::Pt) => Some(pt2px(f)),
-            Value::Length(_, Unit::Em) => {
-                println!("The unit 'em' is currently unsupported. Treated as '16px'");
-                Some(16.0)
-            }
+            Value::Length(f, ref unit) => unit.to_px(f),
+            Value::Calc(ref calc) => calc.to_px(None),
             _ => None,
         }
     }
@@ -107,10 +473,8 @@ impl Value {
             Value::Length(f, Unit::Px) | Value::Num(f) => Some(f),
             Value::Length(f, Unit::Pt) => Some(pt2px(f)),
             Value::Length(f, Unit::Percent) => Some(len * (f / 100.0)),
-            Value::Length(_, Unit::Em) => {
-                println!("The unit 'em' is currently unsupported. Treated as '16px'");
-                Some(16.0)
-            }
+            Value::Length(f, ref unit) => unit.to_px(f),
+            Value::Calc(ref calc) => calc.to_px(Some(len)),
             _ => None,
         }
     }
@@ -119,10 +483,8 @@ impl Value {
         match *self {
             Value::Length(f, Unit::Pt) | Value::Num(f) => Some(f),
             Value::Length(f, Unit::Px) => Some(px2pt(f)),
-            Value::Length(_, Unit::Em) => {
-                println!("The unit 'em' is currently unsupported. Treated as '12pt'");
-                Some(12.0)
-            }
+            Value::Length(f, ref unit) => unit.to_px(f).map(px2pt),
+            Value::Calc(ref calc) => calc.to_px(None).map(px2pt),
             _ => None,
         }
     }
@@ -137,29 +499,79 @@ impl Value {
     pub fn to_color(&self) -> Option<Color> {
         match *self {
             Value::Color(color) => Some(color),
-            Value::Keyword(ref color_name) => match color_name.as_str() {
-                "black" => Some(BLACK),
-                "silver" => Some(SILVER),
-                "gray" => Some(GRAY),
-                "white" => Some(WHITE),
-                "red" => Some(RED),
-                "maroon" => Some(MAROON),
-                "purple" => Some(PURPLE),
-                "fuchsia" => Some(FUCHSIA),
-                "green" => Some(GREEN),
-                "lime" => Some(LIME),
-                "olive" => Some(OLIVE),
-                "yellow" => Some(YELLOW),
-                "navy" => Some(NAVY),
-                "blue" => Some(BLUE),
-                "teal" => Some(TEAL),
-                "aqua" => Some(AQUA),
-                _ => None,
-            },
+            Value::Keyword(ref name) if name == "transparent" => Some(TRANSPARENT),
+            Value::Keyword(ref name) => NAMED_COLORS
+                .binary_search_by_key(&name.as_str(), |&(name, _)| name)
+                .ok()
+                .map(|i| {
+                    let rgb = NAMED_COLORS[i].1;
+                    Color {
+                        r: (rgb >> 16) as u8,
+                        g: (rgb >> 8) as u8,
+                        b: rgb as u8,
+                        a: 255,
+                    }
+                }),
             _ => None,
         }
     }
 
+    /// Like ``to_color``, with ``currentcolor`` being ``current_color``.
+    pub fn to_color_or_current(&self, current_color: Color) -> Option<Color> {
+        match *self {
+            Value::Keyword(ref name) if name == "currentcolor" => Some(current_color),
+            _ => self.to_color(),
+        }
+    }
+
+    /// Converts lengths into px, except percentages, which depend on the property.
+    pub fn resolve_lengths(&self, context: &LengthContext) -> Value {
+        match *self {
+            Value::Length(len, ref unit) => match unit.resolve_length(len, context) {
+                Some(px) => Value::Length(px, Unit::Px),
+                None => self.clone(),
+            },
+            Value::Calc(ref calc) => calc
+                .map_lengths(&mut |len, unit| match unit.resolve_length(len, context) {
+                    Some(px) => Calc::Length(px, Unit::Px),
+                    None => Calc::Length(len, unit.clone()),
+                })
+                .into_value(),
+            _ => self.clone(),
+        }
+    }
+
+    /// Converts percentages into px of ``base``, for the properties whose percentages compute to
+    /// lengths, like ``font-size``.
+    pub fn resolve_percentages(&self, base: f64) -> Value {
+        match *self {
+            Value::Length(len, Unit::Percent) => Value::Length(base * len / 100.0, Unit::Px),
+            Value::Calc(ref calc) => calc
+                .map_lengths(&mut |len, unit| match *unit {
+                    Unit::Percent => Calc::Length(base * len / 100.0, Unit::Px),
+                    _ => Calc::Length(len, unit.clone()),
+                })
+                .into_value(),
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns true if the value has a length in a unit for which ``f`` is true.
+    pub fn has_unit<F: Fn(&Unit) -> bool>(&self, f: F) -> bool {
+        match *self {
+            Value::Length(_, ref unit) => f(unit),
+            Value::Calc(ref calc) => {
+                let mut found = false;
+                calc.map_lengths(&mut |len, unit| {
+                    found |= f(unit);
+                    Calc::Length(len, unit.clone())
+                });
+                found
+            }
+            _ => false,
+        }
+    }
+
     pub fn to_text_decoration(&self) -> Option<TextDecoration> {
         match *self {
             Value::Keyword(ref name) => match name.to_lowercase().as_str() {
@@ -174,6 +586,193 @@ impl Value {
     }
 }
 
+impl Unit {
+    /// Converts a length into px. Units relative to fonts are resolved against the initial font,
+    /// which is what they mean where there is no element, e.g. in media queries. Percentages and
+    /// viewport units give None.
+    pub fn to_px(&self, len: f64) -> Option<f64> {
+        match *self {
+            Unit::Px => Some(len),
+            Unit::Pt => Some(pt2px(len)),
+            Unit::Pc => Some(pt2px(len * 12.0)),
+            Unit::In => Some(len * DPI),
+            Unit::Cm => Some(len * DPI / 2.54),
+            Unit::Mm => Some(len * DPI / 25.4),
+            Unit::Em | Unit::Rem => Some(len * DEFAULT_FONT_SIZE),
+            Unit::Ex | Unit::Ch => Some(len * DEFAULT_FONT_SIZE / 2.0),
+            Unit::Percent | Unit::Vw | Unit::Vh | Unit::Vmin | Unit::Vmax => None,
+        }
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        matches!(
+            *self,
+            Unit::Px | Unit::Pt | Unit::Pc | Unit::In | Unit::Cm | Unit::Mm
+        )
+    }
+
+    pub fn is_viewport_relative(&self) -> bool {
+        matches!(*self, Unit::Vw | Unit::Vh | Unit::Vmin | Unit::Vmax)
+    }
+
+    // Returns None for percentages.
+    fn resolve_length(&self, len: f64, context: &LengthContext) -> Option<f64> {
+        let (width, height) = (context.viewport_width, context.viewport_height);
+        Some(match *self {
+            Unit::Px | Unit::Pt | Unit::Pc | Unit::In | Unit::Cm | Unit::Mm => self.to_px(len)?,
+            Unit::Em => len * context.font_size,
+            Unit::Rem => len * context.root_font_size,
+            Unit::Ex => len * context.x_height,
+            Unit::Ch => len * context.zero_width,
+            Unit::Vw => len * width / 100.0,
+            Unit::Vh => len * height / 100.0,
+            Unit::Vmin => len * width.min(height) / 100.0,
+            Unit::Vmax => len * width.max(height) / 100.0,
+            _ => return None,
+        })
+    }
+}
+
+impl Calc {
+    /// Resolves the expression in px, or as a number. Percentages are relative to
+    /// ``percent_base``, and without one the result is None if there are any.
+    pub fn to_px(&self, percent_base: Option<f64>) -> Option<f64> {
+        let px = self.resolve(percent_base)?;
+        // Dividing by zero gives an infinite value, which is clamped, or NaN, which becomes 0.
+        Some(if px.is_nan() {
+            0.0
+        } else {
+            px.clamp(f64::MIN, f64::MAX)
+        })
+    }
+
+    pub fn is_number(&self) -> bool {
+        self.calc_type() == Some(CalcType::Number)
+    }
+
+    fn resolve(&self, percent_base: Option<f64>) -> Option<f64> {
+        let resolve = |calc: &Calc| calc.resolve(percent_base);
+        let resolve_all = |args: &[Calc]| args.iter().map(resolve).collect::<Option<Vec<_>>>();
+        Some(match *self {
+            Calc::Length(len, Unit::Px) | Calc::Num(len) => len,
+            Calc::Length(len, Unit::Pt) => pt2px(len),
+            Calc::Length(len, Unit::Percent) => percent_base? * len / 100.0,
+            Calc::Length(len, ref unit) => unit.to_px(len)?,
+            Calc::Sum(ref a, ref b) => resolve(a)? + resolve(b)?,
+            Calc::Difference(ref a, ref b) => resolve(a)? - resolve(b)?,
+            Calc::Product(ref a, ref b) => resolve(a)? * resolve(b)?,
+            Calc::Quotient(ref a, ref b) => resolve(a)? / resolve(b)?,
+            Calc::Min(ref args) => resolve_all(args)?.into_iter().fold(f64::INFINITY, f64::min),
+            Calc::Max(ref args) => resolve_all(args)?
+                .into_iter()
+                .fold(f64::NEG_INFINITY, f64::max),
+            Calc::Clamp(ref min, ref value, ref max) => {
+                resolve(value)?.min(resolve(max)?).max(resolve(min)?)
+            }
+        })
+    }
+
+    // Returns None if the types of the operands don't fit, e.g. in ``1px + 2``.
+    fn calc_type(&self) -> Option<CalcType> {
+        let same_type = |args: &[&Calc]| {
+            let first = args[0].calc_type()?;
+            args[1..]
+                .iter()
+                .all(|arg| arg.calc_type() == Some(first))
+                .then_some(first)
+        };
+        match *self {
+            Calc::Length(..) => Some(CalcType::Length),
+            Calc::Num(_) => Some(CalcType::Number),
+            Calc::Sum(ref a, ref b) | Calc::Difference(ref a, ref b) => same_type(&[a, b]),
+            Calc::Product(ref a, ref b) => match (a.calc_type()?, b.calc_type()?) {
+                (CalcType::Number, ty) | (ty, CalcType::Number) => Some(ty),
+                (CalcType::Length, CalcType::Length) => None,
+            },
+            Calc::Quotient(ref a, ref b) => match b.calc_type()? {
+                CalcType::Number => a.calc_type(),
+                CalcType::Length => None,
+            },
+            Calc::Min(ref args) | Calc::Max(ref args) => {
+                same_type(&args.iter().collect::<Vec<_>>())
+            }
+            Calc::Clamp(ref min, ref value, ref max) => same_type(&[min, value, max]),
+        }
+    }
+
+    fn has_relative_units(&self) -> bool {
+        match *self {
+            Calc::Length(_, ref unit) => !unit.is_absolute(),
+            Calc::Num(_) => false,
+            Calc::Sum(ref a, ref b)
+            | Calc::Difference(ref a, ref b)
+            | Calc::Product(ref a, ref b)
+            | Calc::Quotient(ref a, ref b) => a.has_relative_units() || b.has_relative_units(),
+            Calc::Min(ref args) | Calc::Max(ref args) => {
+                args.iter().any(|arg| arg.has_relative_units())
+            }
+            Calc::Clamp(ref min, ref value, ref max) => {
+                [min, value, max].iter().any(|arg| arg.has_relative_units())
+            }
+        }
+    }
+
+    // Replaces every length in the expression with what ``f`` returns for it.
+    fn map_lengths<F: FnMut(f64, &Unit) -> Calc>(&self, f: &mut F) -> Calc {
+        let mut map = |calc: &Calc| Box::new(calc.map_lengths(f));
+        match *self {
+            Calc::Length(len, ref unit) => f(len, unit),
+            Calc::Num(num) => Calc::Num(num),
+            Calc::Sum(ref a, ref b) => Calc::Sum(map(a), map(b)),
+            Calc::Difference(ref a, ref b) => Calc::Difference(map(a), map(b)),
+            Calc::Product(ref a, ref b) => Calc::Product(map(a), map(b)),
+            Calc::Quotient(ref a, ref b) => Calc::Quotient(map(a), map(b)),
+            Calc::Min(ref args) => Calc::Min(args.iter().map(|arg| *map(arg)).collect()),
+            Calc::Max(ref args) => Calc::Max(args.iter().map(|arg| *map(arg)).collect()),
+            Calc::Clamp(ref min, ref value, ref max) => Calc::Clamp(map(min), map(value), map(max)),
+        }
+    }
+
+    // Computes the expression now if it only uses absolute units.
+    fn into_value(self) -> Value {
+        if self.has_relative_units() {
+            return Value::Calc(self);
+        }
+        let px = self.to_px(None).unwrap();
+        match self.calc_type() {
+            Some(CalcType::Number) => Value::Num(px),
+            _ => Value::Length(px, Unit::Px),
+        }
+    }
+}
+
+// ref. https://www.w3.org/TR/css-color-4/#hsl-to-rgb
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (f64, f64, f64) {
+    let f = |n: f64| {
+        let k = (n + hue / 30.0).rem_euclid(12.0);
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    (f(0.0), f(8.0), f(4.0))
+}
+
+// ref. https://www.w3.org/TR/css-color-4/#hwb-to-rgb
+fn hwb_to_rgb(hue: f64, whiteness: f64, blackness: f64) -> (f64, f64, f64) {
+    if whiteness + blackness >= 1.0 {
+        let gray = whiteness / (whiteness + blackness);
+        return (gray, gray, gray);
+    }
+    let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
+    let f = |c: f64| c * (1.0 - whiteness - blackness) + whiteness;
+    (f(r), f(g), f(b))
+}
+
+fn is_math_function(name: &str) -> bool {
+    ["calc", "min", "max", "clamp"]
+        .iter()
+        .any(|f| name.eq_ignore_ascii_case(f))
+}
+
 const DPI: f64 = 96.0;
 
 // TODO: any other better way?
@@ -188,597 +787,1842 @@ pub fn pt2px(f: f64) -> f64 {
 
 pub type Specificity = (usize, usize, usize);
 
+impl MediaQueryList {
+    /// Evaluates the list for a screen with a viewport of the given size in px.
+    pub fn matches(&self, width: f64, height: f64) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query.matches(width, height))
+    }
+}
+
+impl MediaQuery {
+    pub fn matches(&self, width: f64, height: f64) -> bool {
+        // We only ever render to a screen.
+        let media_type = self.media_type == "all" || self.media_type == "screen";
+        let features = self.features.iter().all(|feature| match *feature {
+            MediaFeature::MinWidth(min) => width >= min,
+            MediaFeature::MaxWidth(max) => width <= max,
+            MediaFeature::MinHeight(min) => height >= min,
+            MediaFeature::MaxHeight(max) => height <= max,
+            MediaFeature::Orientation(ref orientation) => {
+                (orientation == "portrait") == (height >= width)
+            }
+            MediaFeature::PrefersColorScheme(ref scheme) => scheme == "light",
+        });
+        (media_type && features) != self.negated
+    }
+}
+
 impl Selector {
     // ref: http://www.w3.org/TR/selectors/#specificity
     pub fn specificity(&self) -> Specificity {
-        fn specificity_simple(simple: &SimpleSelector) -> Specificity {
-            let a = simple.id.iter().count();
-            let b = simple.class.len();
-            let c = simple.tag_name.iter().count();
-            (a, b, c)
-        }
-
         match *self {
-            Selector::Simple(ref simple) => specificity_simple(simple),
-            Selector::Descendant(ref a, ref b) => {
-                let (a1, b1, c1) = specificity_simple(a);
-                let (a2, b2, c2) = (*b).specificity();
-                (a1 + a2, b1 + b2, c1 + c2)
-            }
-            Selector::Child(ref a, ref b) => {
-                let (a1, b1, c1) = specificity_simple(a);
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Descendant(ref a, ref b)
+            | Selector::Child(ref a, ref b)
+            | Selector::NextSibling(ref a, ref b)
+            | Selector::SubsequentSibling(ref a, ref b) => {
+                let (a1, b1, c1) = a.specificity();
                 let (a2, b2, c2) = (*b).specificity();
                 (a1 + a2, b1 + b2, c1 + c2)
             }
         }
     }
-}
 
-pub fn parse(source: String) -> Stylesheet {
-    Stylesheet {
-        rules: Parser::new(source).parse_rules(),
+    /// Returns the compound selectors from left to right, each with the combinator that joins it
+    /// to the next one. The last compound, the subject of the selector, has none.
+    pub fn compounds(&self) -> Vec<(&SimpleSelector, Option<Combinator>)> {
+        let mut compounds = vec![];
+        let mut selector = self;
+        loop {
+            let (compound, combinator, rest) = match *selector {
+                Selector::Simple(ref simple) => {
+                    compounds.push((simple, None));
+                    return compounds;
+                }
+                Selector::Descendant(ref a, ref b) => (a, Combinator::Descendant, b),
+                Selector::Child(ref a, ref b) => (a, Combinator::Child, b),
+                Selector::NextSibling(ref a, ref b) => (a, Combinator::NextSibling, b),
+                Selector::SubsequentSibling(ref a, ref b) => (a, Combinator::SubsequentSibling, b),
+            };
+            compounds.push((compound, Some(combinator)));
+            selector = rest;
+        }
     }
 }
 
-pub fn parse_attr_style(source: String) -> Vec<Declaration> {
-    let mut decls = Vec::new();
-    let mut parser = Parser::new(source);
-    loop {
-        parser.consume_whitespace().unwrap();
-        if parser.eof() {
-            break;
-        }
-        match parser.parse_declaration() {
-            Ok(ok) => decls.push(ok),
-            Err(_) => {}
+impl SimpleSelector {
+    pub fn specificity(&self) -> Specificity {
+        let (mut a, mut b, mut c) = (
+            self.id.iter().count(),
+            self.class.len() + self.attributes.len(),
+            self.tag_name.iter().count() + self.pseudo_element.iter().count(),
+        );
+        for pseudo_class in &self.pseudo_classes {
+            match *pseudo_class {
+                // :not() takes the specificity of its most specific argument.
+                PseudoClass::Not(ref selectors) => {
+                    let (a1, b1, c1) = selectors
+                        .iter()
+                        .map(|s| s.specificity())
+                        .max()
+                        .unwrap_or((0, 0, 0));
+                    a += a1;
+                    b += b1;
+                    c += c1;
+                }
+                _ => b += 1,
+            }
         }
+        (a, b, c)
     }
-    decls
 }
 
-pub fn parse_value(source: String) -> Value {
-    match Parser::new(source).parse_value() {
-        Ok(ok) => ok,
-        Err(_) => Value::Num(0.0),
-    }
+pub fn parse(source: String) -> Stylesheet {
+    parse_with_errors(source).0
+}
+
+/// Like ``parse``, but also returns the parse errors. Rules and declarations with errors are
+/// skipped, the same way a browser does.
+pub fn parse_with_errors(source: String) -> (Stylesheet, Vec<ParseError>) {
+    let mut parser = Parser::new(source.as_str());
+    let rules = parser.parse_rules(true);
+    let mut errors = parser.errors;
+    errors.sort_by_key(|e| (e.line, e.column));
+    let stylesheet = Stylesheet {
+        imports: parser.imports,
+        font_faces: parser.font_faces,
+        rules,
+    };
+    (stylesheet, errors)
 }
 
-fn valid_ident_char(c: char) -> bool {
-    // TODO: other char codes?
-    c.is_alphanumeric() || c == '-' || c == '_'
+/// Parses a ``media`` attribute. Invalid queries never match, the rest of the list still does.
+pub fn parse_media_query_list(source: &str) -> MediaQueryList {
+    Parser::new(source).parse_media_query_list()
 }
 
-fn valid_ident_percent_char(c: char) -> bool {
-    // TODO: other char codes?
-    c.is_alphanumeric() || c == '%'
+pub fn parse_attr_style(source: String) -> Vec<Declaration> {
+    Parser::new(source.as_str()).parse_declarations()
+}
+
+/// Parses the values of a declaration whose ``var()`` references have been substituted.
+/// Returns None if they are invalid.
+pub fn parse_substituted_values(tokens: Vec<Token>) -> Option<Vec<Value>> {
+    match Parser::from_tokens(tokens).parse_values() {
+        Ok(values) => Some(values).filter(|values| !values.is_empty()),
+        Err(_) => None,
+    }
 }
 
-fn valid_alpha_percent_char(c: char) -> bool {
-    // TODO: other char codes?
-    c.is_alphanumeric() || c == '%'
+/// Replaces every ``var(--name, fallback)`` in ``tokens`` with the value that ``lookup`` returns
+/// for ``--name``, or with the fallback if there is none. Returns None if neither exists, which
+/// makes the declaration invalid at computed-value time.
+pub fn substitute_vars<F>(tokens: &[Token], lookup: &mut F) -> Option<Vec<Token>>
+where
+    F: FnMut(&str) -> Option<Vec<Token>>,
+{
+    let mut parser = Parser::from_tokens(tokens.to_vec());
+    let mut substituted = vec![];
+    while let Some(token) = parser.next_token() {
+        match token {
+            Token::Function(ref name) if name.eq_ignore_ascii_case("var") => {
+                let mut arguments = parser.parse_function_arguments();
+                substituted.extend(arguments.substitute_var(lookup)?);
+            }
+            token => substituted.push(token),
+        }
+    }
+    Some(substituted)
 }
 
-fn valid_hex_char(c: char) -> bool {
-    // TODO: other char codes?
-    match c.to_ascii_lowercase() {
-        'a' | 'b' | 'c' | 'd' | 'e' | 'f' => true,
-        c if c.is_numeric() => true,
-        _ => false,
+pub fn parse_value(source: String) -> Value {
+    let mut parser = Parser::new(source.as_str());
+    parser.skip_whitespace();
+    match parser.parse_value() {
+        Ok(ok) => ok,
+        Err(_) => Value::Num(0.0),
     }
 }
 
+// Parses the tokens of a whole stylesheet, or of a part of it such as the contents of a block.
 #[derive(Clone, Debug)]
 struct Parser {
+    tokens: Vec<Token>,
+    // The location of each token, plus one for the end of input.
+    locations: Vec<(usize, usize)>,
     pos: usize,
-    input: String,
+    errors: Vec<ParseError>,
+    imports: Vec<Import>,
+    font_faces: Vec<FontFace>,
 }
 
 impl Parser {
-    fn new(input: String) -> Parser {
+    fn new(source: &str) -> Parser {
+        let mut tokenizer = Tokenizer::new(source);
+        let mut tokens = vec![];
+        let mut locations = vec![];
+        while let Some(token) = tokenizer.next() {
+            tokens.push(token);
+            locations.push(tokenizer.last_token_location());
+        }
+        locations.push(tokenizer.end_location());
         Parser {
+            tokens,
+            locations,
             pos: 0,
-            input: remove_comments(input.as_bytes(), "/*", "*/"),
+            errors: tokenizer.take_errors(),
+            imports: vec![],
+            font_faces: vec![],
         }
     }
 
-    fn parse_rules(&mut self) -> Vec<Rule> {
-        let mut rules = vec![];
-        loop {
-            self.consume_whitespace().unwrap();
-
-            if self.eof() {
-                break;
-            }
-
-            if self.next_char().unwrap() == '@' {
-                // TODO: Ignore all at-mark rules. Implement correctly ASAP!
-                assert_eq!(self.consume_char().unwrap(), '@');
-                let ident = self.parse_identifier().unwrap();
-                if ident == "charset" || ident == "import" {
-                    self.consume_while(|c| c != ';').unwrap();
-                    assert_eq!(self.consume_char().unwrap(), ';');
-                } else if ident == "font-face" || ident == "-ms-viewport" {
-                    self.consume_while(|c| c != '{').unwrap();
-                    assert_eq!(self.consume_char().unwrap(), '{');
-                    loop {
-                        self.consume_whitespace().unwrap();
-                        if self.next_char().unwrap() == '}' {
-                            self.consume_char().unwrap();
-                            break;
-                        }
-                        self.parse_rule().unwrap();
-                    }
-                } else {
-                    // @support, @media...
-                    self.consume_while(|c| c != '{').unwrap();
-                    assert_eq!(self.consume_char().unwrap(), '{');
-                    loop {
-                        self.consume_whitespace().unwrap();
-                        if self.next_char().unwrap() == '}' {
-                            self.consume_char().unwrap();
-                            break;
-                        }
-                        self.parse_rule().unwrap();
-                    }
-                }
-            } else {
-                if let Ok(ok) = self.parse_rule() {
-                    rules.push(ok)
-                }
-            }
+    // A parser for tokens that don't come from a source, so errors are located at 1:1.
+    fn from_tokens(tokens: Vec<Token>) -> Parser {
+        Parser {
+            locations: vec![(1, 1); tokens.len() + 1],
+            tokens,
+            pos: 0,
+            errors: vec![],
+            imports: vec![],
+            font_faces: vec![],
         }
-        rules
     }
 
-    fn parse_rule(&mut self) -> Result<Rule, ()> {
-        Ok(Rule {
-            selectors: self.parse_selectors()?,
-            declarations: self.parse_declarations()?,
-        })
+    // A parser for the tokens in ``start..end``.
+    fn sub_parser(&self, start: usize, end: usize) -> Parser {
+        Parser {
+            tokens: self.tokens[start..end].to_vec(),
+            locations: self.locations[start..end + 1].to_vec(),
+            pos: 0,
+            errors: vec![],
+            imports: vec![],
+            font_faces: vec![],
+        }
     }
 
-    fn parse_selectors(&mut self) -> Result<Vec<Selector>, ()> {
-        let mut selectors = Vec::new();
-        loop {
-            if let Ok(ok) = self.parse_selector() {
-                selectors.push(ok);
-            }
-            self.consume_whitespace()?;
-            match self.next_char()? {
-                ',' => {
-                    self.consume_char()?;
-                    self.consume_whitespace()?;
-                }
-                '{' => break,
-                c => {
-                    println!("Unexpected character {} in selector list", c);
-                    // debug: panic!();
-                    self.consume_char()?;
-                }
-            }
-        }
-        // Return selectors with highest specificity first, for use in matching.
-        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
-        Ok(selectors)
+    // Error at the next token.
+    fn error<S: Into<String>>(&self, message: S) -> ParseError {
+        self.error_at(self.pos, message)
     }
 
-    fn parse_selector(&mut self) -> Result<Selector, ()> {
-        let s1 = self.parse_simple_selector()?;
-        self.consume_whitespace()?;
-        match self.next_char()? {
-            // Descendant
-            c if c.is_alphanumeric() || c == '#' || c == '.' || c == ':' || c == '[' => {
-                let s2 = self.parse_selector()?;
-                return Ok(Selector::Descendant(s1, Box::new(s2)));
-            }
-            '>' => {
-                assert_eq!(self.consume_char()?, '>');
-                self.consume_whitespace()?;
-                let s2 = self.parse_selector()?;
-                return Ok(Selector::Child(s1, Box::new(s2)));
-            }
-            '+' => {
-                assert_eq!(self.consume_char()?, '+');
-                self.consume_whitespace()?;
-                let s2 = self.parse_selector()?;
-                // TODO: Adjacent sibling selector is treated as Descendant selector for now.
-                return Ok(Selector::Descendant(s1, Box::new(s2)));
-            }
-            _ => {}
+    // Error at the token consumed last.
+    fn error_at_last_token<S: Into<String>>(&self, message: S) -> ParseError {
+        self.error_at(self.pos.saturating_sub(1), message)
+    }
+
+    fn error_at<S: Into<String>>(&self, index: usize, message: S) -> ParseError {
+        let (line, column) = self.locations[index.min(self.tokens.len())];
+        ParseError::new(line, column, message)
+    }
+
+    fn unexpected_token(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(token) => self.error(format!("expected {} but found '{}'", expected, token)),
+            None => self.error(format!("expected {} but found end of input", expected)),
         }
-        Ok(Selector::Simple(s1))
     }
 
-    fn parse_simple_selector(&mut self) -> Result<SimpleSelector, ()> {
-        let mut unsupported_feature = false;
-        let mut selector = SimpleSelector {
-            tag_name: None,
-            id: None,
-            class: HashSet::new(),
-        };
-        while !self.eof() {
-            match self.next_char()? {
-                '#' => {
-                    self.consume_char()?;
-                    selector.id = Some(self.parse_identifier()?);
-                }
-                '.' => {
-                    self.consume_char()?;
-                    selector.class.insert(self.parse_identifier()?);
+    // Methods for parsing rules:
+
+    fn parse_rules(&mut self, top_level: bool) -> Vec<Rule> {
+        let mut rules = vec![];
+        // @import is only valid before any other rule but @charset.
+        let mut imports_allowed = top_level;
+        loop {
+            match self.peek().cloned() {
+                None => break,
+                Some(Token::Whitespace) => self.pos += 1,
+                Some(Token::Cdo) | Some(Token::Cdc) if top_level => self.pos += 1,
+                Some(Token::AtKeyword(ref name)) if name.eq_ignore_ascii_case("import") => {
+                    self.parse_import_rule(imports_allowed)
                 }
-                '*' => {
-                    // universal selector
-                    self.consume_char()?;
+                Some(Token::AtKeyword(ref name)) if name.eq_ignore_ascii_case("media") => {
+                    imports_allowed = false;
+                    rules.extend(self.parse_media_rule());
                 }
-                ':' => {
-                    self.parse_pseudo_class_or_element()?;
+                Some(Token::AtKeyword(ref name)) if name.eq_ignore_ascii_case("font-face") => {
+                    imports_allowed = false;
+                    self.parse_font_face_rule();
                 }
-                '[' => {
-                    unsupported_feature = self.parse_attribute().is_err();
+                Some(Token::AtKeyword(name)) => {
+                    imports_allowed &= name.eq_ignore_ascii_case("charset");
+                    self.parse_at_rule();
                 }
-                c if valid_ident_char(c) => {
-                    selector.tag_name = Some(self.parse_identifier()?);
+                Some(_) => {
+                    imports_allowed = false;
+                    rules.extend(self.parse_qualified_rule());
                 }
-                _ => break,
             }
         }
-        if unsupported_feature {
-            Err(())
-        } else {
-            Ok(selector)
-        }
+        rules
     }
 
-    // TODO: Implement correctly
-    fn parse_pseudo_class_or_element(&mut self) -> Result<(), ()> {
-        assert_eq!(self.skip_char_if_any(':')?, true); // pseudo-class
-        self.skip_char_if_any(':')?; //pseudo-element
-        self.consume_whitespace()?;
-        self.parse_identifier()?;
-        self.consume_whitespace()?;
-        if self.skip_char_if_any('(')? {
-            self.consume_while(|c| c != ')')?;
-            assert_eq!(self.consume_char()?, ')');
+    // Returns the rules in the block, each with the media query list added to its ``media``.
+    fn parse_media_rule(&mut self) -> Vec<Rule> {
+        let start = self.pos;
+        let (mut prelude, block) = self.consume_at_rule();
+        let media = prelude.parse_media_query_list();
+        self.errors.append(&mut prelude.errors);
+        let mut block = match block {
+            Some(block) => block,
+            None => {
+                let e = self.error_at(start, "expected a block after @media");
+                self.errors.push(e);
+                return vec![];
+            }
+        };
+        let mut rules = block.parse_rules(false);
+        self.errors.append(&mut block.errors);
+        for font_face in &mut block.font_faces {
+            font_face.media.insert(0, media.clone());
         }
-        Ok(())
-    }
-
-    // TODO: Implement correctly
-    fn parse_attribute(&mut self) -> Result<(), ()> {
-        if self.skip_char_if_any('[')? {
-            self.consume_while(|c| c != ']')?;
-            assert_eq!(self.consume_char()?, ']');
+        self.font_faces.append(&mut block.font_faces);
+        for rule in &mut rules {
+            rule.media.insert(0, media.clone());
         }
-        // TODO: Just returns Err(()) to ignore this selector for now
-        Err(())
+        rules
     }
 
-    fn parse_declarations(&mut self) -> Result<Vec<Declaration>, ()> {
-        assert_eq!(self.consume_char()?, '{');
-        let mut declarations = Vec::new();
-        loop {
-            self.consume_whitespace()?;
-            if self.next_char()? == '}' {
-                self.consume_char()?;
-                break;
-            }
-            declarations.push(self.parse_declaration()?);
-        }
-        Ok(declarations)
+    // TODO: At-rules other than @import, @media and @font-face are ignored for now.
+    fn parse_at_rule(&mut self) {
+        self.consume_at_rule();
     }
 
-    fn parse_declaration(&mut self) -> Result<Declaration, ()> {
-        let property_name = self.parse_identifier()?;
-        self.consume_whitespace()?;
-        assert_eq!(self.consume_char()?, ':');
-        self.consume_whitespace()?;
-        let values = self.parse_values()?;
-        self.consume_whitespace()?;
-
-        Ok(Declaration {
-            name: property_name,
-            values: values,
-        })
+    fn parse_import_rule(&mut self, imports_allowed: bool) {
+        let start = self.pos;
+        let (mut prelude, block) = self.consume_at_rule();
+        let import = if block.is_some() {
+            Err(self.error_at(start, "unexpected block after @import"))
+        } else if !imports_allowed {
+            Err(self.error_at(start, "@import must come before all other rules"))
+        } else {
+            prelude.parse_import()
+        };
+        self.errors.append(&mut prelude.errors);
+        match import {
+            Ok(import) => self.imports.push(import),
+            Err(e) => self.errors.push(e),
+        }
     }
 
-    // Methods for parsing values:
-
-    fn parse_values(&mut self) -> Result<Vec<Value>, ()> {
-        let mut values = vec![];
-
-        if let Ok(()) = (|| -> Result<(), ()> {
-            loop {
-                self.consume_whitespace()?;
-                if self.eof() {
-                    break;
+    // Consumes an at-rule and returns parsers for its prelude and its block, if any.
+    fn consume_at_rule(&mut self) -> (Parser, Option<Parser>) {
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            let end = self.pos;
+            match self.peek() {
+                Some(&Token::Semicolon) => {
+                    self.pos += 1;
+                    return (self.sub_parser(start, end), None);
                 }
-                if let Ok(true) = self.skip_char_if_any(';') {
-                    break;
+                Some(&Token::OpenCurly) => {
+                    let block = self.parse_block();
+                    return (self.sub_parser(start, end), Some(block));
                 }
-
-                if let Ok(ok) = self.parse_value() {
-                    values.push(ok)
+                Some(_) => {
+                    self.skip_component_value();
                 }
-
-                self.consume_while(|c| c == ' ' || c == '\t')?;
-                if self.skip_char_if_any('\n')? || self.next_char()? == '}' {
-                    break;
+                None => {
+                    let e = self.error("unexpected end of input in at-rule");
+                    self.errors.push(e);
+                    return (self.sub_parser(start, end), None);
                 }
-
-                self.skip_char_if_any(',')?;
             }
-            Ok(())
-        })()
-        {}
-
-        Ok(values)
+        }
     }
 
-    fn parse_value(&mut self) -> Result<Value, ()> {
-        match self.next_char()? {
-            '-' if self.next2_char()?.is_numeric() => self.parse_length(),
-            '.' | '0'...'9' => self.parse_length(),
-            '#' => self.parse_color(),
-            '\"' | '\'' => self.parse_string(),
-            _ => {
-                self.skip_char_if_any('!')?; // TODO: Is this correct?
-                self.skip_char_if_any('\\')?; // TODO: Is this correct?
-
-                let ident = self.parse_identifier()?;
-                match ident.as_str() {
-                    "rgb" => self.parse_rgb_color(),
-                    "rgba" => self.parse_rgba_color(),
-                    "url" => self.parse_url(),
-                    _ if !self.eof() && self.next_char()? == '(' => {
-                        // TODO: Unsupported functions are ignored.
-                        let mut nest = 0;
-                        self.consume_while(|c| {
-                            match c {
-                                '(' => nest += 1,
-                                ')' => nest -= 1,
-                                _ => {}
-                            }
-                            nest > 0
-                        })?;
-                        assert_eq!(self.consume_char()?, ')');
-                        Ok(Value::Keyword(ident))
-                    }
-                    _ => Ok(Value::Keyword(ident)),
-                }
+    fn parse_import(&mut self) -> Result<Import, ParseError> {
+        self.skip_whitespace();
+        let url = match self.peek() {
+            Some(Token::String(url)) => {
+                let url = url.clone();
+                self.pos += 1;
+                url
             }
-        }
+            _ => self.parse_url()?,
+        };
+        let media = self.parse_media_query_list();
+        Ok(Import { url, media })
     }
 
-    fn parse_length(&mut self) -> Result<Value, ()> {
-        let num = self.parse_float()?;
-        if !self.eof() && valid_alpha_percent_char(self.next_char()?) {
-            Ok(Value::Length(num, self.parse_unit()?))
-        } else {
-            Ok(Value::Num(num))
+    // Parses ``url(a.css)`` or ``url("a.css")``.
+    fn parse_url(&mut self) -> Result<String, ParseError> {
+        match self.next_token() {
+            Some(Token::Url(url)) => Ok(url),
+            Some(Token::Function(ref name)) if name.eq_ignore_ascii_case("url") => {
+                let mut args = self.parse_function_arguments();
+                args.skip_whitespace();
+                match (args.next_token(), args.skip_whitespace(), args.peek()) {
+                    (Some(Token::String(url)), _, None) => Ok(url),
+                    _ => Err(self.error_at_last_token("invalid url()")),
+                }
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected_token("a URL"))
+            }
         }
     }
 
-    fn parse_float(&mut self) -> Result<f64, ()> {
-        let n = self.consume_while(|c| match c {
-            '-' | '0'...'9' | '.' => true,
-            _ => false,
-        })?
-            .parse()
-            .or_else(|_| Err(()));
-
-        // TODO: Ignore expression
-        self.consume_while(|c| {
-            c.is_numeric() || c == '.' || c == '-' || c == '+' || c == '*' || c == '/'
-        })?;
-
-        n
-    }
-
-    fn parse_string(&mut self) -> Result<Value, ()> {
-        let quote = self.consume_char()?;
-        self.consume_while(|c| c != quote)?;
-        assert_eq!(self.consume_char()?, quote);
-        // TODO: Implement correctly
-        Ok(Value::Num(0.0))
-    }
-
-    fn parse_unit(&mut self) -> Result<Unit, ()> {
-        match &*self.parse_identifier_percent()? {
-            "px" => Ok(Unit::Px),
-            "pt" => Ok(Unit::Pt),
-            "%" => Ok(Unit::Percent),
-            "em" => Ok(Unit::Em),
-            u => {
-                println!("unrecognized unit: {}", u);
-                // Unrecognized units are treated as Px
-                Ok(Unit::Pt)
+    fn parse_font_face_rule(&mut self) {
+        let start = self.pos;
+        let (_, block) = self.consume_at_rule();
+        let mut block = match block {
+            Some(block) => block,
+            None => {
+                let e = self.error_at(start, "expected a block after @font-face");
+                self.errors.push(e);
+                return;
             }
+        };
+        let font_face = block.parse_font_face();
+        self.errors.append(&mut block.errors);
+        match font_face {
+            Ok(font_face) => self.font_faces.push(font_face),
+            Err(e) => self.errors.push(self.error_at(start, e)),
         }
     }
 
-    fn parse_rgb_color(&mut self) -> Result<Value, ()> {
-        assert_eq!(self.consume_char_ignore_whitescape()?, '(');
-        let r = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
-        let g = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
-        let b = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ')');
-        Ok(Value::Color(Color {
-            r: r as u8,
-            g: g as u8,
-            b: b as u8,
-            a: 255,
-        }))
+    // Parses the descriptors in the block of an ``@font-face`` rule. Invalid descriptors are
+    // skipped like invalid declarations, but the rule needs a family and a source.
+    fn parse_font_face(&mut self) -> Result<FontFace, &'static str> {
+        let mut family = None;
+        let mut sources = vec![];
+        let mut weight = Value::Keyword("normal".to_string());
+        let mut style = Value::Keyword("normal".to_string());
+        loop {
+            match self.peek() {
+                None => break,
+                Some(&Token::Whitespace) | Some(&Token::Semicolon) => self.pos += 1,
+                Some(_) => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|t| *t != Token::Semicolon) {
+                        self.skip_component_value();
+                    }
+                    let mut descriptor = self.sub_parser(start, self.pos);
+                    let result = descriptor.parse_property_name().and_then(|name| {
+                        descriptor.skip_whitespace();
+                        if name == "src" {
+                            sources = descriptor.parse_font_face_sources();
+                            return Ok(());
+                        }
+                        let start = descriptor.pos;
+                        let invalid =
+                            descriptor.error_at(start, format!("invalid value for '{}'", name));
+                        let values = descriptor.parse_values()?;
+                        // Of a range of weights or an oblique angle, the first value is kept.
+                        let values = match (name.as_str(), &values[..]) {
+                            ("font-weight", [value, _]) | ("font-style", [value, _]) => {
+                                slice::from_ref(value)
+                            }
+                            (_, values) => values,
+                        };
+                        let parsed = properties::longhand(&name)
+                            .and_then(|longhand| longhand.value_type.parse(values));
+                        match (name.as_str(), parsed.as_deref()) {
+                            ("font-family", Some([Value::String(name)])) => {
+                                family = Some(name.clone())
+                            }
+                            ("font-weight", Some([Value::Keyword(k)]))
+                                if k == "bolder" || k == "lighter" =>
+                            {
+                                return Err(invalid)
+                            }
+                            ("font-weight", Some([value])) => weight = value.clone(),
+                            ("font-style", Some([value])) => style = value.clone(),
+                            ("font-family", _) | ("font-weight", _) | ("font-style", _) => {
+                                return Err(invalid)
+                            }
+                            // ``unicode-range``, ``font-display`` and the like.
+                            _ => {}
+                        }
+                        Ok(())
+                    });
+                    if let Err(e) = result {
+                        self.errors.push(e);
+                    }
+                    self.errors.append(&mut descriptor.errors);
+                }
+            }
+        }
+        match family {
+            _ if sources.is_empty() => Err("@font-face needs a 'src'"),
+            Some(family) => Ok(FontFace {
+                family,
+                sources,
+                weight,
+                style,
+                media: vec![],
+            }),
+            None => Err("@font-face needs a 'font-family'"),
+        }
+    }
+
+    // Parses the comma-separated sources of ``src``. The invalid ones are left out.
+    fn parse_font_face_sources(&mut self) -> Vec<FontFaceSource> {
+        let mut sources = vec![];
+        while self.peek().is_some() {
+            let start = self.pos;
+            while self.peek().is_some_and(|t| *t != Token::Comma) {
+                self.skip_component_value();
+            }
+            let mut source = self.sub_parser(start, self.pos);
+            self.pos += 1;
+            source.skip_whitespace();
+            let start = source.pos;
+            let parsed = match source.peek() {
+                Some(Token::Function(ref name)) if name.eq_ignore_ascii_case("local") => {
+                    source.pos += 1;
+                    let mut args = source.parse_function_arguments();
+                    args.parse_values()
+                        .ok()
+                        .and_then(|values| match &values[..] {
+                            [Value::String(name)] => Some(FontFaceSource::Local(name.clone())),
+                            _ => values
+                                .iter()
+                                .map(|value| match value {
+                                    Value::Keyword(k) => Some(k.as_str()),
+                                    _ => None,
+                                })
+                                .collect::<Option<Vec<_>>>()
+                                .filter(|words| !words.is_empty())
+                                .map(|words| FontFaceSource::Local(words.join(" "))),
+                        })
+                }
+                _ => source.parse_url().ok().map(FontFaceSource::Url),
+            };
+            source.skip_whitespace();
+            let supported = match source.peek() {
+                Some(Token::Function(ref name)) if name.eq_ignore_ascii_case("format") => {
+                    source.pos += 1;
+                    let mut args = source.parse_function_arguments();
+                    args.parse_values().ok().is_some_and(|formats| {
+                        formats.iter().any(|format| match format {
+                            Value::String(format) | Value::Keyword(format) => {
+                                FONT_FORMATS.contains(&format.to_lowercase().as_str())
+                            }
+                            _ => false,
+                        })
+                    })
+                }
+                // ``tech()`` is ignored.
+                _ => true,
+            };
+            match parsed {
+                Some(_) if !supported => {}
+                Some(parsed) => sources.push(parsed),
+                None => {
+                    let e = source.error_at(start, "invalid source in 'src'");
+                    self.errors.push(e);
+                }
+            }
+        }
+        sources
+    }
+
+    fn parse_qualified_rule(&mut self) -> Option<Rule> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(&Token::OpenCurly) => break,
+                Some(_) => {
+                    self.skip_component_value();
+                }
+                None => {
+                    let e = self.error("unexpected end of input; expected '{'");
+                    self.errors.push(e);
+                    return None;
+                }
+            }
+        }
+
+        let mut prelude = self.sub_parser(start, self.pos);
+        let selectors = prelude.parse_selectors();
+        self.errors.append(&mut prelude.errors);
+
+        let mut block = self.parse_block();
+        let declarations = block.parse_declarations();
+        self.errors.append(&mut block.errors);
+
+        match selectors {
+            Ok(selectors) => Some(Rule {
+                selectors,
+                declarations,
+                media: vec![],
+            }),
+            Err(e) => {
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+
+    // Consumes a {}-block and returns a parser for its contents.
+    fn parse_block(&mut self) -> Parser {
+        let start = self.pos + 1;
+        let closed = self.skip_component_value();
+        if closed {
+            self.sub_parser(start, self.pos - 1)
+        } else {
+            let e = self.error("unexpected end of input; expected '}'");
+            self.errors.push(e);
+            self.sub_parser(start, self.pos)
+        }
+    }
+
+    // Skips a token, or a whole block or function with its contents. Returns false if the input
+    // ends before the block or function is closed.
+    fn skip_component_value(&mut self) -> bool {
+        let mut closing_tokens = vec![];
+        while let Some(token) = self.peek().cloned() {
+            self.pos += 1;
+            match token {
+                Token::OpenCurly => closing_tokens.push(Token::CloseCurly),
+                Token::OpenSquare => closing_tokens.push(Token::CloseSquare),
+                Token::OpenParen | Token::Function(_) => closing_tokens.push(Token::CloseParen),
+                ref t if closing_tokens.last() == Some(t) => {
+                    closing_tokens.pop();
+                }
+                _ => {}
+            }
+            if closing_tokens.is_empty() {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Methods for parsing media queries:
+
+    fn parse_media_query_list(&mut self) -> MediaQueryList {
+        let mut queries = vec![];
+        self.skip_whitespace();
+        if self.peek().is_none() {
+            return MediaQueryList(queries);
+        }
+        loop {
+            let start = self.pos;
+            while !matches!(self.peek(), Some(&Token::Comma) | None) {
+                self.skip_component_value();
+            }
+            let query = self.sub_parser(start, self.pos).parse_media_query();
+            queries.push(query.unwrap_or_else(|e| {
+                self.errors.push(e);
+                MediaQuery {
+                    negated: true,
+                    media_type: "all".to_string(),
+                    features: vec![],
+                }
+            }));
+            if self.next_token().is_none() {
+                return MediaQueryList(queries);
+            }
+        }
+    }
+
+    fn parse_media_query(&mut self) -> Result<MediaQuery, ParseError> {
+        self.skip_whitespace();
+        let mut negated = false;
+        if let Some(Token::Ident(ident)) = self.peek() {
+            if ident.eq_ignore_ascii_case("not") || ident.eq_ignore_ascii_case("only") {
+                negated = ident.eq_ignore_ascii_case("not");
+                self.pos += 1;
+                self.skip_whitespace();
+            }
+        }
+        let mut media_type = None;
+        if let Some(Token::Ident(_)) = self.peek() {
+            let name = self.parse_media_type()?;
+            if ["not", "only", "and", "or", "layer"].contains(&name.as_str()) {
+                return Err(self.error_at_last_token(format!("invalid media type '{}'", name)));
+            }
+            media_type = Some(name);
+        }
+        let mut features = vec![];
+        let mut needs_feature = media_type.is_none();
+        loop {
+            self.skip_whitespace();
+            if !needs_feature {
+                match self.peek() {
+                    None => break,
+                    Some(Token::Ident(and)) if and.eq_ignore_ascii_case("and") => {
+                        self.pos += 1;
+                        self.skip_whitespace();
+                    }
+                    Some(_) => {
+                        return Err(self.unexpected_token("'and', ',' or end of media query"))
+                    }
+                }
+            }
+            self.parse_media_feature(&mut features)?;
+            needs_feature = false;
+        }
+        Ok(MediaQuery {
+            negated,
+            media_type: media_type.unwrap_or_else(|| "all".to_string()),
+            features,
+        })
+    }
+
+    // Parses a parenthesized media feature and appends the tests it needs to ``features``.
+    fn parse_media_feature(&mut self, features: &mut Vec<MediaFeature>) -> Result<(), ParseError> {
+        if self.next_token() != Some(Token::OpenParen) {
+            self.pos -= 1;
+            return Err(self.unexpected_token("'('"));
+        }
+        let mut feature = self.parse_function_arguments();
+        self.errors.append(&mut feature.errors);
+        feature.skip_whitespace();
+        let name = match feature.next_token() {
+            Some(Token::Ident(name)) => name.to_lowercase(),
+            _ => {
+                feature.pos -= 1;
+                return Err(feature.unexpected_token("a media feature"));
+            }
+        };
+        feature.skip_whitespace();
+        let value = match feature.next_token() {
+            None => None,
+            Some(Token::Colon) => {
+                feature.skip_whitespace();
+                let value = feature.parse_value()?;
+                feature.skip_whitespace();
+                if feature.peek().is_some() {
+                    return Err(feature.unexpected_token("')'"));
+                }
+                Some(value)
+            }
+            _ => {
+                feature.pos -= 1;
+                return Err(feature.unexpected_token("':' or ')'"));
+            }
+        };
+        let invalid_value = || feature.error_at(0, format!("invalid value for '{}'", name));
+        let length = |value: &Value| match *value {
+            Value::Length(len, ref unit) => unit.to_px(len).ok_or_else(invalid_value),
+            Value::Num(0.0) => Ok(0.0),
+            _ => Err(invalid_value()),
+        };
+        match (name.as_str(), value) {
+            // In a boolean context, these are true for any viewport.
+            ("width", None)
+            | ("height", None)
+            | ("orientation", None)
+            | ("prefers-color-scheme", None) => {}
+            ("width", Some(ref value)) => {
+                let width = length(value)?;
+                features.push(MediaFeature::MinWidth(width));
+                features.push(MediaFeature::MaxWidth(width));
+            }
+            ("height", Some(ref value)) => {
+                let height = length(value)?;
+                features.push(MediaFeature::MinHeight(height));
+                features.push(MediaFeature::MaxHeight(height));
+            }
+            ("min-width", Some(ref value)) => features.push(MediaFeature::MinWidth(length(value)?)),
+            ("max-width", Some(ref value)) => features.push(MediaFeature::MaxWidth(length(value)?)),
+            ("min-height", Some(ref value)) => {
+                features.push(MediaFeature::MinHeight(length(value)?))
+            }
+            ("max-height", Some(ref value)) => {
+                features.push(MediaFeature::MaxHeight(length(value)?))
+            }
+            ("orientation", Some(Value::Keyword(keyword)))
+                if keyword == "portrait" || keyword == "landscape" =>
+            {
+                features.push(MediaFeature::Orientation(keyword))
+            }
+            ("prefers-color-scheme", Some(Value::Keyword(keyword)))
+                if keyword == "light" || keyword == "dark" =>
+            {
+                features.push(MediaFeature::PrefersColorScheme(keyword))
+            }
+            ("orientation", Some(_)) | ("prefers-color-scheme", Some(_)) => {
+                return Err(invalid_value())
+            }
+            _ => return Err(feature.error_at(0, format!("unknown media feature '{}'", name))),
+        }
+        Ok(())
+    }
+
+    fn parse_media_type(&mut self) -> Result<String, ParseError> {
+        match self.next_token() {
+            Some(Token::Ident(media_type)) => Ok(media_type.to_lowercase()),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected_token("a media type"))
+            }
+        }
+    }
+
+    // Methods for parsing selectors:
+
+    fn parse_selectors(&mut self) -> Result<Vec<Selector>, ParseError> {
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.parse_selector()?);
+            match self.next_token() {
+                Some(Token::Comma) => {}
+                _ => break,
+            }
+        }
+        // Return selectors with highest specificity first, for use in matching.
+        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
+        Ok(selectors)
+    }
+
+    // Parses a selector up to the next comma.
+    fn parse_selector(&mut self) -> Result<Selector, ParseError> {
+        let mut compounds = vec![];
+        let mut combinators = vec![];
+        loop {
+            compounds.push(self.parse_simple_selector()?);
+            let whitespace = self.skip_whitespace();
+            match self.peek() {
+                Some(&Token::Delim(c @ ('>' | '+' | '~'))) => combinators.push(c),
+                Some(&Token::Comma) | None => break,
+                Some(_) if whitespace => {
+                    combinators.push(' ');
+                    continue;
+                }
+                Some(_) => return Err(self.unexpected_token("a selector")),
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+        }
+
+        let mut selector = Selector::Simple(compounds.pop().unwrap());
+        while let Some(compound) = compounds.pop() {
+            selector = match combinators.pop() {
+                Some('>') => Selector::Child(compound, Box::new(selector)),
+                Some('+') => Selector::NextSibling(compound, Box::new(selector)),
+                Some('~') => Selector::SubsequentSibling(compound, Box::new(selector)),
+                _ => Selector::Descendant(compound, Box::new(selector)),
+            };
+        }
+        Ok(selector)
+    }
+
+    fn parse_simple_selector(&mut self) -> Result<SimpleSelector, ParseError> {
+        let mut selector = SimpleSelector {
+            tag_name: None,
+            id: None,
+            class: HashSet::new(),
+            attributes: vec![],
+            pseudo_classes: vec![],
+            pseudo_element: None,
+        };
+        let start = self.pos;
+        match self.peek().cloned() {
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                selector.tag_name = Some(name.to_lowercase());
+            }
+            Some(Token::Delim('*')) => self.pos += 1, // universal selector
+            _ => {}
+        }
+        // A pseudo-element comes last.
+        while selector.pseudo_element.is_none() {
+            match self.peek().cloned() {
+                Some(Token::Hash { value, is_id: true }) => {
+                    self.pos += 1;
+                    selector.id = Some(value);
+                }
+                Some(Token::Delim('.')) => {
+                    self.pos += 1;
+                    match self.next_token() {
+                        Some(Token::Ident(class)) => {
+                            selector.class.insert(class);
+                        }
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.unexpected_token("a class name"));
+                        }
+                    }
+                }
+                Some(Token::Colon) => self.parse_pseudo_class_or_element(&mut selector)?,
+                Some(Token::OpenSquare) => {
+                    let start = self.pos + 1;
+                    if !self.skip_component_value() {
+                        return Err(self.error("unexpected end of input; expected ']'"));
+                    }
+                    let attribute = self.sub_parser(start, self.pos - 1).parse_attribute()?;
+                    selector.attributes.push(attribute);
+                }
+                _ => break,
+            }
+        }
+        if self.pos == start {
+            return Err(self.unexpected_token("a selector"));
+        }
+        Ok(selector)
+    }
+
+    fn parse_pseudo_class_or_element(
+        &mut self,
+        selector: &mut SimpleSelector,
+    ) -> Result<(), ParseError> {
+        self.pos += 1;
+        let is_pseudo_element = self.peek() == Some(&Token::Colon);
+        if is_pseudo_element {
+            self.pos += 1;
+        }
+        let pseudo_class = match self.next_token() {
+            Some(Token::Ident(name)) => {
+                let name = name.to_lowercase();
+                // The pseudo-elements of CSS 2 may have a single colon.
+                if is_pseudo_element
+                    || matches!(
+                        name.as_str(),
+                        "before" | "after" | "first-line" | "first-letter"
+                    )
+                {
+                    selector.pseudo_element = Some(name);
+                    return Ok(());
+                }
+                match name.as_str() {
+                    "root" => PseudoClass::Root,
+                    "empty" => PseudoClass::Empty,
+                    "first-child" => PseudoClass::FirstChild,
+                    "last-child" => PseudoClass::LastChild,
+                    "only-child" => PseudoClass::OnlyChild,
+                    "first-of-type" => PseudoClass::FirstOfType,
+                    "last-of-type" => PseudoClass::LastOfType,
+                    "only-of-type" => PseudoClass::OnlyOfType,
+                    "link" | "any-link" => PseudoClass::Link,
+                    "hover" | "active" | "focus" | "focus-visible" | "focus-within" | "visited"
+                    | "target" | "checked" | "disabled" | "enabled" => {
+                        PseudoClass::Unsupported(name)
+                    }
+                    _ => {
+                        return Err(
+                            self.error_at_last_token(format!("unknown pseudo-class ':{}'", name))
+                        )
+                    }
+                }
+            }
+            Some(Token::Function(name)) if !is_pseudo_element => {
+                let unknown =
+                    self.error_at_last_token(format!("unknown pseudo-class ':{}()'", name));
+                let start = self.pos;
+                self.pos -= 1;
+                if !self.skip_component_value() {
+                    return Err(self.error("unexpected end of input; expected ')'"));
+                }
+                let mut arguments = self.sub_parser(start, self.pos - 1);
+                match name.to_lowercase().as_str() {
+                    "nth-child" => arguments
+                        .parse_nth()
+                        .map(|(a, b)| PseudoClass::NthChild(a, b))?,
+                    "nth-last-child" => arguments
+                        .parse_nth()
+                        .map(|(a, b)| PseudoClass::NthLastChild(a, b))?,
+                    "nth-of-type" => arguments
+                        .parse_nth()
+                        .map(|(a, b)| PseudoClass::NthOfType(a, b))?,
+                    "nth-last-of-type" => arguments
+                        .parse_nth()
+                        .map(|(a, b)| PseudoClass::NthLastOfType(a, b))?,
+                    "not" => PseudoClass::Not(arguments.parse_simple_selectors()?),
+                    _ => return Err(unknown),
+                }
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected_token("a pseudo-class or pseudo-element"));
+            }
+        };
+        selector.pseudo_classes.push(pseudo_class);
+        Ok(())
+    }
+
+    // Parses the comma-separated simple selectors in ``:not()``.
+    fn parse_simple_selectors(&mut self) -> Result<Vec<SimpleSelector>, ParseError> {
+        let mut selectors = vec![];
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.parse_simple_selector()?);
+            self.skip_whitespace();
+            match self.next_token() {
+                Some(Token::Comma) => {}
+                None => return Ok(selectors),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected_token("',' or ')'"));
+                }
+            }
+        }
     }
 
-    fn parse_rgba_color(&mut self) -> Result<Value, ()> {
-        assert_eq!(self.consume_char_ignore_whitescape()?, '(');
-        let r = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
-        let g = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
-        let b = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
-        let a = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ')');
+    // Parses the argument of ``:nth-child()`` and the like into ``(a, b)``.
+    // ref. https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+    fn parse_nth(&mut self) -> Result<(i32, i32), ParseError> {
+        fn integer(n: f64) -> Option<i32> {
+            if n.fract() == 0.0 {
+                Some(n as i32)
+            } else {
+                None
+            }
+        }
+
+        self.skip_whitespace();
+        let start = self.pos;
+        let invalid = |parser: &Parser| parser.error_at(start, "invalid An+B");
+        let (a, rest) = match self.next_token() {
+            Some(Token::Ident(ref s))
+                if s.eq_ignore_ascii_case("odd") || s.eq_ignore_ascii_case("even") =>
+            {
+                let b = if s.eq_ignore_ascii_case("odd") { 1 } else { 0 };
+                self.skip_whitespace();
+                return if self.peek().is_none() {
+                    Ok((2, b))
+                } else {
+                    Err(invalid(self))
+                };
+            }
+            Some(Token::Number { value: b, .. }) => {
+                self.skip_whitespace();
+                return match integer(b) {
+                    Some(b) if self.peek().is_none() => Ok((0, b)),
+                    _ => Err(invalid(self)),
+                };
+            }
+            Some(Token::Dimension(a, unit)) => match integer(a) {
+                Some(a) => (a, unit.to_lowercase()),
+                None => return Err(invalid(self)),
+            },
+            Some(Token::Ident(s)) => match s.to_lowercase() {
+                ref s if s.starts_with('-') => (-1, s[1..].to_string()),
+                s => (1, s),
+            },
+            // "+n" is a '+' followed by an identifier.
+            Some(Token::Delim('+')) => match self.next_token() {
+                Some(Token::Ident(s)) => (1, s.to_lowercase()),
+                _ => return Err(invalid(self)),
+            },
+            _ => return Err(invalid(self)),
+        };
+        let rest = match rest.strip_prefix('n') {
+            Some(rest) => rest.to_string(),
+            None => return Err(invalid(self)),
+        };
+        self.skip_whitespace();
+        let b = if rest.is_empty() {
+            match self.next_token() {
+                None => 0,
+                Some(Token::Delim(sign)) if sign == '+' || sign == '-' => {
+                    self.skip_whitespace();
+                    match self.next_token().and_then(|t| match t {
+                        Token::Number {
+                            value: b,
+                            has_sign: false,
+                        } => integer(b),
+                        _ => None,
+                    }) {
+                        Some(b) if sign == '-' => -b,
+                        Some(b) => b,
+                        None => return Err(invalid(self)),
+                    }
+                }
+                Some(Token::Number {
+                    value: b,
+                    has_sign: true,
+                }) => integer(b).ok_or_else(|| invalid(self))?,
+                _ => return Err(invalid(self)),
+            }
+        } else if rest == "-" {
+            match self.next_token() {
+                Some(Token::Number {
+                    value: b,
+                    has_sign: false,
+                }) => -integer(b).ok_or_else(|| invalid(self))?,
+                _ => return Err(invalid(self)),
+            }
+        } else {
+            match rest.strip_prefix('-') {
+                Some(digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                    -digits.parse::<i32>().map_err(|_| invalid(self))?
+                }
+                _ => return Err(invalid(self)),
+            }
+        };
+        self.skip_whitespace();
+        if self.peek().is_some() {
+            return Err(invalid(self));
+        }
+        Ok((a, b))
+    }
+
+    // Parses the contents of ``[...]``.
+    fn parse_attribute(&mut self) -> Result<AttrSelector, ParseError> {
+        self.skip_whitespace();
+        let name = match self.next_token() {
+            Some(Token::Ident(name)) => name.to_lowercase(),
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected_token("an attribute name"));
+            }
+        };
+        self.skip_whitespace();
+
+        let operator = match self.next_token() {
+            None => {
+                return Ok(AttrSelector {
+                    name,
+                    operator: AttrOperator::Exists,
+                    value: String::new(),
+                    case_insensitive: false,
+                })
+            }
+            Some(Token::Delim('=')) => AttrOperator::Equals,
+            Some(Token::Delim(c)) if self.peek() == Some(&Token::Delim('=')) => {
+                self.pos += 1;
+                match c {
+                    '~' => AttrOperator::Includes,
+                    '|' => AttrOperator::DashMatch,
+                    '^' => AttrOperator::Prefix,
+                    '$' => AttrOperator::Suffix,
+                    '*' => AttrOperator::Substring,
+                    _ => {
+                        self.pos -= 2;
+                        return Err(self.unexpected_token("']' or an attribute operator"));
+                    }
+                }
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected_token("']' or an attribute operator"));
+            }
+        };
+        self.skip_whitespace();
+
+        let value = match self.next_token() {
+            Some(Token::Ident(value)) | Some(Token::String(value)) => value,
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected_token("an attribute value"));
+            }
+        };
+        self.skip_whitespace();
+
+        let case_insensitive = match self.peek() {
+            Some(Token::Ident(flag)) if flag.eq_ignore_ascii_case("i") => true,
+            Some(Token::Ident(flag)) if flag.eq_ignore_ascii_case("s") => false,
+            Some(_) => return Err(self.unexpected_token("']'")),
+            None => false,
+        };
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek().is_some() {
+            return Err(self.unexpected_token("']'"));
+        }
+        Ok(AttrSelector {
+            name,
+            operator,
+            value,
+            case_insensitive,
+        })
+    }
+
+    // Methods for parsing declarations:
+
+    fn parse_declarations(&mut self) -> Vec<Declaration> {
+        let mut declarations = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(&Token::Whitespace) | Some(&Token::Semicolon) => self.pos += 1,
+                Some(&Token::AtKeyword(_)) => self.parse_at_rule(),
+                Some(_) => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|t| *t != Token::Semicolon) {
+                        self.skip_component_value();
+                    }
+                    let mut declaration = self.sub_parser(start, self.pos);
+                    match declaration.parse_declaration() {
+                        Ok(mut ok) => declarations.append(&mut ok),
+                        Err(e) => self.errors.push(e),
+                    }
+                    self.errors.append(&mut declaration.errors);
+                }
+            }
+        }
+        declarations
+    }
+
+    // Parses a declaration into declarations of longhands, see ``properties::parse``.
+    fn parse_declaration(&mut self) -> Result<Vec<Declaration>, ParseError> {
+        let name = self.parse_property_name()?;
+        let important = self.remove_important();
+        let declaration = |name: String, values| Declaration {
+            name,
+            values,
+            important,
+        };
+        let uses_var = self.tokens[self.pos..]
+            .iter()
+            .any(|t| matches!(t, Token::Function(name) if name.eq_ignore_ascii_case("var")));
+        if name.starts_with("--") || uses_var {
+            self.skip_whitespace();
+            let mut tokens = self.tokens[self.pos..].to_vec();
+            while tokens.last() == Some(&Token::Whitespace) {
+                tokens.pop();
+            }
+            if tokens.is_empty() && !name.starts_with("--") {
+                return Err(self.unexpected_token("a value"));
+            }
+            return Ok(match properties::shorthand(&name) {
+                Some(shorthand) => shorthand
+                    .longhands
+                    .iter()
+                    .map(|longhand| {
+                        let value = Value::PendingSubstitution(name.clone(), tokens.clone());
+                        declaration(longhand.to_string(), vec![value])
+                    })
+                    .collect(),
+                None => vec![declaration(name, vec![Value::Unparsed(tokens)])],
+            });
+        }
+        self.skip_whitespace();
+        let start = self.pos;
+        let values = self.parse_values()?;
+        if values.is_empty() {
+            return Err(self.unexpected_token("a value"));
+        }
+        match properties::parse(&name, &values) {
+            Some(longhands) => Ok(longhands
+                .into_iter()
+                .map(|(name, values)| declaration(name, values))
+                .collect()),
+            None => Err(self.error_at(start, format!("invalid value for '{}'", name))),
+        }
+    }
+
+    // Parses the name of a declaration or descriptor, and the colon after it.
+    fn parse_property_name(&mut self) -> Result<String, ParseError> {
+        let name = match self.next_token() {
+            // Custom property names are case-sensitive.
+            Some(Token::Ident(name)) if name.starts_with("--") => name,
+            Some(Token::Ident(name)) => name.to_lowercase(),
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected_token("a property name"));
+            }
+        };
+        self.skip_whitespace();
+        if self.next_token() != Some(Token::Colon) {
+            self.pos -= 1;
+            return Err(self.unexpected_token("':'"));
+        }
+        Ok(name)
+    }
+
+    // Removes a trailing "!important" from the declaration and returns whether there was one.
+    fn remove_important(&mut self) -> bool {
+        let mut rest = self.tokens[self.pos..]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|&(_, t)| *t != Token::Whitespace);
+        let range = match (rest.next(), rest.next()) {
+            (Some((i, Token::Ident(important))), Some((j, Token::Delim('!'))))
+                if important.eq_ignore_ascii_case("important") =>
+            {
+                self.pos + j..self.pos + i + 1
+            }
+            _ => return false,
+        };
+        self.tokens.drain(range.clone());
+        self.locations.drain(range);
+        true
+    }
+
+    // Methods for parsing values:
+
+    fn parse_values(&mut self) -> Result<Vec<Value>, ParseError> {
+        let mut values = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(&Token::Comma) => {
+                    self.pos += 1;
+                    values.push(Value::Comma);
+                }
+                Some(&Token::Delim('/')) => {
+                    self.pos += 1;
+                    values.push(Value::Slash);
+                }
+                Some(_) => values.push(self.parse_value()?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        match self.next_token() {
+            Some(Token::Ident(name)) => Ok(Value::Keyword(name.to_lowercase())),
+            Some(Token::Number { value, .. }) => Ok(Value::Num(value)),
+            Some(Token::Percentage(num)) => Ok(Value::Length(num, Unit::Percent)),
+            Some(Token::Dimension(num, unit)) => Ok(Value::Length(num, self.parse_unit(&unit)?)),
+            Some(Token::Hash { value, .. }) => self.parse_color(&value),
+            Some(Token::String(string)) => Ok(Value::String(string)),
+            Some(Token::Url(url)) => Ok(Value::Url(url)),
+            Some(Token::Function(ref name)) if name.eq_ignore_ascii_case("url") => {
+                self.pos -= 1;
+                self.parse_url().map(Value::Url)
+            }
+            Some(Token::Function(name)) => {
+                let start = self.pos - 1;
+                let mut arguments = self.parse_function_arguments();
+                let value = match name.to_lowercase().as_str() {
+                    name @ ("rgb" | "rgba" | "hsl" | "hsla" | "hwb") => {
+                        arguments.parse_color_function(name)
+                    }
+                    name if is_math_function(name) => {
+                        arguments.parse_math_function(name).map(Calc::into_value)
+                    }
+                    name => Err(self.error_at(start, format!("unsupported function '{}()'", name))),
+                };
+                self.errors.append(&mut arguments.errors);
+                value
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected_token("a value"))
+            }
+        }
+    }
+
+    // Parses the arguments of a math function.
+    fn parse_math_function(&mut self, name: &str) -> Result<Calc, ParseError> {
+        let mut args = vec![];
+        loop {
+            args.push(self.parse_calc_sum()?);
+            match self.next_token() {
+                None => break,
+                Some(Token::Comma) if name != "calc" => {}
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected_token("')'"));
+                }
+            }
+        }
+        let calc = match name {
+            "min" => Calc::Min(args),
+            "max" => Calc::Max(args),
+            "clamp" if args.len() == 3 => {
+                let mut args = args.into_iter().map(Box::new);
+                let (min, value, max) = (args.next(), args.next(), args.next());
+                Calc::Clamp(min.unwrap(), value.unwrap(), max.unwrap())
+            }
+            "clamp" => return Err(self.error_at(0, "clamp() takes three arguments")),
+            _ => args.remove(0),
+        };
+        if calc.calc_type().is_none() {
+            return Err(self.error_at(0, format!("mismatched types in {}()", name)));
+        }
+        Ok(calc)
+    }
+
+    // Parses terms added or subtracted, which needs whitespace around the operator.
+    fn parse_calc_sum(&mut self) -> Result<Calc, ParseError> {
+        self.skip_whitespace();
+        let mut sum = self.parse_calc_product()?;
+        loop {
+            let whitespace = self.skip_whitespace();
+            let operator = match self.peek() {
+                Some(&Token::Delim(c @ ('+' | '-'))) if whitespace => c,
+                _ => return Ok(sum),
+            };
+            self.pos += 1;
+            if !self.skip_whitespace() {
+                return Err(self.error(format!("expected whitespace after '{}'", operator)));
+            }
+            let term = Box::new(self.parse_calc_product()?);
+            sum = match operator {
+                '+' => Calc::Sum(Box::new(sum), term),
+                _ => Calc::Difference(Box::new(sum), term),
+            };
+        }
+    }
+
+    fn parse_calc_product(&mut self) -> Result<Calc, ParseError> {
+        let mut product = self.parse_calc_value()?;
+        loop {
+            let start = self.pos;
+            self.skip_whitespace();
+            let operator = match self.peek() {
+                Some(&Token::Delim(c @ ('*' | '/'))) => c,
+                _ => {
+                    self.pos = start;
+                    return Ok(product);
+                }
+            };
+            self.pos += 1;
+            self.skip_whitespace();
+            let factor = self.parse_calc_value()?;
+            if operator == '/' && factor == Calc::Num(0.0) {
+                return Err(self.error_at_last_token("division by zero"));
+            }
+            product = match operator {
+                '*' => Calc::Product(Box::new(product), Box::new(factor)),
+                _ => Calc::Quotient(Box::new(product), Box::new(factor)),
+            };
+        }
+    }
+
+    fn parse_calc_value(&mut self) -> Result<Calc, ParseError> {
+        match self.next_token() {
+            Some(Token::Number { value, .. }) => Ok(Calc::Num(value)),
+            Some(Token::Percentage(num)) => Ok(Calc::Length(num, Unit::Percent)),
+            Some(Token::Dimension(num, unit)) => Ok(Calc::Length(num, self.parse_unit(&unit)?)),
+            Some(Token::OpenParen) => {
+                let mut nested = self.parse_function_arguments();
+                let calc = nested
+                    .parse_calc_sum()
+                    .and_then(|calc| match nested.peek() {
+                        None => Ok(calc),
+                        Some(_) => Err(nested.unexpected_token("')'")),
+                    });
+                self.errors.append(&mut nested.errors);
+                calc
+            }
+            Some(Token::Function(ref name)) if is_math_function(name) => {
+                let mut arguments = self.parse_function_arguments();
+                let calc = arguments.parse_math_function(&name.to_lowercase());
+                self.errors.append(&mut arguments.errors);
+                calc
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected_token("a number, length or percentage"))
+            }
+        }
+    }
+
+    // Substitutes a ``var()`` function, given a parser for its arguments.
+    fn substitute_var<F>(&mut self, lookup: &mut F) -> Option<Vec<Token>>
+    where
+        F: FnMut(&str) -> Option<Vec<Token>>,
+    {
+        self.skip_whitespace();
+        let name = match self.next_token() {
+            Some(Token::Ident(name)) if name.starts_with("--") => name,
+            _ => return None,
+        };
+        self.skip_whitespace();
+        match self.next_token() {
+            None => lookup(&name),
+            Some(Token::Comma) => {
+                lookup(&name).or_else(|| substitute_vars(&self.tokens[self.pos..], lookup))
+            }
+            Some(_) => None,
+        }
+    }
+
+    // The function token has been consumed. Returns a parser for the arguments.
+    fn parse_function_arguments(&mut self) -> Parser {
+        let start = self.pos;
+        self.pos -= 1;
+        if self.skip_component_value() {
+            self.sub_parser(start, self.pos - 1)
+        } else {
+            let e = self.error("unexpected end of input; expected ')'");
+            self.errors.push(e);
+            self.sub_parser(start, self.pos)
+        }
+    }
+
+    fn parse_unit(&mut self, unit: &str) -> Result<Unit, ParseError> {
+        Ok(match unit.to_lowercase().as_str() {
+            "px" => Unit::Px,
+            "pt" => Unit::Pt,
+            "pc" => Unit::Pc,
+            "in" => Unit::In,
+            "cm" => Unit::Cm,
+            "mm" => Unit::Mm,
+            "em" => Unit::Em,
+            "rem" => Unit::Rem,
+            "ex" => Unit::Ex,
+            "ch" => Unit::Ch,
+            "vw" => Unit::Vw,
+            "vh" => Unit::Vh,
+            "vmin" => Unit::Vmin,
+            "vmax" => Unit::Vmax,
+            unit => return Err(self.error_at_last_token(format!("unknown unit '{}'", unit))),
+        })
+    }
+
+    // Parses the arguments of ``rgb()``, ``rgba()``, ``hsl()``, ``hsla()`` or ``hwb()``, either
+    // separated by commas or, in the modern syntax, by spaces with an optional "/ alpha".
+    fn parse_color_function(&mut self, name: &str) -> Result<Value, ParseError> {
+        let mut args = vec![];
+        // Whether the arguments are separated by commas, once known.
+        let mut commas = None;
+        let mut slash = false;
+        loop {
+            self.skip_whitespace();
+            match self.next_token() {
+                Some(arg @ Token::Number { .. })
+                | Some(arg @ Token::Percentage(_))
+                | Some(arg @ Token::Dimension(..)) => args.push(arg),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected_token("a number or percentage"));
+                }
+            }
+            let whitespace = self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(&Token::Comma) if commas != Some(false) && name != "hwb" => {
+                    commas = Some(true)
+                }
+                Some(&Token::Delim('/')) if commas != Some(true) && args.len() == 3 => {
+                    commas = Some(false);
+                    slash = true;
+                }
+                Some(_) if whitespace && commas != Some(true) && args.len() < 3 => {
+                    commas = Some(false);
+                    continue;
+                }
+                Some(_) => return Err(self.unexpected_token("')'")),
+            }
+            self.pos += 1;
+        }
+        if args.len() < 3 || args.len() > 4 || (args.len() == 4 && commas == Some(false) && !slash)
+        {
+            return Err(self.error_at(0, format!("expected 3 or 4 arguments to {}()", name)));
+        }
+
+        let invalid =
+            |arg: &Token| self.error_at(0, format!("invalid argument '{}' to {}()", arg, name));
+        // Saturation, lightness, whiteness and blackness, from 0 to 1.
+        let fraction = |arg: &Token| match *arg {
+            Token::Percentage(p) => Ok((p / 100.0).clamp(0.0, 1.0)),
+            Token::Number { value: n, .. } if commas != Some(true) => {
+                Ok((n / 100.0).clamp(0.0, 1.0))
+            }
+            _ => Err(invalid(arg)),
+        };
+        let hue = |arg: &Token| match *arg {
+            Token::Number { value: deg, .. } => Ok(deg),
+            Token::Dimension(angle, ref unit) => match unit.to_lowercase().as_str() {
+                "deg" => Ok(angle),
+                "rad" => Ok(angle.to_degrees()),
+                "grad" => Ok(angle * 0.9),
+                "turn" => Ok(angle * 360.0),
+                _ => Err(invalid(arg)),
+            },
+            _ => Err(invalid(arg)),
+        };
+        let (r, g, b) = match name {
+            "rgb" | "rgba" => {
+                let channel = |arg: &Token| match *arg {
+                    Token::Number { value: n, .. } => Ok(n / 255.0),
+                    Token::Percentage(p) => Ok(p / 100.0),
+                    _ => Err(invalid(arg)),
+                };
+                (channel(&args[0])?, channel(&args[1])?, channel(&args[2])?)
+            }
+            "hsl" | "hsla" => hsl_to_rgb(hue(&args[0])?, fraction(&args[1])?, fraction(&args[2])?),
+            _ => hwb_to_rgb(hue(&args[0])?, fraction(&args[1])?, fraction(&args[2])?),
+        };
+        let alpha = match args.get(3) {
+            Some(&Token::Number { value: n, .. }) => n,
+            Some(&Token::Percentage(p)) => p / 100.0,
+            Some(arg) => return Err(invalid(arg)),
+            None => 1.0,
+        };
+        let byte = |f: f64| (f.clamp(0.0, 1.0) * 255.0).round() as u8;
         Ok(Value::Color(Color {
-            r: r as u8,
-            g: g as u8,
-            b: b as u8,
-            a: (255.0 * a) as u8,
+            r: byte(r),
+            g: byte(g),
+            b: byte(b),
+            a: byte(alpha),
         }))
     }
 
-    fn parse_url(&mut self) -> Result<Value, ()> {
-        // TODO: Implement correctly
-        assert_eq!(self.consume_char_ignore_whitescape()?, '(');
-        self.consume_while(|c| c != ')')?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ')');
-        Ok(Value::Num(0.0))
-    }
-
-    fn parse_color(&mut self) -> Result<Value, ()> {
-        assert_eq!(self.consume_char()?, '#');
-        let hex_str = self.parse_hex_num()?;
-        let (r, g, b) = match hex_str.len() {
-            3 => {
-                let r = u8::from_str_radix(&hex_str[0..1], 16).unwrap();
-                let g = u8::from_str_radix(&hex_str[1..2], 16).unwrap();
-                let b = u8::from_str_radix(&hex_str[2..3], 16).unwrap();
-                (r * 16 + r, g * 16 + g, b * 16 + b)
-            }
-            6 => (
-                u8::from_str_radix(&hex_str[0..2], 16).unwrap(),
-                u8::from_str_radix(&hex_str[2..4], 16).unwrap(),
-                u8::from_str_radix(&hex_str[4..6], 16).unwrap(),
-            ),
-            _ => panic!(),
+    fn parse_color(&mut self, hex_str: &str) -> Result<Value, ParseError> {
+        let digits: Vec<u8> = hex_str
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .unwrap_or_default();
+        let channels: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|d| d * 17).collect(),
+            6 | 8 => digits.chunks(2).map(|d| d[0] * 16 + d[1]).collect(),
+            _ => return Err(self.error_at_last_token(format!("invalid color '#{}'", hex_str))),
         };
         Ok(Value::Color(Color {
-            r: r,
-            g: g,
-            b: b,
-            a: 255,
+            r: channels[0],
+            g: channels[1],
+            b: channels[2],
+            a: channels.get(3).cloned().unwrap_or(255),
         }))
     }
 
-    fn parse_hex_num(&mut self) -> Result<String, ()> {
-        self.consume_while(valid_hex_char)
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
     }
 
-    // fn parse_hex_pair(&mut self) -> Result<u8, ()> {
-    //     let s = &self.input[self.pos..self.pos + 2];
-    //     self.pos += 2;
-    //     u8::from_str_radix(s, 16).unwrap()
-    // }
+    // Moves to the next token even at the end of input, so that ``self.pos -= 1`` always goes
+    // back to the token returned.
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
 
-    fn parse_identifier(&mut self) -> Result<String, ()> {
-        Ok(self.consume_while(valid_ident_char)?.to_lowercase())
+    // Returns true if any whitespace is skipped.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek() == Some(&Token::Whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
     }
+}
 
-    fn parse_identifier_percent(&mut self) -> Result<String, ()> {
-        Ok(self.consume_while(valid_ident_percent_char)?.to_lowercase())
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Selector::Simple(ref selector) => write!(f, "{}", selector),
+            Selector::Descendant(ref a, ref b) => write!(f, "{} {}", a, b),
+            Selector::Child(ref a, ref b) => write!(f, "{} > {}", a, b),
+            Selector::NextSibling(ref a, ref b) => write!(f, "{} + {}", a, b),
+            Selector::SubsequentSibling(ref a, ref b) => write!(f, "{} ~ {}", a, b),
+        }
     }
+}
 
-    fn consume_char_ignore_whitescape(&mut self) -> Result<char, ()> {
-        self.consume_whitespace()?;
-        let c = self.consume_char()?;
-        self.consume_whitespace()?;
-        Ok(c)
+impl fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut universal = true;
+        if let Some(ref tag_name) = self.tag_name {
+            universal = false;
+            write!(f, "{}", tag_name)?;
+        }
+        for class in &self.class {
+            universal = false;
+            write!(f, ".{}", class)?;
+        }
+        if let Some(ref id) = self.id {
+            universal = false;
+            write!(f, "#{}", id)?;
+        }
+        for attribute in &self.attributes {
+            universal = false;
+            write!(f, "{}", attribute)?;
+        }
+        for pseudo_class in &self.pseudo_classes {
+            universal = false;
+            write!(f, "{}", pseudo_class)?;
+        }
+        if let Some(ref pseudo_element) = self.pseudo_element {
+            universal = false;
+            write!(f, "::{}", pseudo_element)?;
+        }
+        if universal {
+            write!(f, "*")?;
+        }
+        Ok(())
     }
+}
 
-    fn consume_whitespace(&mut self) -> Result<(), ()> {
-        self.consume_while(char::is_whitespace).and(Ok(()))
+impl fmt::Display for PseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, a, b) = match *self {
+            PseudoClass::Root => return write!(f, ":root"),
+            PseudoClass::Empty => return write!(f, ":empty"),
+            PseudoClass::FirstChild => return write!(f, ":first-child"),
+            PseudoClass::LastChild => return write!(f, ":last-child"),
+            PseudoClass::OnlyChild => return write!(f, ":only-child"),
+            PseudoClass::FirstOfType => return write!(f, ":first-of-type"),
+            PseudoClass::LastOfType => return write!(f, ":last-of-type"),
+            PseudoClass::OnlyOfType => return write!(f, ":only-of-type"),
+            PseudoClass::Link => return write!(f, ":link"),
+            PseudoClass::Unsupported(ref name) => return write!(f, ":{}", name),
+            PseudoClass::Not(ref selectors) => {
+                write!(f, ":not(")?;
+                for (i, selector) in selectors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", selector)?;
+                }
+                return write!(f, ")");
+            }
+            PseudoClass::NthChild(a, b) => ("nth-child", a, b),
+            PseudoClass::NthLastChild(a, b) => ("nth-last-child", a, b),
+            PseudoClass::NthOfType(a, b) => ("nth-of-type", a, b),
+            PseudoClass::NthLastOfType(a, b) => ("nth-last-of-type", a, b),
+        };
+        write!(f, ":{}({}n{:+})", name, a, b)
     }
+}
 
-    fn consume_while<F>(&mut self, mut f: F) -> Result<String, ()>
-    where
-        F: FnMut(char) -> bool,
-    {
-        let mut s = "".to_string();
-        while !self.eof() && f(self.next_char()?) {
-            s.push(self.consume_char()?);
+impl fmt::Display for AttrSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = match self.operator {
+            AttrOperator::Exists => return write!(f, "[{}]", self.name),
+            AttrOperator::Equals => "=",
+            AttrOperator::Includes => "~=",
+            AttrOperator::DashMatch => "|=",
+            AttrOperator::Prefix => "^=",
+            AttrOperator::Suffix => "$=",
+            AttrOperator::Substring => "*=",
+        };
+        write!(f, "[{}{}{:?}", self.name, operator, self.value)?;
+        if self.case_insensitive {
+            write!(f, " i")?;
         }
-        Ok(s)
+        write!(f, "]")
     }
-    fn consume_char(&mut self) -> Result<char, ()> {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().ok_or(())?;
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
-        Ok(cur_char)
+}
+
+impl fmt::Display for MediaQueryList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, query) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", query)?;
+        }
+        Ok(())
     }
+}
 
-    fn skip_char_if_any(&mut self, c: char) -> Result<bool, ()> {
-        if !self.eof() && self.next_char()? == c {
-            assert_eq!(self.consume_char()?, c);
-            return Ok(true);
+impl fmt::Display for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "not ")?;
+        }
+        write!(f, "{}", self.media_type)?;
+        for feature in &self.features {
+            match *feature {
+                MediaFeature::MinWidth(px) => write!(f, " and (min-width: {}px)", px)?,
+                MediaFeature::MaxWidth(px) => write!(f, " and (max-width: {}px)", px)?,
+                MediaFeature::MinHeight(px) => write!(f, " and (min-height: {}px)", px)?,
+                MediaFeature::MaxHeight(px) => write!(f, " and (max-height: {}px)", px)?,
+                MediaFeature::Orientation(ref orientation) => {
+                    write!(f, " and (orientation: {})", orientation)?
+                }
+                MediaFeature::PrefersColorScheme(ref scheme) => {
+                    write!(f, " and (prefers-color-scheme: {})", scheme)?
+                }
+            }
         }
-        Ok(false)
+        Ok(())
     }
+}
 
-    fn next_char(&self) -> Result<char, ()> {
-        self.input[self.pos..].chars().next().ok_or(())
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Unit::Px => write!(f, "px"),
+            Unit::Pt => write!(f, "pt"),
+            Unit::Pc => write!(f, "pc"),
+            Unit::In => write!(f, "in"),
+            Unit::Cm => write!(f, "cm"),
+            Unit::Mm => write!(f, "mm"),
+            Unit::Percent => write!(f, "%"),
+            Unit::Em => write!(f, "em"),
+            Unit::Rem => write!(f, "rem"),
+            Unit::Ex => write!(f, "ex"),
+            Unit::Ch => write!(f, "ch"),
+            Unit::Vw => write!(f, "vw"),
+            Unit::Vh => write!(f, "vh"),
+            Unit::Vmin => write!(f, "vmin"),
+            Unit::Vmax => write!(f, "vmax"),
+        }
     }
+}
 
-    fn next2_char(&self) -> Result<char, ()> {
-        let mut iter = self.input[self.pos..].char_indices();
-        iter.next().ok_or(())?;
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.input[self.pos + next_pos..].chars().next().ok_or(())
+impl fmt::Display for Calc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (function, args): (&str, Vec<&Calc>) = match *self {
+            Calc::Min(ref args) => ("min", args.iter().collect()),
+            Calc::Max(ref args) => ("max", args.iter().collect()),
+            Calc::Clamp(ref min, ref value, ref max) => ("clamp", vec![min, value, max]),
+            _ => ("calc", vec![self]),
+        };
+        write!(f, "{}(", function)?;
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            arg.fmt_expression(f)?;
+        }
+        write!(f, ")")
     }
+}
 
-    fn eof(&self) -> bool {
-        self.pos >= self.input.len()
+impl Calc {
+    // Writes the expression without an enclosing ``calc()``.
+    fn fmt_expression(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (a, operator, b) = match *self {
+            Calc::Length(len, ref unit) => return write!(f, "{}{}", len, unit),
+            Calc::Num(num) => return write!(f, "{}", num),
+            Calc::Sum(ref a, ref b) => (a, '+', b),
+            Calc::Difference(ref a, ref b) => (a, '-', b),
+            Calc::Product(ref a, ref b) => (a, '*', b),
+            Calc::Quotient(ref a, ref b) => (a, '/', b),
+            Calc::Min(_) | Calc::Max(_) | Calc::Clamp(..) => return write!(f, "{}", self),
+        };
+        for (i, operand) in [a, b].iter().enumerate() {
+            if i > 0 {
+                write!(f, " {} ", operator)?;
+            }
+            match ***operand {
+                Calc::Sum(..) | Calc::Difference(..) | Calc::Product(..) | Calc::Quotient(..) => {
+                    write!(f, "(")?;
+                    operand.fmt_expression(f)?;
+                    write!(f, ")")?;
+                }
+                _ => operand.fmt_expression(f)?,
+            }
+        }
+        Ok(())
     }
 }
 
 impl fmt::Display for Stylesheet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for import in &self.imports {
+            try!(writeln!(
+                f,
+                "@import url({:?}) {};",
+                import.url, import.media
+            ));
+        }
+        for font_face in &self.font_faces {
+            let sources: Vec<String> = font_face
+                .sources
+                .iter()
+                .map(|source| match source {
+                    FontFaceSource::Url(url) => format!("url({:?})", url),
+                    FontFaceSource::Local(name) => format!("local({:?})", name),
+                })
+                .collect();
+            for media in &font_face.media {
+                write!(f, "@media {} ", media)?;
+            }
+            writeln!(
+                f,
+                "@font-face {{ font-family: {:?}; src: {} }}",
+                font_face.family,
+                sources.join(", ")
+            )?;
+        }
         for rule in &self.rules {
+            for media in &rule.media {
+                try!(write!(f, "@media {} ", media));
+            }
             for (i, selector) in rule.selectors.iter().enumerate() {
-                fn show(f: &mut fmt::Formatter, selector: &Selector) -> fmt::Result {
-                    fn show_simple(
-                        f: &mut fmt::Formatter,
-                        selector: &SimpleSelector,
-                    ) -> fmt::Result {
-                        let mut universal = true;
-                        if let Some(ref tag_name) = selector.tag_name {
-                            universal = false;
-                            try!(write!(f, "{}", tag_name));
-                        }
-                        if !selector.class.is_empty() {
-                            universal = false;
-                            for class in &selector.class {
-                                try!(write!(f, ".{}", class))
-                            }
-                        }
-                        if let Some(ref id) = selector.id {
-                            universal = false;
-                            try!(write!(f, "#{}", id));
-                        }
-                        if universal {
-                            try!(write!(f, "*"))
-                        }
-                        Ok(())
-                    }
-
-                    match selector {
-                        &Selector::Simple(ref selector) => show_simple(f, selector),
-                        &Selector::Descendant(ref a, ref b) => {
-                            try!(show_simple(f, &*a));
-                            try!(write!(f, " "));
-                            show(f, &*b)
-                        }
-                        &Selector::Child(ref a, ref b) => {
-                            try!(show_simple(f, &*a));
-                            try!(write!(f, " > "));
-                            show(f, &*b)
-                        }
-                    }
-                }
-                try!(show(f, &selector));
+                try!(write!(f, "{}", selector));
 
                 if i != rule.selectors.len() - 1 {
                     try!(write!(f, ", "));
@@ -793,17 +2637,25 @@ impl fmt::Display for Stylesheet {
                         " {}",
                         match value {
                             &Value::Keyword(ref kw) => kw.clone(),
-                            &Value::Length(ref f, Unit::Px) => format!("{}px", f),
-                            &Value::Length(ref f, Unit::Pt) => format!("{}pt", f),
-                            &Value::Length(ref f, Unit::Percent) => format!("{}%", f),
-                            &Value::Length(ref f, Unit::Em) => format!("{}em", f),
+                            Value::Length(f, unit) => format!("{}{}", f, unit),
                             &Value::Num(ref f) => format!("{}", f),
                             &Value::Color(ref color) => {
                                 format!("rgba({}, {}, {}, {})", color.r, color.g, color.b, color.a)
                             }
+                            Value::Calc(calc) => calc.to_string(),
+                            Value::String(string) => format!("{:?}", string),
+                            Value::Url(url) => format!("url({:?})", url),
+                            Value::Comma => ",".to_string(),
+                            Value::Slash => "/".to_string(),
+                            Value::Unparsed(tokens) | Value::PendingSubstitution(_, tokens) => {
+                                tokens.iter().map(|token| token.to_string()).collect()
+                            }
                         }
                     ))
                 }
+                if decl.important {
+                    try!(write!(f, " !important"));
+                }
                 try!(writeln!(f));
             }
             try!(writeln!(f, "}}"));
@@ -829,122 +2681,156 @@ fn test1() {
             unknown: unknown(11)
         }";
     let stylesheet = parse(src.to_string());
-    let rules = vec![
-        Rule {
-            selectors: vec![
-                Selector::Simple(SimpleSelector {
-                    tag_name: None,
-                    id: Some("id".to_string()),
-                    class: HashSet::new(),
-                }),
-                Selector::Simple(SimpleSelector {
-                    tag_name: None,
-                    id: None,
-                    class: {
-                        let mut h = HashSet::new();
-                        h.insert("class".to_string());
-                        h
-                    },
-                }),
-                Selector::Child(
-                    SimpleSelector {
-                        tag_name: Some("p".to_string()),
-                        id: None,
-                        class: HashSet::new(),
-                    },
-                    Box::new(Selector::Simple(SimpleSelector {
-                        tag_name: Some("a".to_string()),
-                        id: None,
-                        class: HashSet::new(),
-                    })),
-                ),
-                Selector::Descendant(
-                    SimpleSelector {
-                        tag_name: Some("div".to_string()),
-                        id: None,
-                        class: HashSet::new(),
-                    },
-                    Box::new(Selector::Simple(SimpleSelector {
-                        tag_name: Some("p".to_string()),
-                        id: None,
-                        class: HashSet::new(),
-                    })),
-                ),
-                Selector::Simple(SimpleSelector {
-                    tag_name: Some("div".to_string()),
+    let rules = vec![Rule {
+        selectors: vec![
+            Selector::Simple(SimpleSelector {
+                tag_name: None,
+                id: Some("id".to_string()),
+                class: HashSet::new(),
+                attributes: vec![],
+                pseudo_classes: vec![],
+                pseudo_element: None,
+            }),
+            Selector::Simple(SimpleSelector {
+                tag_name: None,
+                id: None,
+                class: {
+                    let mut h = HashSet::new();
+                    h.insert("class".to_string());
+                    h
+                },
+                attributes: vec![],
+                pseudo_classes: vec![],
+                pseudo_element: None,
+            }),
+            Selector::Child(
+                SimpleSelector {
+                    tag_name: Some("p".to_string()),
                     id: None,
                     class: HashSet::new(),
-                }),
-                Selector::Simple(SimpleSelector {
-                    tag_name: Some("h1".to_string()),
+                    attributes: vec![],
+                    pseudo_classes: vec![],
+                    pseudo_element: None,
+                },
+                Box::new(Selector::Simple(SimpleSelector {
+                    tag_name: Some("a".to_string()),
                     id: None,
                     class: HashSet::new(),
-                }),
-                Selector::Simple(SimpleSelector {
-                    tag_name: None,
+                    attributes: vec![],
+                    pseudo_classes: vec![],
+                    pseudo_element: None,
+                })),
+            ),
+            Selector::Descendant(
+                SimpleSelector {
+                    tag_name: Some("div".to_string()),
                     id: None,
                     class: HashSet::new(),
-                }),
-            ],
-            declarations: vec![
-                Declaration {
-                    name: "width".to_string(),
-                    values: vec![Value::Length(70.0, Unit::Percent)],
-                },
-                Declaration {
-                    name: "height".to_string(),
-                    values: vec![Value::Length(50.0, Unit::Px)],
-                },
-                Declaration {
-                    name: "font-weight".to_string(),
-                    values: vec![Value::Keyword("bold".to_string())],
-                },
-                Declaration {
-                    name: "z-index".to_string(),
-                    values: vec![Value::Num(2.0)],
+                    attributes: vec![],
+                    pseudo_classes: vec![],
+                    pseudo_element: None,
                 },
-                Declaration {
-                    name: "font-size".to_string(),
-                    values: vec![Value::Length(10.0, Unit::Pt)],
-                },
-                Declaration {
-                    name: "color".to_string(),
-                    values: vec![
-                        Value::Color(Color {
-                            r: 0xff,
-                            g: 0xff,
-                            b: 0xff,
-                            a: 0xff,
-                        }),
-                    ],
-                },
-                Declaration {
-                    name: "background-color".to_string(),
-                    values: vec![
-                        Value::Color(Color {
-                            r: 0x00,
-                            g: 0x33,
-                            b: 0x00,
-                            a: 0xff,
-                        }),
-                    ],
-                },
-                Declaration {
-                    name: "content".to_string(),
-                    values: vec![Value::Num(0.0)],
-                },
-                Declaration {
-                    name: "background-image".to_string(),
-                    values: vec![Value::Num(0.0)],
-                },
-                Declaration {
-                    name: "unknown".to_string(),
-                    values: vec![Value::Keyword("unknown".to_string())],
-                },
-            ],
-        },
-    ];
-    assert_eq!(stylesheet, Stylesheet { rules: rules });
+                Box::new(Selector::Simple(SimpleSelector {
+                    tag_name: Some("p".to_string()),
+                    id: None,
+                    class: HashSet::new(),
+                    attributes: vec![],
+                    pseudo_classes: vec![],
+                    pseudo_element: None,
+                })),
+            ),
+            Selector::Simple(SimpleSelector {
+                tag_name: Some("div".to_string()),
+                id: None,
+                class: HashSet::new(),
+                attributes: vec![],
+                pseudo_classes: vec![],
+                pseudo_element: None,
+            }),
+            Selector::Simple(SimpleSelector {
+                tag_name: Some("h1".to_string()),
+                id: None,
+                class: HashSet::new(),
+                attributes: vec![],
+                pseudo_classes: vec![],
+                pseudo_element: None,
+            }),
+            Selector::Simple(SimpleSelector {
+                tag_name: None,
+                id: None,
+                class: HashSet::new(),
+                attributes: vec![],
+                pseudo_classes: vec![],
+                pseudo_element: None,
+            }),
+        ],
+        declarations: vec![
+            Declaration {
+                name: "width".to_string(),
+                values: vec![Value::Length(70.0, Unit::Percent)],
+                important: false,
+            },
+            Declaration {
+                name: "height".to_string(),
+                values: vec![Value::Length(50.0, Unit::Px)],
+                important: false,
+            },
+            Declaration {
+                name: "font-weight".to_string(),
+                values: vec![Value::Keyword("bold".to_string())],
+                important: false,
+            },
+            Declaration {
+                name: "z-index".to_string(),
+                values: vec![Value::Num(2.0)],
+                important: false,
+            },
+            Declaration {
+                name: "font-size".to_string(),
+                values: vec![Value::Length(10.0, Unit::Pt)],
+                important: false,
+            },
+            Declaration {
+                name: "color".to_string(),
+                values: vec![Value::Color(Color {
+                    r: 0xff,
+                    g: 0xff,
+                    b: 0xff,
+                    a: 0xff,
+                })],
+                important: false,
+            },
+            Declaration {
+                name: "background-color".to_string(),
+                values: vec![Value::Color(Color {
+                    r: 0x00,
+                    g: 0x33,
+                    b: 0x00,
+                    a: 0xff,
+                })],
+                important: false,
+            },
+            Declaration {
+                name: "content".to_string(),
+                values: vec![Value::String("aa".to_string())],
+                important: false,
+            },
+            Declaration {
+                name: "background-image".to_string(),
+                values: vec![Value::Url("aaa".to_string())],
+                important: false,
+            },
+        ],
+        media: vec![],
+    }];
+    assert_eq!(
+        stylesheet,
+        Stylesheet {
+            imports: vec![],
+            font_faces: vec![],
+            rules: rules
+        }
+    );
 }
 
 #[test]
@@ -958,10 +2844,12 @@ fn test2() {
             Declaration {
                 name: "color".to_string(),
                 values: vec![Value::Keyword("black".to_string())],
+                important: false,
             },
             Declaration {
-                name: "background".to_string(),
+                name: "background-color".to_string(),
                 values: vec![Value::Keyword("white".to_string())],
+                important: false,
             },
         ]
     );
@@ -977,25 +2865,23 @@ fn test_rgb_rgba() {
         vec![
             Declaration {
                 name: "color".to_string(),
-                values: vec![
-                    Value::Color(Color {
-                        r: 1,
-                        g: 2,
-                        b: 3,
-                        a: 255,
-                    }),
-                ],
+                values: vec![Value::Color(Color {
+                    r: 1,
+                    g: 2,
+                    b: 3,
+                    a: 255,
+                }),],
+                important: false,
             },
             Declaration {
-                name: "background".to_string(),
-                values: vec![
-                    Value::Color(Color {
-                        r: 250,
-                        g: 1,
-                        b: 250,
-                        a: (255.0 * 0.3) as u8,
-                    }),
-                ],
+                name: "background-color".to_string(),
+                values: vec![Value::Color(Color {
+                    r: 250,
+                    g: 1,
+                    b: 250,
+                    a: (255.0 * 0.3_f64).round() as u8,
+                }),],
+                important: false,
             },
         ]
     );
@@ -1003,8 +2889,9 @@ fn test_rgb_rgba() {
 
 #[test]
 fn test_at_mark_rules() {
-    // At-mark rules are all ignored.
-    parse(
+    // At-mark rules other than @import, @media and @font-face are ignored, and so is an
+    // @font-face without a family and a source.
+    let stylesheet = parse(
         "
           @import 'a.css';
 
@@ -1014,36 +2901,564 @@ fn test_at_mark_rules() {
             } 
           }
 
+          @page { 
+            div { 
+              a: b 
+            } 
+          }
+
           @media { 
             div { 
               a: b 
             } 
           }
         "
+        .to_string(),
+    );
+    let imports: Vec<&str> = stylesheet.imports.iter().map(|i| i.url.as_str()).collect();
+    assert_eq!(imports, ["a.css"]);
+    assert_eq!(stylesheet.font_faces, []);
+    assert_eq!(stylesheet.rules.len(), 1);
+    assert_eq!(stylesheet.rules[0].selectors[0].to_string(), "div");
+    assert_eq!(stylesheet.rules[0].declarations[0].name, "a");
+    assert_eq!(stylesheet.rules[0].media, [MediaQueryList(vec![])]);
+}
+
+#[test]
+fn test_import() {
+    let (stylesheet, errors) = parse_with_errors(
+        "@charset 'utf-8';\n\
+         @import url(a.css);\n\
+         @IMPORT 'b.css' screen, not print;\n\
+         @import url( 'c.css' ) ;\n\
+         @import d.css;\n\
+         p {}\n\
+         @import 'e.css';"
+            .to_string(),
+    );
+    let imports: Vec<String> = stylesheet
+        .imports
+        .iter()
+        .map(|import| {
+            let media: Vec<String> = import.media.0.iter().map(|q| q.to_string()).collect();
+            format!("{} {}", import.url, media.join(","))
+        })
+        .collect();
+    assert_eq!(imports, vec!["a.css ", "b.css screen,not print", "c.css "]);
+    let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "5:9: expected a URL but found 'd'",
+            "7:1: @import must come before all other rules",
+        ]
+    );
+}
+
+#[test]
+fn test_font_face() {
+    let (stylesheet, errors) = parse_with_errors(
+        "@font-face {\n\
+           font-family: 'A B';\n\
+           src: local(A  B), url(a.woff2) format('woff2'), url('a.ttf') format(truetype), 1px;\n\
+           font-weight: 300 700; font-style: italic; font-display: swap;\n\
+         }\n\
+         @media print { @font-face { font-family: C; src: url(c.otf); font-weight: bolder } }\n\
+         @font-face { font-family: a, b; src: url(d.ttf) }\n\
+         @font-face { font-family: D }"
+            .to_string(),
+    );
+    assert_eq!(
+        stylesheet.font_faces,
+        vec![
+            FontFace {
+                family: "A B".to_string(),
+                sources: vec![
+                    FontFaceSource::Local("a b".to_string()),
+                    FontFaceSource::Url("a.ttf".to_string()),
+                ],
+                weight: Value::Num(300.0),
+                style: Value::Keyword("italic".to_string()),
+                media: vec![],
+            },
+            FontFace {
+                family: "c".to_string(),
+                sources: vec![FontFaceSource::Url("c.otf".to_string())],
+                weight: Value::Keyword("normal".to_string()),
+                style: Value::Keyword("normal".to_string()),
+                media: vec![parse_media_query_list("print")],
+            },
+        ]
+    );
+    let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "3:80: invalid source in 'src'",
+            "6:75: invalid value for 'font-weight'",
+            "7:1: @font-face needs a 'font-family'",
+            "7:27: invalid value for 'font-family'",
+            "8:1: @font-face needs a 'src'",
+        ]
+    );
+}
+
+#[test]
+fn test_media_query_list() {
+    let matches = |media: &str| parse_media_query_list(media).matches(800.0, 600.0);
+    assert!(matches(""));
+    assert!(matches(" all "));
+    assert!(matches("SCREEN"));
+    assert!(matches("only screen"));
+    assert!(matches("print, screen"));
+    assert!(matches("not print"));
+    assert!(!matches("print"));
+    assert!(!matches("not all"));
+    assert!(!matches("tv, speech"));
+    // Invalid queries don't match, but the others in the list still can.
+    assert!(!matches("only"));
+    assert!(!matches("screen print"));
+    assert!(!matches(","));
+    assert!(matches("not, (x y), screen"));
+
+    assert!(matches("(min-width: 800px)"));
+    assert!(!matches("(min-width: 801px)"));
+    assert!(matches(
+        "screen and (max-width: 50em) and (min-height: 450pt)"
+    ));
+    assert!(!matches("screen and (max-height: 599px)"));
+    assert!(matches("(width: 800px) and (height: 600px)"));
+    assert!(matches(
+        "(orientation: landscape) and (prefers-color-scheme: light)"
+    ));
+    assert!(!matches(
+        "(orientation: portrait), (prefers-color-scheme: dark)"
+    ));
+    assert!(matches("not screen and (max-width: 600px)"));
+    assert!(matches("(width) and (orientation)"));
+    assert!(!matches("(min-width: 800px) or (max-width: 1px)"));
+    assert!(!matches("(min-width: 50%)"));
+    assert!(!matches("(unknown: 1px)"));
+    assert!(!matches("not (unknown)"));
+
+    assert_eq!(
+        parse_media_query_list(
+            "only screen and (width: 10px), NOT print AND (orientation: portrait)"
+        )
+        .to_string(),
+        "screen and (min-width: 10px) and (max-width: 10px), \
+         not print and (orientation: portrait)"
+    );
+}
+
+#[test]
+fn test_media_rule() {
+    let (stylesheet, errors) = parse_with_errors(
+        "a {}\n\
+         @media screen and (max-width: 600px) { b {} @media print { c {} } }\n\
+         @media (bad) { d {} }\n\
+         e {}"
             .to_string(),
     );
+    let rules: Vec<String> = stylesheet
+        .rules
+        .iter()
+        .map(|rule| {
+            let media: Vec<String> = rule.media.iter().map(|m| m.to_string()).collect();
+            format!("{} [{}]", rule.selectors[0], media.join("; "))
+        })
+        .collect();
+    assert_eq!(
+        rules,
+        vec![
+            "a []",
+            "b [screen and (max-width: 600px)]",
+            "c [screen and (max-width: 600px); print]",
+            "d [not all]",
+            "e []",
+        ]
+    );
+    assert_eq!(
+        errors,
+        vec![ParseError::new(3, 9, "unknown media feature 'bad'")]
+    );
 }
 
 #[test]
 fn test_pseudo() {
-    parse(
+    let stylesheet = parse(
         "
         div:hover { 
         }
         div::first-line {
         }
+        li:nth-child( odd ), li:NTH-LAST-OF-TYPE(-n+ 3), li:nth-child(-2n-1), li:nth-of-type(4),
+        li:nth-child(2n +1) {
+        }
+        :root:not(.a, p#b:first-child) {
+        }
         "
+        .to_string(),
+    );
+    let selectors: Vec<String> = stylesheet
+        .rules
+        .iter()
+        .flat_map(|rule| rule.selectors.iter().map(|s| s.to_string()))
+        .collect();
+    assert_eq!(
+        selectors,
+        vec![
+            "div:hover",
+            "div::first-line",
+            "li:nth-child(2n+1)",
+            "li:nth-last-of-type(-1n+3)",
+            "li:nth-child(-2n-1)",
+            "li:nth-of-type(0n+4)",
+            "li:nth-child(2n+1)",
+            ":root:not(.a, p#b:first-child)",
+        ]
+    );
+    assert_eq!(stylesheet.rules[3].selectors[0].specificity(), (1, 2, 1));
+
+    // B needs a sign after ``An``, but none after a separate sign.
+    let (stylesheet, errors) = parse_with_errors(
+        "a:unknown {} a:nth-child(n+) {} a:nth-child(2 n) {}\n\
+         a:nth-child(2n 1) {} a:nth-child(n- +1) {} a:nth-child(n + -1) {}"
             .to_string(),
     );
+    assert!(stylesheet.rules.is_empty());
+    assert_eq!(errors.len(), 6);
+}
+
+#[test]
+fn test_combinators() {
+    let stylesheet = parse("a>b c+d~e, a +b~ c {}".to_string());
+    let selectors: Vec<String> = stylesheet.rules[0]
+        .selectors
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(selectors, vec!["a > b c + d ~ e", "a + b ~ c"]);
+    assert_eq!(stylesheet.rules[0].selectors[0].specificity(), (0, 0, 5));
+
+    let (stylesheet, errors) = parse_with_errors("a + {} ~ b {} a ~ > b {}".to_string());
+    assert!(stylesheet.rules.is_empty());
+    assert_eq!(errors.len(), 3);
 }
 
 #[test]
 fn test_attribute() {
-    parse(
+    let stylesheet = parse(
         "
         input[type='submit'] {
         }
+        [ lang |= en ], a[href$=\".PDF\" i], [title] {
+        }
         "
+        .to_string(),
+    );
+    let selectors: Vec<String> = stylesheet
+        .rules
+        .iter()
+        .flat_map(|rule| rule.selectors.iter())
+        .map(|selector| match *selector {
+            Selector::Simple(ref simple) => {
+                assert_eq!(selector.specificity().1, simple.attributes.len());
+                simple.attributes.iter().map(|a| a.to_string()).collect()
+            }
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        selectors,
+        vec![
+            "[type=\"submit\"]",
+            "[href$=\".PDF\" i]",
+            "[lang|=\"en\"]",
+            "[title]"
+        ]
+    );
+
+    let (stylesheet, errors) = parse_with_errors("[a=] {} [a=b c] {} [=b] {}".to_string());
+    assert!(stylesheet.rules.is_empty());
+    assert_eq!(errors.len(), 3);
+}
+
+#[test]
+fn test_parse_errors() {
+    let (stylesheet, errors) = parse_with_errors(
+        "a { color: #abcde; width: 1px }\n\
+         b { color red; height: 2px }\n\
+         c { width: url(x); margin: foo(1px); top: 3px }\n\
+         /* c { } "
+            .to_string(),
+    );
+    assert_eq!(
+        errors,
+        vec![
+            ParseError::new(1, 12, "invalid color '#abcde'"),
+            ParseError::new(2, 11, "expected ':' but found 'red'"),
+            ParseError::new(3, 12, "invalid value for 'width'"),
+            ParseError::new(3, 28, "unsupported function 'foo()'"),
+            ParseError::new(4, 1, "unclosed comment"),
+        ]
+    );
+    // The declarations with errors are dropped, the rest are kept.
+    let names: Vec<Vec<&str>> = stylesheet
+        .rules
+        .iter()
+        .map(|rule| rule.declarations.iter().map(|d| d.name.as_str()).collect())
+        .collect();
+    assert_eq!(names, vec![vec!["width"], vec!["height"], vec!["top"]]);
+}
+
+#[test]
+fn test_error_recovery() {
+    let (stylesheet, errors) = parse_with_errors(
+        "@unknown { a { } } b } c { x: 1 }\n\
+         d, e..f { x: 1 }\n\
+         g { x: 1; y: 2 {;} z; @at; w: 4 !important }\n\
+         h { v: ; u: rgb(1, 2, 3"
+            .to_string(),
+    );
+    assert_eq!(
+        stylesheet,
+        parse("g { x: 1; w: 4 !important } h { u: rgb(1, 2, 3) }".to_string())
+    );
+    let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "1:22: expected a selector but found '}'",
+            "2:6: expected a class name but found '.'",
+            "3:16: expected a value but found '{'",
+            "4:8: expected a value but found end of input",
+            "4:24: unexpected end of input; expected '}'",
+            "4:24: unexpected end of input; expected ')'",
+        ]
+    );
+}
+
+#[test]
+fn test_var() {
+    let declarations = parse_attr_style(
+        "--Brand: rgb(1, 2, 3) ; --empty:; margin: 0 VAR(--x) !important; w: var(--a, var(--b, 4px))"
+            .to_string(),
+    );
+    let unparsed: Vec<(&str, String, bool)> = declarations
+        .iter()
+        .map(|decl| match decl.values[..] {
+            [Value::Unparsed(ref tokens)] | [Value::PendingSubstitution(_, ref tokens)] => {
+                let css = tokens.iter().map(|t| t.to_string()).collect();
+                (decl.name.as_str(), css, decl.important)
+            }
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        unparsed,
+        vec![
+            ("--Brand", "rgb(1, 2, 3)".to_string(), false),
+            ("--empty", "".to_string(), false),
+            ("margin-top", "0 VAR(--x)".to_string(), true),
+            ("margin-right", "0 VAR(--x)".to_string(), true),
+            ("margin-bottom", "0 VAR(--x)".to_string(), true),
+            ("margin-left", "0 VAR(--x)".to_string(), true),
+            ("w", "var(--a, var(--b, 4px))".to_string(), false),
+        ]
+    );
+    // Each longhand of a shorthand knows which shorthand to expand.
+    assert!(matches!(
+        declarations[3].values[0],
+        Value::PendingSubstitution(ref shorthand, _) if shorthand == "margin"
+    ));
+
+    let substitute = |tokens: &[Token]| {
+        let mut lookup = |name: &str| match name {
+            "--x" => Some(Tokenizer::new("1px 2px").collect()),
+            _ => None,
+        };
+        substitute_vars(tokens, &mut lookup).and_then(parse_substituted_values)
+    };
+    let values = |i: usize| match declarations[i].values[0] {
+        Value::Unparsed(ref tokens) | Value::PendingSubstitution(_, ref tokens) => {
+            substitute(tokens)
+        }
+        _ => unreachable!(),
+    };
+    let px = |n| Value::Length(n, Unit::Px);
+    assert_eq!(values(2), Some(vec![Value::Num(0.0), px(1.0), px(2.0)]));
+    assert_eq!(values(6), Some(vec![px(4.0)]));
+    assert_eq!(
+        substitute(&Tokenizer::new("var(--y)").collect::<Vec<_>>()),
+        None
+    );
+    assert_eq!(
+        substitute(&Tokenizer::new("var(--y,)").collect::<Vec<_>>()),
+        None
+    );
+    assert_eq!(
+        substitute(&Tokenizer::new("var(x, 1)").collect::<Vec<_>>()),
+        None
+    );
+}
+
+#[test]
+fn test_calc() {
+    let parse = |source: &str| {
+        let (stylesheet, errors) = parse_with_errors(format!("a {{ w: {} }}", source));
+        let values: Vec<String> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .flat_map(|decl| decl.values.iter().map(|v| format!("{:?}", v)))
+            .collect();
+        let errors: Vec<String> = errors.iter().map(|e| e.message.clone()).collect();
+        (values.join(" "), errors.join("; "))
+    };
+    let calc = |source: &str| match parse_value(source.to_string()) {
+        Value::Calc(calc) => calc,
+        value => panic!("{:?}", value),
+    };
+
+    // Absolute units are computed right away.
+    assert_eq!(parse("calc(1px + 2pt * 3)").0, "Length(9.0, Px)");
+    assert_eq!(parse("CALC((1 + 2) / 4)").0, "Num(0.75)");
+    assert_eq!(parse("max(1px, min(2px, 3px))").0, "Length(2.0, Px)");
+    assert_eq!(parse("clamp(1px, 5px, 3px)").0, "Length(3.0, Px)");
+
+    let width = calc("calc(100% - (2 * 1em + 10px) / 2)");
+    assert_eq!(width.to_string(), "calc(100% - (((2 * 1em) + 10px) / 2))");
+    assert_eq!(width.to_px(Some(200.0)), Some(179.0));
+    assert_eq!(width.to_px(None), None);
+    assert_eq!(Value::Calc(width).maybe_percent_to_px(100.0), Some(79.0));
+    let clamp = calc("clamp(10px, 50%, max(20em, 400px))");
+    assert_eq!(clamp.to_string(), "clamp(10px, 50%, max(20em, 400px))");
+    assert_eq!(clamp.to_px(Some(10.0)), Some(10.0));
+    assert_eq!(clamp.to_px(Some(2000.0)), Some(400.0));
+    assert!(calc("calc(50% / 2)").to_px(Some(1.0)).is_some());
+    assert!(!calc("calc(50% / 2)").is_number());
+
+    // Dividing by a zero computed later is clamped.
+    assert_eq!(calc("calc(1% / (1 - 1))").to_px(Some(1.0)), Some(f64::MAX));
+    assert_eq!(calc("calc(0% / (1 - 1))").to_px(Some(1.0)), Some(0.0));
+
+    assert_eq!(parse("calc(1px + 2)").1, "mismatched types in calc()");
+    assert_eq!(parse("calc(1px * 2%)").1, "mismatched types in calc()");
+    assert_eq!(parse("calc(2 / 1px)").1, "mismatched types in calc()");
+    assert_eq!(parse("max(1px, 2)").1, "mismatched types in max()");
+    assert_eq!(parse("calc(1px / 0)").1, "division by zero");
+    assert_eq!(parse("clamp(1px, 2px)").1, "clamp() takes three arguments");
+    assert_eq!(parse("calc(1px +(2px))").1, "expected whitespace after '+'");
+    assert_eq!(parse("calc(1px -2px)").1, "expected ')' but found '-2px'");
+    assert_eq!(parse("calc(1px, 2px)").1, "expected ')' but found ','");
+}
+
+#[test]
+fn test_units() {
+    let (stylesheet, errors) = parse_with_errors(
+        "a { a: 1in 2.54cm 10mm 1pc 3pt; b: 1rem 2EX 3ch 4vw 5vh 6vmin 7vmax; c: 1px 2furlongs }"
             .to_string(),
     );
+    let px: Vec<Option<f64>> = stylesheet.rules[0].declarations[0]
+        .values
+        .iter()
+        .map(|value| value.to_px().map(|px| (px * 1000.0).round() / 1000.0))
+        .collect();
+    assert_eq!(
+        px,
+        [Some(96.0), Some(96.0), Some(37.795), Some(16.0), Some(4.0)]
+    );
+    let units: Vec<String> = stylesheet.rules[0].declarations[1]
+        .values
+        .iter()
+        .map(|value| match *value {
+            Value::Length(len, ref unit) => format!("{}{}", len, unit),
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        units,
+        ["1rem", "2ex", "3ch", "4vw", "5vh", "6vmin", "7vmax"]
+    );
+    // Without an element, font-relative units are relative to the initial font.
+    assert_eq!(Value::Length(2.0, Unit::Em).to_px(), Some(32.0));
+    assert_eq!(Value::Length(2.0, Unit::Em).to_pt(), Some(24.0));
+    assert_eq!(
+        Value::Length(1.0, Unit::Em).maybe_percent_to_px(100.0),
+        Some(16.0)
+    );
+    assert_eq!(stylesheet.rules[0].declarations.len(), 2);
+    assert_eq!(
+        errors,
+        vec![ParseError::new(1, 77, "unknown unit 'furlongs'")]
+    );
+
+    let context = LengthContext {
+        viewport_width: 1000.0,
+        viewport_height: 500.0,
+        root_font_size: 20.0,
+        font_size: 10.0,
+        x_height: 7.0,
+        zero_width: 9.0,
+    };
+    let resolve = |source: &str| parse_value(source.to_string()).resolve_lengths(&context);
+    let px = |px| Value::Length(px, Unit::Px);
+    assert_eq!(resolve("2rem"), px(40.0));
+    assert_eq!(resolve("2ex"), px(14.0));
+    assert_eq!(resolve("2ch"), px(18.0));
+    assert_eq!(resolve("10vw"), px(100.0));
+    assert_eq!(resolve("10vh"), px(50.0));
+    assert_eq!(resolve("10vmin"), px(50.0));
+    assert_eq!(resolve("10vmax"), px(100.0));
+    assert_eq!(resolve("calc(50vw - 1rem)"), px(480.0));
+    assert_eq!(resolve("2em"), px(20.0));
+    match resolve("calc(100% - 1ch)") {
+        Value::Calc(calc) => assert_eq!(calc.to_string(), "calc(100% - 9px)"),
+        value => panic!("{:?}", value),
+    }
+    assert_eq!(
+        resolve("calc(100% - 1ch)").resolve_percentages(50.0),
+        px(41.0)
+    );
+    assert_eq!(
+        parse_value("150%".to_string()).resolve_percentages(10.0),
+        px(15.0)
+    );
+}
+
+#[test]
+fn test_colors() {
+    let color = |source: &str| parse_value(source.to_string()).to_color();
+    let rgba = |r, g, b, a| Some(Color { r, g, b, a });
+
+    assert_eq!(color("RebeccaPurple"), rgba(0x66, 0x33, 0x99, 255));
+    assert_eq!(color("lightgoldenrodyellow"), rgba(0xfa, 0xfa, 0xd2, 255));
+    assert_eq!(color("transparent"), rgba(0, 0, 0, 0));
+    assert_eq!(color("currentcolor"), None);
+    assert_eq!(
+        parse_value("currentColor".to_string()).to_color_or_current(NAVY),
+        Some(NAVY)
+    );
+    assert_eq!(color("#f008"), rgba(255, 0, 0, 0x88));
+    assert_eq!(color("#12345678"), rgba(0x12, 0x34, 0x56, 0x78));
+    assert_eq!(color("rgb(255 0 0 / 50%)"), rgba(255, 0, 0, 128));
+    assert_eq!(color("rgba(100%, 50%, 0%)"), rgba(255, 128, 0, 255));
+    assert_eq!(color("rgb(300, -1, 0, 2)"), rgba(255, 0, 0, 255));
+    assert_eq!(color("hsl(120, 100%, 25%)"), rgba(0, 128, 0, 255));
+    assert_eq!(color("hsla(0.5turn 100 50 / 0.5)"), rgba(0, 255, 255, 128));
+    assert_eq!(color("hsl(-120deg, 100%, 50%)"), rgba(0, 0, 255, 255));
+    assert_eq!(color("hwb(0 0% 0%)"), rgba(255, 0, 0, 255));
+    assert_eq!(color("hwb(90 60% 60%)"), rgba(128, 128, 128, 255));
+
+    let error = |source: &str| {
+        let (_, errors) = parse_with_errors(format!("a {{ color: {} }}", source));
+        errors[0].message.clone()
+    };
+    assert_eq!(error("rgb(1, 2 3)"), "expected ')' but found '3'");
+    assert_eq!(error("rgb(1 2, 3)"), "expected ')' but found ','");
+    assert_eq!(error("rgb(1 2 3 4)"), "expected ')' but found '4'");
+    assert_eq!(error("rgb(1 2)"), "expected 3 or 4 arguments to rgb()");
+    assert_eq!(error("hsl(1, 2, 3%)"), "invalid argument '2' to hsl()");
+    assert_eq!(error("hsl(1px 2% 3%)"), "invalid argument '1px' to hsl()");
+    assert_eq!(error("hwb(1, 2%, 3%)"), "expected ')' but found ','");
 }
@@ -0,0 +1,577 @@
+//! CSS tokenizer.
+//! ref. https://www.w3.org/TR/css-syntax-3/#tokenization
+
+use parse_error::ParseError;
+
+use std::{fmt, mem};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    /// A function name with its opening parenthesis, e.g. ``rgb(``.
+    Function(String),
+    AtKeyword(String),
+    Hash {
+        value: String,
+        /// True if the value would be a valid identifier, i.e. it can be an id selector.
+        is_id: bool,
+    },
+    String(String),
+    BadString,
+    Url(String),
+    BadUrl,
+    Delim(char),
+    Number {
+        value: f64,
+        /// True if the number starts with ``+`` or ``-``, which matters in ``An+B``.
+        has_sign: bool,
+    },
+    Percentage(f64),
+    Dimension(f64, String),
+    Whitespace,
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+pub struct Tokenizer {
+    input: Vec<char>,
+    pos: usize,
+    // Indices of the first character of each line.
+    line_starts: Vec<usize>,
+    errors: Vec<ParseError>,
+    last_token_start: usize,
+}
+
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n'
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_ident_char(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\x00'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+fn is_valid_escape(c1: Option<char>, c2: Option<char>) -> bool {
+    c1 == Some('\\') && c2 != Some('\n')
+}
+
+fn would_start_ident(c1: Option<char>, c2: Option<char>, c3: Option<char>) -> bool {
+    match c1 {
+        Some('-') => c2.is_some_and(|c| is_ident_start(c) || c == '-') || is_valid_escape(c2, c3),
+        Some('\\') => is_valid_escape(c1, c2),
+        Some(c) => is_ident_start(c),
+        None => false,
+    }
+}
+
+fn would_start_number(c1: Option<char>, c2: Option<char>, c3: Option<char>) -> bool {
+    let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+    match c1 {
+        Some('+') | Some('-') => is_digit(c2) || (c2 == Some('.') && is_digit(c3)),
+        Some('.') => is_digit(c2),
+        c => is_digit(c),
+    }
+}
+
+impl Tokenizer {
+    pub fn new(input: &str) -> Tokenizer {
+        // Preprocess the input stream: normalize newlines and replace NULs.
+        let input: Vec<char> = input
+            .replace("\r\n", "\n")
+            .chars()
+            .map(|c| match c {
+                '\r' | '\x0C' => '\n',
+                '\0' => '\u{FFFD}',
+                c => c,
+            })
+            .collect();
+        let line_starts = ::std::iter::once(0)
+            .chain(
+                input
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &c)| c == '\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        Tokenizer {
+            input,
+            pos: 0,
+            line_starts,
+            errors: vec![],
+            last_token_start: 0,
+        }
+    }
+
+    /// Returns the parse errors found so far.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        mem::take(&mut self.errors)
+    }
+
+    /// The line and column where the token last returned by ``next`` starts.
+    pub fn last_token_location(&self) -> (usize, usize) {
+        self.location(self.last_token_start)
+    }
+
+    /// The line and column just past the end of the input.
+    pub fn end_location(&self) -> (usize, usize) {
+        self.location(self.input.len())
+    }
+
+    fn location(&self, index: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= index);
+        (line, index - self.line_starts[line - 1] + 1)
+    }
+
+    // Reports an error at the character consumed last.
+    fn error(&mut self, message: &str) {
+        let (line, column) = self.location(self.pos.saturating_sub(1));
+        self.errors.push(ParseError::new(line, column, message));
+    }
+
+    fn peek(&self, n: usize) -> Option<char> {
+        self.input.get(self.pos + n).cloned()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn consume_comments(&mut self) {
+        while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+            let start = self.pos;
+            self.pos += 2;
+            loop {
+                match self.consume() {
+                    Some('*') if self.peek(0) == Some('/') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    Some(_) => {}
+                    None => {
+                        let (line, column) = self.location(start);
+                        self.errors
+                            .push(ParseError::new(line, column, "unclosed comment"));
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn consume_token(&mut self) -> Option<Token> {
+        self.consume_comments();
+        self.last_token_start = self.pos;
+        let c = self.consume()?;
+        let token = match c {
+            c if is_whitespace(c) => {
+                while self.peek(0).is_some_and(is_whitespace) {
+                    self.pos += 1;
+                }
+                Token::Whitespace
+            }
+            '"' | '\'' => self.consume_string(c),
+            '#' if self.peek(0).is_some_and(is_ident_char)
+                || is_valid_escape(self.peek(0), self.peek(1)) =>
+            {
+                let is_id = would_start_ident(self.peek(0), self.peek(1), self.peek(2));
+                Token::Hash {
+                    value: self.consume_name(),
+                    is_id,
+                }
+            }
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '+' | '-' | '.' if would_start_number(Some(c), self.peek(0), self.peek(1)) => {
+                self.pos -= 1;
+                self.consume_numeric()
+            }
+            '-' if self.peek(0) == Some('-') && self.peek(1) == Some('>') => {
+                self.pos += 2;
+                Token::Cdc
+            }
+            '-' if would_start_ident(Some(c), self.peek(0), self.peek(1)) => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            '<' if self.peek(0) == Some('!')
+                && self.peek(1) == Some('-')
+                && self.peek(2) == Some('-') =>
+            {
+                self.pos += 3;
+                Token::Cdo
+            }
+            '@' if would_start_ident(self.peek(0), self.peek(1), self.peek(2)) => {
+                Token::AtKeyword(self.consume_name())
+            }
+            '\\' if is_valid_escape(Some(c), self.peek(0)) => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            '\\' => {
+                self.error("invalid escape");
+                Token::Delim(c)
+            }
+            c if c.is_ascii_digit() => {
+                self.pos -= 1;
+                self.consume_numeric()
+            }
+            c if is_ident_start(c) => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            c => Token::Delim(c),
+        };
+        Some(token)
+    }
+
+    fn consume_numeric(&mut self) -> Token {
+        let has_sign = matches!(self.peek(0), Some('+') | Some('-'));
+        let number = self.consume_number();
+        if would_start_ident(self.peek(0), self.peek(1), self.peek(2)) {
+            Token::Dimension(number, self.consume_name())
+        } else if self.peek(0) == Some('%') {
+            self.pos += 1;
+            Token::Percentage(number)
+        } else {
+            Token::Number {
+                value: number,
+                has_sign,
+            }
+        }
+    }
+
+    fn consume_number(&mut self) -> f64 {
+        let start = self.pos;
+        let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        if matches!(self.peek(0), Some('+') | Some('-')) {
+            self.pos += 1;
+        }
+        while is_digit(self.peek(0)) {
+            self.pos += 1;
+        }
+        if self.peek(0) == Some('.') && is_digit(self.peek(1)) {
+            self.pos += 1;
+            while is_digit(self.peek(0)) {
+                self.pos += 1;
+            }
+        }
+        if matches!(self.peek(0), Some('e') | Some('E')) {
+            let sign = matches!(self.peek(1), Some('+') | Some('-')) as usize;
+            if is_digit(self.peek(1 + sign)) {
+                self.pos += 1 + sign;
+                while is_digit(self.peek(0)) {
+                    self.pos += 1;
+                }
+            }
+        }
+        let repr: String = self.input[start..self.pos].iter().collect();
+        repr.parse().unwrap_or(0.0)
+    }
+
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_name();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.pos += 1;
+        if name.eq_ignore_ascii_case("url") {
+            while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace)
+            {
+                self.pos += 1;
+            }
+            let quoted = |c: Option<char>| c == Some('"') || c == Some('\'');
+            let next = if self.peek(0).is_some_and(is_whitespace) {
+                self.peek(1)
+            } else {
+                self.peek(0)
+            };
+            if !quoted(next) {
+                return self.consume_url();
+            }
+        }
+        Token::Function(name)
+    }
+
+    fn consume_string(&mut self, quote: char) -> Token {
+        let mut value = String::new();
+        loop {
+            match self.consume() {
+                Some(c) if c == quote => return Token::String(value),
+                None => {
+                    self.error("unexpected end of input in string");
+                    return Token::String(value);
+                }
+                Some('\n') => {
+                    self.error("newline in string");
+                    self.pos -= 1;
+                    return Token::BadString;
+                }
+                Some('\\') => match self.peek(0) {
+                    None => {}
+                    Some('\n') => self.pos += 1,
+                    Some(_) => value.push(self.consume_escape()),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    // The "url(" has been consumed.
+    fn consume_url(&mut self) -> Token {
+        let mut value = String::new();
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        loop {
+            match self.consume() {
+                Some(')') => return Token::Url(value),
+                None => {
+                    self.error("unexpected end of input in url");
+                    return Token::Url(value);
+                }
+                Some(c) if is_whitespace(c) => {
+                    while self.peek(0).is_some_and(is_whitespace) {
+                        self.pos += 1;
+                    }
+                    match self.consume() {
+                        Some(')') => return Token::Url(value),
+                        None => {
+                            self.error("unexpected end of input in url");
+                            return Token::Url(value);
+                        }
+                        Some(_) => {
+                            self.error("whitespace in url");
+                            return self.consume_bad_url();
+                        }
+                    }
+                }
+                Some(c) if c == '"' || c == '\'' || c == '(' || is_non_printable(c) => {
+                    self.error("unexpected character in url");
+                    return self.consume_bad_url();
+                }
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    value.push(self.consume_escape())
+                }
+                Some('\\') => {
+                    self.error("invalid escape");
+                    return self.consume_bad_url();
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn consume_bad_url(&mut self) -> Token {
+        loop {
+            match self.consume() {
+                Some(')') | None => return Token::BadUrl,
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    self.consume_escape();
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    // The backslash has been consumed.
+    fn consume_escape(&mut self) -> char {
+        let c = match self.consume() {
+            Some(c) => c,
+            None => {
+                self.error("unexpected end of input in escape");
+                return '\u{FFFD}';
+            }
+        };
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+        let mut hex = c.to_string();
+        while hex.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.extend(self.consume());
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .filter(|&n| n != 0)
+            .and_then(::std::char::from_u32)
+            .unwrap_or('\u{FFFD}')
+    }
+
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_ident_char(c) => {
+                    self.pos += 1;
+                    name.push(c);
+                }
+                Some('\\') if is_valid_escape(self.peek(0), self.peek(1)) => {
+                    self.pos += 1;
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+}
+
+impl Iterator for Tokenizer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.consume_token()
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Ident(ref name) => write!(f, "{}", name),
+            Token::Function(ref name) => write!(f, "{}(", name),
+            Token::AtKeyword(ref name) => write!(f, "@{}", name),
+            Token::Hash { ref value, .. } => write!(f, "#{}", value),
+            Token::String(ref value) => write!(f, "\"{}\"", value),
+            Token::BadString => write!(f, "bad string"),
+            Token::Url(ref value) => write!(f, "url({})", value),
+            Token::BadUrl => write!(f, "bad url"),
+            Token::Delim(c) => write!(f, "{}", c),
+            Token::Number { value, .. } => write!(f, "{}", value),
+            Token::Percentage(n) => write!(f, "{}%", n),
+            Token::Dimension(n, ref unit) => write!(f, "{}{}", n, unit),
+            Token::Whitespace => write!(f, " "),
+            Token::Cdo => write!(f, "<!--"),
+            Token::Cdc => write!(f, "-->"),
+            Token::Colon => write!(f, ":"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Comma => write!(f, ","),
+            Token::OpenSquare => write!(f, "["),
+            Token::CloseSquare => write!(f, "]"),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+            Token::OpenCurly => write!(f, "{{"),
+            Token::CloseCurly => write!(f, "}}"),
+        }
+    }
+}
+
+#[test]
+fn test_tokens() {
+    let tokens: Vec<Token> = Tokenizer::new(
+        "@media a#b.c>d:hover{width:-1.5e1px;w:50%/*x*/ 2 url( a.png ) rgb(1,+.5) 'x\\41' #1f}",
+    )
+    .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::AtKeyword("media".to_string()),
+            Token::Whitespace,
+            Token::Ident("a".to_string()),
+            Token::Hash {
+                value: "b".to_string(),
+                is_id: true,
+            },
+            Token::Delim('.'),
+            Token::Ident("c".to_string()),
+            Token::Delim('>'),
+            Token::Ident("d".to_string()),
+            Token::Colon,
+            Token::Ident("hover".to_string()),
+            Token::OpenCurly,
+            Token::Ident("width".to_string()),
+            Token::Colon,
+            Token::Dimension(-15.0, "px".to_string()),
+            Token::Semicolon,
+            Token::Ident("w".to_string()),
+            Token::Colon,
+            Token::Percentage(50.0),
+            Token::Whitespace,
+            Token::Number {
+                value: 2.0,
+                has_sign: false,
+            },
+            Token::Whitespace,
+            Token::Url("a.png".to_string()),
+            Token::Whitespace,
+            Token::Function("rgb".to_string()),
+            Token::Number {
+                value: 1.0,
+                has_sign: false,
+            },
+            Token::Comma,
+            Token::Number {
+                value: 0.5,
+                has_sign: true,
+            },
+            Token::CloseParen,
+            Token::Whitespace,
+            Token::String("xA".to_string()),
+            Token::Whitespace,
+            Token::Hash {
+                value: "1f".to_string(),
+                is_id: false,
+            },
+            Token::CloseCurly,
+        ]
+    );
+}
+
+#[test]
+fn test_bad_tokens() {
+    let mut tokenizer = Tokenizer::new("a: 'b\nurl(c d) /* e");
+    let tokens: Vec<Token> = tokenizer.by_ref().collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Ident("a".to_string()),
+            Token::Colon,
+            Token::Whitespace,
+            Token::BadString,
+            Token::Whitespace,
+            Token::BadUrl,
+            Token::Whitespace,
+        ]
+    );
+    let errors: Vec<String> = tokenizer
+        .take_errors()
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            "1:6: newline in string",
+            "2:7: whitespace in url",
+            "2:10: unclosed comment"
+        ]
+    );
+}
@@ -5,6 +5,8 @@ use std::cell::RefCell;
 
 pub fn default_style() -> Stylesheet {
     Stylesheet {
+        imports: vec![],
+        font_faces: vec![],
         rules: DEFAULT_RULES.with(|default_rules| default_rules.borrow().clone()),
     }
 }
@@ -27,19 +29,22 @@ thread_local!(
         rule_style(&mut rules);
         rule_title(&mut rules);
         rule_script(&mut rules);
+        rule_head(&mut rules);
         RefCell::new(rules)
     }
 );
 
 macro_rules! tag_name { ($name:expr) => {
     Selector::Simple(SimpleSelector {
-        tag_name: Some($name.to_string()), id: None, class: HashSet::new() })
+        tag_name: Some($name.to_string()), id: None, class: HashSet::new(), attributes: vec![],
+        pseudo_classes: vec![], pseudo_element: None })
 }}
 
 macro_rules! decl { ($name:expr, $( $val:expr ),*) => {
     Declaration {
         name: $name.to_string(),
         values: vec![$($val)*],
+        important: false,
     }
 }}
 
@@ -55,9 +60,13 @@ fn rule_universal(rules: &mut Vec<Rule>) {
                 tag_name: None,
                 id: None,
                 class: HashSet::new(),
+                attributes: vec![],
+                pseudo_classes: vec![],
+                pseudo_element: None,
             }),
         ],
         declarations: vec![decl!("display", keyword!("block"))],
+        media: vec![],
     });
 }
 
@@ -68,8 +77,9 @@ fn rule_html(rules: &mut Vec<Rule>) {
             // decl!("width", keyword!("auto")),
             // decl!("padding", len_px!(0f64)),
             // decl!("margin", len_px!(0f64)),
-            decl!("background", color!(WHITE)),
+            decl!("background-color", color!(WHITE)),
         ],
+        media: vec![],
     });
 }
 
@@ -87,6 +97,7 @@ fn rule_span(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("span")],
         declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
     });
 }
 
@@ -96,8 +107,12 @@ fn rule_h1(rules: &mut Vec<Rule>) {
         declarations: vec![
             decl!("font-size", len_px!(30f64)),
             decl!("font-weight", keyword!("bold")),
-            decl!("padding", len_px!(10f64)),
+            decl!("padding-top", len_px!(10f64)),
+            decl!("padding-right", len_px!(10f64)),
+            decl!("padding-bottom", len_px!(10f64)),
+            decl!("padding-left", len_px!(10f64)),
         ],
+        media: vec![],
     });
 }
 
@@ -107,8 +122,12 @@ fn rule_h2(rules: &mut Vec<Rule>) {
         declarations: vec![
             decl!("font-size", len_px!(24f64)),
             decl!("font-weight", keyword!("bold")),
-            decl!("padding", len_px!(10f64)),
+            decl!("padding-top", len_px!(10f64)),
+            decl!("padding-right", len_px!(10f64)),
+            decl!("padding-bottom", len_px!(10f64)),
+            decl!("padding-left", len_px!(10f64)),
         ],
+        media: vec![],
     });
 }
 
@@ -118,8 +137,12 @@ fn rule_h3(rules: &mut Vec<Rule>) {
         declarations: vec![
             decl!("font-size", len_px!(19f64)),
             decl!("font-weight", keyword!("bold")),
-            decl!("padding", len_px!(10f64)),
+            decl!("padding-top", len_px!(10f64)),
+            decl!("padding-right", len_px!(10f64)),
+            decl!("padding-bottom", len_px!(10f64)),
+            decl!("padding-left", len_px!(10f64)),
         ],
+        media: vec![],
     });
 }
 
@@ -139,6 +162,7 @@ fn rule_a(rules: &mut Vec<Rule>) {
             ),
             decl!("text-decoration", keyword!("underline")),
         ],
+        media: vec![],
     });
 }
 
@@ -146,6 +170,7 @@ fn rule_img(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("img")],
         declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
     });
 }
 
@@ -156,6 +181,7 @@ fn rule_b(rules: &mut Vec<Rule>) {
             decl!("display", keyword!("inline")),
             decl!("font-weight", keyword!("bold")),
         ],
+        media: vec![],
     });
 }
 
@@ -166,6 +192,7 @@ fn rule_i(rules: &mut Vec<Rule>) {
             decl!("display", keyword!("inline")),
             decl!("font-style", keyword!("italic")),
         ],
+        media: vec![],
     });
 }
 
@@ -173,6 +200,7 @@ fn rule_button(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("button")],
         declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
     });
 }
 
@@ -180,6 +208,7 @@ fn rule_style(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("style")],
         declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
     });
 }
 
@@ -187,6 +216,7 @@ fn rule_title(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("title")],
         declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
     });
 }
 
@@ -194,5 +224,14 @@ fn rule_script(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("script")],
         declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
+    });
+}
+
+fn rule_head(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("head"), tag_name!("link"), tag_name!("meta")],
+        declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
     });
 }
@@ -1,5 +1,4 @@
 use std::collections::{HashMap, HashSet};
-use std::path::{Path, PathBuf};
 use std::{fmt, iter};
 use css;
 
@@ -28,6 +27,22 @@ pub enum LayoutType {
     Button,
 }
 
+/// A stylesheet that a document uses, see ``Node::find_stylesheets``.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StylesheetElement {
+    pub source: StylesheetSource,
+    /// The ``media`` attribute.
+    pub media: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StylesheetSource {
+    /// The URL of a linked stylesheet.
+    Link(String),
+    /// The contents of a ``<style>`` element.
+    Style(String),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ElementData {
     pub tag_name: String,
@@ -74,6 +89,13 @@ impl Node {
             NodeType::Text(_) => LayoutType::Text,
         }
     }
+    pub fn tag_name(&self) -> Option<&str> {
+        match self.data {
+            NodeType::Element(ElementData { ref tag_name, .. }) => Some(tag_name.as_str()),
+            NodeType::Text(_) => None,
+        }
+    }
+
     pub fn is_inline(&self) -> bool {
         match self.data {
             NodeType::Element(ElementData { ref tag_name, .. }) => match tag_name.as_str() {
@@ -114,33 +136,63 @@ impl Node {
         }
     }
 
-    pub fn find_stylesheet_path(&self) -> Option<PathBuf> {
-        let mut buf = vec![];
-        self.find_nodes_by_tag_name("link", &mut buf);
-        for &Node { ref data, .. } in buf {
-            match data {
-                &NodeType::Element(ElementData { ref attrs, .. }) => {
-                    if let Some(name) = attrs.get("rel") {
-                        println!(">>{}", name);
-                        if name == "stylesheet" {
-                            return attrs
-                                .get("href")
-                                .and_then(|filename| Some(Path::new(filename).to_path_buf()));
-                        }
+    /// Returns the stylesheets of ``<link rel="stylesheet">`` and ``<style>`` elements in
+    /// document order. Disabled links, alternate stylesheets and sheets of a type other than
+    /// ``text/css`` are left out.
+    pub fn find_stylesheets(&self) -> Vec<StylesheetElement> {
+        let mut stylesheets = vec![];
+        self.collect_stylesheets(&mut stylesheets);
+        stylesheets
+    }
+
+    fn collect_stylesheets(&self, stylesheets: &mut Vec<StylesheetElement>) {
+        let elem = match self.data {
+            NodeType::Element(ref elem) => elem,
+            NodeType::Text(_) => return,
+        };
+        let is_css = elem
+            .attrs
+            .get("type")
+            .is_none_or(|ty| ty.trim().eq_ignore_ascii_case("text/css"));
+        let media = elem.attrs.get("media").cloned();
+        match elem.tag_name.as_str() {
+            "link" if is_css && !elem.attrs.contains_key("disabled") => {
+                let rel = elem.attrs.get("rel").map_or("", |rel| rel.as_str());
+                let has_rel = |name: &str| {
+                    rel.split_ascii_whitespace()
+                        .any(|token| token.eq_ignore_ascii_case(name))
+                };
+                if let Some(href) = elem.attrs.get("href") {
+                    if has_rel("stylesheet") && !has_rel("alternate") {
+                        stylesheets.push(StylesheetElement {
+                            source: StylesheetSource::Link(href.clone()),
+                            media,
+                        });
                     }
                 }
-                &NodeType::Text(_) => {}
+            }
+            "style" if is_css => {
+                let css = self
+                    .children
+                    .iter()
+                    .filter_map(|child| match child.data {
+                        NodeType::Text(ref text) => Some(text.as_str()),
+                        NodeType::Element(_) => None,
+                    })
+                    .collect();
+                stylesheets.push(StylesheetElement {
+                    source: StylesheetSource::Style(css),
+                    media,
+                });
+            }
+            // Their contents are not part of the document.
+            "template" | "noscript" => {}
+            _ => {
+                for child in &self.children {
+                    child.collect_stylesheets(stylesheets);
+                }
             }
         }
-        None
-    }
-
-    pub fn find_stylesheet_in_style_tag(&self) -> Option<String> {
-        self.find_first_node_by_tag_name("style")
-            .and_then(|&Node { ref children, .. }| match children[0].data {
-                NodeType::Element(_) => None,
-                NodeType::Text(ref s) => Some(s.clone()),
-            })
     }
 
     pub fn image_url(&self) -> Option<&String> {
@@ -247,3 +299,38 @@ fn test_id() {
         None
     )
 }
+
+#[test]
+fn test_find_stylesheets() {
+    use html;
+    use std::path::Path;
+
+    let dom = html::parse(
+        "<link rel=stylesheet href=a.css>\
+         <style media=print>p {}</style>\
+         <link rel='Alternate stylesheet' href=b.css>\
+         <link rel=stylesheet href=c.css disabled>\
+         <link rel=icon href=d.png>\
+         <body><style type=text/plain>p {}</style>\
+         <link rel='preload stylesheet' href=e.css media='screen'><style>q {}</style>"
+            .to_string(),
+        Path::new("/x/a.html").to_path_buf(),
+    );
+    let link = |href: &str, media: Option<&str>| StylesheetElement {
+        source: StylesheetSource::Link(href.to_string()),
+        media: media.map(|m| m.to_string()),
+    };
+    let style = |css: &str, media: Option<&str>| StylesheetElement {
+        source: StylesheetSource::Style(css.to_string()),
+        media: media.map(|m| m.to_string()),
+    };
+    assert_eq!(
+        dom.find_stylesheets(),
+        vec![
+            link("/x/a.css", None),
+            style("p {}", Some("print")),
+            link("/x/e.css", Some("screen")),
+            style("q {}", None),
+        ]
+    );
+}
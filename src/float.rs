@@ -255,8 +255,7 @@ impl LayoutBox {
 
         let d = &mut self.dimensions;
 
-        if let Some(x) = self.property.value("width") {
-            let width = x[0].clone();
+        if let Some(width) = self.property.width() {
             if let Some(width) = width.maybe_percent_to_px(cb_width) {
                 d.content.width = Au::from_f64_px(width)
             }
@@ -1,11 +1,19 @@
+use css::{px2pt, MediaQueryList};
 
-
-use css::px2pt;
-
-use std::cell::RefCell;
-use gtk::pango::{ Layout,FontDescription};
-use gtk::{cairo,pango};
 use app_units::Au;
+use gtk::glib::Cast;
+use gtk::pango::prelude::{FontFamilyExt, FontMapExt};
+use gtk::pango::{FontDescription, Layout};
+use gtk::{cairo, pango};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::Read;
+use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
 
 thread_local!(
     pub static PANGO_LAYOUT: RefCell<Layout> = {
@@ -14,77 +22,224 @@ thread_local!(
         let layout = Layout::new(&ctx);
         RefCell::new(layout)
     };
-    pub static FONT_DESC: RefCell<FontDescription> = {
-        RefCell::new(FontDescription::from_string("sans-serif normal 16"))
-    }
+    // The font ``PANGO_LAYOUT`` measures text in.
+    static LAYOUT_FONT: RefCell<Option<Font>> = const { RefCell::new(None) };
+    // See ``Font::ex_and_ch``.
+    static UNIT_SIZES: RefCell<HashMap<Font, (f64, f64)>> = RefCell::new(HashMap::new());
+    // The families of ``@font-face`` rules by lowercase name, see ``add_font_file``.
+    static FONT_FACES: RefCell<HashMap<String, Vec<FontFace>>> = RefCell::new(HashMap::new());
 );
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+// The parts of fontconfig that pango doesn't expose.
+#[link(name = "fontconfig")]
+extern "C" {
+    fn FcConfigAppFontAddFile(config: *mut c_void, file: *const c_char) -> c_int;
+    fn FcFreeTypeQuery(
+        file: *const c_char,
+        id: c_int,
+        blanks: *mut c_void,
+        count: *mut c_int,
+    ) -> *mut c_void;
+    fn FcPatternGetString(
+        pattern: *mut c_void,
+        object: *const c_char,
+        n: c_int,
+        value: *mut *const c_char,
+    ) -> c_int;
+    fn FcPatternDestroy(pattern: *mut c_void);
+}
+
+const FC_FAMILY: &[u8] = b"family\0";
+
+// A face of a family that an ``@font-face`` rule defines.
+struct FontFace {
+    // The family fontconfig knows the font by.
+    family: String,
+    weight: FontWeight,
+    slant: FontSlant,
+    media: Vec<MediaQueryList>,
+    // Whether ``media`` matched the viewport last, see ``match_font_face_media``.
+    matches: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Font {
+    /// The names of the families to use, in order of preference. Generic families such as
+    /// ``serif`` are left to fontconfig.
+    pub families: Vec<String>,
     pub size: Au,
     pub weight: FontWeight,
     pub slant: FontSlant,
+    pub stretch: FontStretch,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum FontWeight {
-    Normal,
-    Bold,
-}
+/// A weight from 1 to 1000.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FontWeight(pub u16);
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum FontSlant {
     Normal,
     Italic,
+    Oblique,
 }
 
-impl Font {
-    pub fn new(size: Au, weight: FontWeight, slant: FontSlant) -> Font {
-        FONT_DESC.with(|font_desc| {
-            let mut font_desc = font_desc.borrow_mut();
-            font_desc.set_size(pango::units_from_double(px2pt(size.to_f64_px())));
-            font_desc.set_style(slant.to_pango_font_slant());
-            font_desc.set_weight(weight.to_pango_font_weight());
-            PANGO_LAYOUT.with(|layout| {
-                layout.borrow_mut().set_font_description(Some(&*font_desc));
-            })
-        });
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontStretch {
+    UltraCondensed,
+    ExtraCondensed,
+    Condensed,
+    SemiCondensed,
+    Normal,
+    SemiExpanded,
+    Expanded,
+    ExtraExpanded,
+    UltraExpanded,
+}
 
-        Font {
-            size: size,
-            weight: weight,
-            slant: slant,
-        }
+impl FontWeight {
+    pub const NORMAL: FontWeight = FontWeight(400);
+    pub const BOLD: FontWeight = FontWeight(700);
+}
+
+impl FontStretch {
+    /// The stretch closest to a ``font-stretch`` percentage.
+    pub fn from_percentage(percentage: f64) -> FontStretch {
+        const STRETCHES: [(f64, FontStretch); 9] = [
+            (50.0, FontStretch::UltraCondensed),
+            (62.5, FontStretch::ExtraCondensed),
+            (75.0, FontStretch::Condensed),
+            (87.5, FontStretch::SemiCondensed),
+            (100.0, FontStretch::Normal),
+            (112.5, FontStretch::SemiExpanded),
+            (125.0, FontStretch::Expanded),
+            (150.0, FontStretch::ExtraExpanded),
+            (200.0, FontStretch::UltraExpanded),
+        ];
+        STRETCHES
+            .iter()
+            .min_by(|a, b| {
+                (a.0 - percentage)
+                    .abs()
+                    .partial_cmp(&(b.0 - percentage).abs())
+                    .unwrap()
+            })
+            .unwrap()
+            .1
     }
+}
 
+impl Font {
     pub fn new_empty() -> Font {
         Font {
+            families: vec![],
             size: Au(0),
-            weight: FontWeight::Normal,
+            weight: FontWeight::NORMAL,
             slant: FontSlant::Normal,
+            stretch: FontStretch::Normal,
         }
     }
 
+    /// The font as pango describes it, falling back to ``sans-serif`` when none of the families
+    /// is installed.
+    pub fn description(&self) -> FontDescription {
+        let mut families: Vec<String> = self
+            .families
+            .iter()
+            .flat_map(|family| match family.as_str() {
+                // Not every fontconfig has an alias for it.
+                "system-ui" => vec!["sans-serif".to_string()],
+                family => self.font_face_families(family),
+            })
+            // Pango separates the families with commas.
+            .filter(|family| !family.contains(','))
+            .collect();
+        families.push("sans-serif".to_string());
+        let mut font_desc = FontDescription::new();
+        font_desc.set_family(&families.join(","));
+        font_desc.set_size(pango::units_from_double(px2pt(self.size.to_f64_px())));
+        font_desc.set_weight(self.weight.to_pango_font_weight());
+        font_desc.set_style(self.slant.to_pango_font_slant());
+        font_desc.set_stretch(self.stretch.to_pango_stretch());
+        font_desc
+    }
+
+    // The families fontconfig knows the ``@font-face`` family ``family`` by, those whose weight
+    // and slant are closest to this font's first. Other families, and those whose faces are all
+    // in non-matching ``@media`` rules, are left as they are.
+    fn font_face_families(&self, family: &str) -> Vec<String> {
+        FONT_FACES.with(|font_faces| {
+            let font_faces = font_faces.borrow();
+            let mut faces: Vec<&FontFace> = font_faces
+                .get(&family.to_lowercase())
+                .into_iter()
+                .flatten()
+                .filter(|face| face.matches)
+                .collect();
+            if faces.is_empty() {
+                return vec![family.to_string()];
+            }
+            faces.sort_by_key(|face| {
+                (
+                    face.slant != self.slant,
+                    (face.weight.0 as i32 - self.weight.0 as i32).abs(),
+                )
+            });
+            faces.iter().map(|face| face.family.clone()).collect()
+        })
+    }
+
+    // Runs ``f`` on ``PANGO_LAYOUT`` set to this font.
+    fn with_layout<T, F: FnOnce(&Layout) -> T>(&self, f: F) -> T {
+        LAYOUT_FONT.with(|layout_font| {
+            let mut layout_font = layout_font.borrow_mut();
+            PANGO_LAYOUT.with(|layout| {
+                let layout = layout.borrow_mut();
+                if layout_font.as_ref() != Some(self) {
+                    layout.set_font_description(Some(&self.description()));
+                    *layout_font = Some(self.clone());
+                }
+                f(&layout)
+            })
+        })
+    }
+
     pub fn text_width(&self, text: &str) -> f64 {
-        PANGO_LAYOUT.with(|layout| {
-            let layout = layout.borrow_mut();
+        self.with_layout(|layout| {
             layout.set_text(text);
             pango::units_to_double(layout.size().0)
         })
     }
 
+    /// Returns the sizes of the ``ex`` and ``ch`` units in px: the height of "x" and the advance
+    /// of "0". Where the font can't tell, either is half the font size.
+    pub fn ex_and_ch(&self) -> (f64, f64) {
+        if let Some(sizes) = UNIT_SIZES.with(|sizes| sizes.borrow().get(self).cloned()) {
+            return sizes;
+        }
+
+        let x_height = self.with_layout(|layout| {
+            layout.set_text("x");
+            pango::units_to_double(layout.extents().0.height())
+        });
+        let zero_width = self.text_width("0");
+        let half = self.size.to_f64_px() / 2.0;
+        let or_half = |len: f64| if len > 0.0 { len } else { half };
+        let sizes = (or_half(x_height), or_half(zero_width));
+        UNIT_SIZES.with(|unit_sizes| unit_sizes.borrow_mut().insert(self.clone(), sizes));
+        sizes
+    }
+
     pub fn get_ascent_descent(&self) -> (Au, Au) {
-        FONT_DESC.with(|font_desc| {
-            let font_desc = font_desc.borrow();
-            PANGO_LAYOUT.with(|layout| {
-                let ctx = layout.borrow_mut().context();
-                let metrics =
-                    ctx.metrics(Some(&*font_desc), Some(&pango::Language::from_string("")));
-                (
-                    Au::from_f64_px(pango::units_to_double(metrics.ascent()) as f64),
-                    Au::from_f64_px(pango::units_to_double(metrics.descent()) as f64),
-                )
-            })
+        self.with_layout(|layout| {
+            let metrics = layout.context().metrics(
+                layout.font_description().as_ref(),
+                Some(&pango::Language::from_string("")),
+            );
+            (
+                Au::from_f64_px(pango::units_to_double(metrics.ascent()) as f64),
+                Au::from_f64_px(pango::units_to_double(metrics.descent()) as f64),
+            )
         })
     }
 
@@ -93,8 +248,7 @@ impl Font {
             return (0, 0.0);
         }
 
-        PANGO_LAYOUT.with(|layout| {
-            let layout = layout.borrow_mut();
+        self.with_layout(|layout| {
             // TODO: Inefficient implementation!
             let mut text_width = 0.0;
             let mut last_splittable_pos = None;
@@ -129,3 +283,195 @@ impl Font {
         })
     }
 }
+
+/// Makes the font file at ``path`` available as the face of ``family`` with ``weight`` and
+/// ``slant``, for ``@font-face``, where ``media`` matches. Returns false if it can't be read or is
+/// a WOFF file. Call ``reload_fonts`` once the fonts are added.
+pub fn add_font_file(
+    family: &str,
+    path: &Path,
+    weight: FontWeight,
+    slant: FontSlant,
+    media: &[MediaQueryList],
+) -> bool {
+    // fontconfig reads WOFF, but pango can't render it.
+    let mut signature = [0; 4];
+    let is_woff = fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .map_or(true, |_| &signature == b"wOFF" || &signature == b"wOF2");
+    if is_woff {
+        return false;
+    }
+    let file = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    // fontconfig knows the font by the family in the file, which needn't be ``family``.
+    let font_family = match font_file_family(&file) {
+        Some(font_family) => font_family,
+        None => return false,
+    };
+    if unsafe { FcConfigAppFontAddFile(ptr::null_mut(), file.as_ptr()) } == 0 {
+        return false;
+    }
+    add_font_face(family, font_family, weight, slant, media);
+    true
+}
+
+// The family of the first font in a font file, or None if fontconfig can't read it.
+fn font_file_family(file: &CStr) -> Option<String> {
+    unsafe {
+        let mut count = 0;
+        let pattern = FcFreeTypeQuery(file.as_ptr(), 0, ptr::null_mut(), &mut count);
+        if pattern.is_null() {
+            return None;
+        }
+        let mut family = ptr::null();
+        let result =
+            FcPatternGetString(pattern, FC_FAMILY.as_ptr() as *const c_char, 0, &mut family);
+        let family = match result {
+            0 => Some(CStr::from_ptr(family).to_string_lossy().into_owned()),
+            _ => None,
+        };
+        FcPatternDestroy(pattern);
+        family
+    }
+}
+
+/// Like ``add_font_file``, for ``local()``: makes the installed family ``name`` available as
+/// ``family``. Returns false if it isn't installed.
+pub fn add_local_font(
+    family: &str,
+    name: &str,
+    weight: FontWeight,
+    slant: FontSlant,
+    media: &[MediaQueryList],
+) -> bool {
+    let installed = pangocairo::FontMap::default()
+        .list_families()
+        .iter()
+        .any(|installed| installed.name().eq_ignore_ascii_case(name));
+    if installed {
+        add_font_face(family, name.to_string(), weight, slant, media);
+    }
+    installed
+}
+
+fn add_font_face(
+    family: &str,
+    font_family: String,
+    weight: FontWeight,
+    slant: FontSlant,
+    media: &[MediaQueryList],
+) {
+    let face = FontFace {
+        family: font_family,
+        weight,
+        slant,
+        media: media.to_vec(),
+        matches: media.is_empty(),
+    };
+    FONT_FACES.with(|font_faces| {
+        font_faces
+            .borrow_mut()
+            .entry(family.to_lowercase())
+            .or_default()
+            .push(face)
+    });
+}
+
+/// Forgets the ``@font-face`` families, e.g. of the previous page. The font files stay known to
+/// fontconfig.
+pub fn clear_font_faces() {
+    FONT_FACES.with(|font_faces| font_faces.borrow_mut().clear());
+}
+
+/// Evaluates the media queries of the ``@font-face`` families for a viewport of ``width`` x
+/// ``height`` px. Returns true if that changed which faces are used, and so how text measures.
+pub fn match_font_face_media(width: f64, height: f64) -> bool {
+    let changed = FONT_FACES.with(|font_faces| {
+        let mut changed = false;
+        for face in font_faces.borrow_mut().values_mut().flatten() {
+            let matches = face.media.iter().all(|media| media.matches(width, height));
+            changed |= face.matches != matches;
+            face.matches = matches;
+        }
+        changed
+    });
+    if changed {
+        LAYOUT_FONT.with(|layout_font| *layout_font.borrow_mut() = None);
+        UNIT_SIZES.with(|unit_sizes| unit_sizes.borrow_mut().clear());
+    }
+    changed
+}
+
+/// Makes measurement and painting use the fonts added so far. Pango reads the fonts fontconfig
+/// knows only once per font map, so this switches to a new one.
+pub fn reload_fonts() {
+    let font_map = pangocairo::FontMap::new();
+    pangocairo::FontMap::set_default(font_map.downcast_ref::<pangocairo::FontMap>());
+    PANGO_LAYOUT.with(|layout| {
+        let layout = layout.borrow();
+        layout.context().set_font_map(Some(&font_map));
+        layout.context_changed();
+    });
+    LAYOUT_FONT.with(|layout_font| *layout_font.borrow_mut() = None);
+    UNIT_SIZES.with(|unit_sizes| unit_sizes.borrow_mut().clear());
+}
+
+#[test]
+fn test_font_file() {
+    use css::parse_media_query_list;
+
+    // Every printable ASCII character of the font is a 1em square.
+    let path = Path::new("reftests/fonts/square.ttf");
+    let font = Font {
+        families: vec!["Square".to_string(), "Wide Square".to_string()],
+        size: Au::from_px(20),
+        ..Font::new_empty()
+    };
+    assert_eq!(font.font_face_families("Square"), ["Square"]);
+    assert!(font.text_width("xx") < 40.0);
+
+    assert!(add_font_file(
+        "square",
+        path,
+        FontWeight::BOLD,
+        FontSlant::Normal,
+        &[]
+    ));
+    assert!(add_font_file(
+        "Wide Square",
+        path,
+        FontWeight::NORMAL,
+        FontSlant::Normal,
+        &[parse_media_query_list("(min-width: 1000px)")]
+    ));
+    assert!(!add_font_file(
+        "Square",
+        Path::new("reftests/fonts/missing.ttf"),
+        FontWeight::NORMAL,
+        FontSlant::Normal,
+        &[]
+    ));
+    reload_fonts();
+    assert!(!match_font_face_media(800.0, 600.0));
+
+    // The family is matched case-insensitively, and resolves to the family in the file.
+    assert_eq!(font.font_face_families("SQUARE"), ["Naglfar Square"]);
+    assert_eq!(font.font_face_families("Wide Square"), ["Wide Square"]);
+    assert_eq!(
+        font.description().family().unwrap(),
+        "Naglfar Square,Wide Square,sans-serif"
+    );
+    assert_eq!(font.text_width("xx"), 40.0);
+    assert_eq!(font.ex_and_ch(), (20.0, 20.0));
+
+    // Faces in ``@media`` rules are used only where the media queries match.
+    assert!(match_font_face_media(1000.0, 600.0));
+    assert_eq!(font.font_face_families("Wide Square"), ["Naglfar Square"]);
+
+    clear_font_faces();
+    reload_fonts();
+    assert!(font.text_width("xx") < 40.0);
+}
@@ -0,0 +1,60 @@
+use interface::{build_display_list, update_html_source};
+use layout;
+use painter::DisplayList;
+use window::render_item;
+
+use std::default::Default;
+use std::fs::File;
+use std::path::Path;
+
+use gtk::cairo;
+
+use app_units::Au;
+
+/// Loads ``html_src`` and lays it out against a ``width`` x ``height`` viewport.
+/// No GTK window (and therefore no display) is needed.
+pub fn render(html_src: String, width: i32, height: i32) -> DisplayList {
+    update_html_source(html_src);
+
+    let mut viewport: layout::Dimensions = Default::default();
+    viewport.content.width = Au::from_px(width);
+    viewport.content.height = Au::from_px(height);
+
+    build_display_list(viewport)
+}
+
+/// Replays ``items`` onto a new ``width`` x ``height`` image surface, the same way
+/// ``window::RenderingWindow`` does onto its drawing area.
+pub fn paint(
+    items: &DisplayList,
+    width: i32,
+    height: i32,
+) -> Result<cairo::ImageSurface, cairo::Error> {
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)?;
+    {
+        let ctx = cairo::Context::new(&surface)?;
+
+        // A window shows white behind the page.
+        ctx.set_source_rgb(1.0, 1.0, 1.0);
+        ctx.paint()?;
+
+        let pango_layout = pangocairo::functions::create_layout(&ctx);
+        for item in items {
+            render_item(&ctx, &pango_layout, &item.command);
+        }
+    }
+    Ok(surface)
+}
+
+/// Renders ``html_src`` headlessly and writes the viewport to ``png_path``.
+pub fn render_to_png(
+    html_src: String,
+    width: i32,
+    height: i32,
+    png_path: &Path,
+) -> Result<(), cairo::IoError> {
+    let items = render(html_src, width, height);
+    let surface = paint(&items, width, height)?;
+    let mut file = File::create(png_path)?;
+    surface.write_to_png(&mut file)
+}
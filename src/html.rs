@@ -1,14 +1,24 @@
 use dom;
 
-use std::collections::HashMap;
+use html_tokenizer::{is_html_whitespace, Token, Tokenizer};
+use parse_error::ParseError;
+
 use std::cell::RefCell;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::iter;
+use std::mem;
 use std::path::PathBuf;
-use std::cmp::max;
-use std::str::from_utf8;
 
 thread_local!(pub static CUR_DIR: RefCell<PathBuf> = { RefCell::new(PathBuf::new()) });
 
 pub fn parse(source: String, file_path: PathBuf) -> dom::Node {
+    parse_with_errors(source, file_path).0
+}
+
+/// Like ``parse``, but also returns the parse errors, in source order. They never stop parsing:
+/// the returned tree is what a browser would build from the same source.
+pub fn parse_with_errors(source: String, file_path: PathBuf) -> (dom::Node, Vec<ParseError>) {
     CUR_DIR.with(|cur_dir| {
         *cur_dir.borrow_mut() = if let Some(parent) = file_path.parent() {
             parent.to_path_buf()
@@ -17,277 +27,1387 @@ pub fn parse(source: String, file_path: PathBuf) -> dom::Node {
             file_path.to_path_buf()
         }
     });
-    let mut nodes = match Parser::new(source).parse_nodes() {
-        Ok(nodes) => nodes,
-        Err(_) => panic!("unknown error"),
+    TreeBuilder::new().run(Tokenizer::new(source.as_str()))
+}
+
+/// Blanks out the comments in ``s`` (comments may be nested). Line breaks and byte offsets are
+/// kept so that positions in the result are positions in ``s``. An unclosed comment runs to the
+/// end, and a closing delimiter outside of any comment is kept as it is.
+pub fn remove_comments(s: &str, opening: &str, closing: &str) -> (String, Vec<ParseError>) {
+    let mut level = 0;
+    let mut pos = 0;
+    let mut ret = String::with_capacity(s.len());
+    let mut errors = vec![];
+    let mut comment_start = 0;
+
+    let blank = |ret: &mut String, s: &str| {
+        for c in s.chars() {
+            match c {
+                '\n' => ret.push('\n'),
+                c => ret.extend(iter::repeat(' ').take(c.len_utf8())),
+            }
+        }
     };
 
-    // If the document contains a root element, just return it. Otherwise, create one.
-    if nodes.len() == 1 {
-        nodes.swap_remove(0)
-    } else {
-        dom::Node::elem("html".to_string(), HashMap::new(), nodes)
+    while pos < s.len() {
+        let rest = &s[pos..];
+        if rest.starts_with(opening) {
+            if level == 0 {
+                comment_start = pos;
+            }
+            level += 1;
+            blank(&mut ret, opening);
+            pos += opening.len();
+        } else if rest.starts_with(closing) {
+            if level == 0 {
+                errors.push(ParseError::at(
+                    s,
+                    pos,
+                    format!("\"{}\" outside of a comment", closing),
+                ));
+                ret.push_str(closing);
+            } else {
+                level -= 1;
+                blank(&mut ret, closing);
+            }
+            pos += closing.len();
+        } else {
+            let c = rest.chars().next().unwrap_or(' ');
+            if level == 0 {
+                ret.push(c);
+            } else {
+                blank(&mut ret, &rest[..c.len_utf8()]);
+            }
+            pos += c.len_utf8();
+        }
     }
+
+    if level != 0 {
+        errors.push(ParseError::at(s, comment_start, "unclosed comment"));
+    }
+
+    (ret, errors)
 }
 
-fn is_not_to_close_tag(tag_name: &str) -> bool {
-    if tag_name == "br" || tag_name == "img" || tag_name == "hr" || tag_name == "meta"
-        || tag_name == "input" || tag_name == "embed" || tag_name == "area"
-        || tag_name == "base" || tag_name == "col" || tag_name == "keygen"
-        || tag_name == "link" || tag_name == "param" || tag_name == "source"
-    {
-        true
-    } else {
-        false
+/// Builds the DOM following the tree construction stage of the HTML Standard, so that broken
+/// markup ends up as the same tree a browser would build: ``<html>``, ``<head>`` and ``<body>``
+/// are created when they are omitted, end tags are implied (``<p>a<p>b`` is two paragraphs),
+/// misnested formatting elements are repaired with the adoption agency algorithm and stray end
+/// tags are ignored.
+///
+/// Tables are handled inside the "in body" insertion mode with a reduced version of the table
+/// modes. Templates, framesets, forms' owner and foreign (SVG/MathML) content are not supported.
+struct TreeBuilder {
+    // Nodes live in an arena until the end because the adoption agency algorithm moves nodes
+    // between parents. The first node is the document.
+    nodes: Vec<ArenaNode>,
+    mode: InsertionMode,
+    // The mode to go back to after the text of a script, style, title, etc.
+    original_mode: InsertionMode,
+    open_elements: Vec<NodeId>,
+    active_formatting_elements: Vec<FormattingEntry>,
+    head: Option<NodeId>,
+    errors: Vec<ParseError>,
+    // Where the token being processed starts.
+    location: (usize, usize),
+}
+
+type NodeId = usize;
+
+const DOCUMENT: NodeId = 0;
+
+enum ArenaData {
+    Element(String, dom::AttrMap),
+    Text(String),
+}
+
+struct ArenaNode {
+    data: ArenaData,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FormattingEntry {
+    Marker,
+    Element(NodeId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+    Text,
+    AfterBody,
+    AfterAfterBody,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+}
+
+impl Scope {
+    fn is_boundary(self, tag_name: &str) -> bool {
+        match (self, tag_name) {
+            (_, "html") | (_, "table") | (_, "template") => true,
+            (Scope::Table, _) => false,
+            (_, "applet")
+            | (_, "caption")
+            | (_, "td")
+            | (_, "th")
+            | (_, "marquee")
+            | (_, "object") => true,
+            (Scope::ListItem, "ol") | (Scope::ListItem, "ul") => true,
+            (Scope::Button, "button") => true,
+            _ => false,
+        }
     }
 }
 
-pub fn remove_comments(s: &[u8], opening: &str, closing: &str) -> String {
-    let mut level = 0;
-    let mut pos = 0;
-    let mut ret = "".to_string();
-    let len = s.len();
-    let opening_len = opening.len();
-    let closing_len = closing.len();
+fn is_special(tag_name: &str) -> bool {
+    matches!(
+        tag_name,
+        "address"
+            | "applet"
+            | "area"
+            | "article"
+            | "aside"
+            | "base"
+            | "basefont"
+            | "bgsound"
+            | "blockquote"
+            | "body"
+            | "br"
+            | "button"
+            | "caption"
+            | "center"
+            | "col"
+            | "colgroup"
+            | "dd"
+            | "details"
+            | "dir"
+            | "div"
+            | "dl"
+            | "dt"
+            | "embed"
+            | "fieldset"
+            | "figcaption"
+            | "figure"
+            | "footer"
+            | "form"
+            | "frame"
+            | "frameset"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "head"
+            | "header"
+            | "hgroup"
+            | "hr"
+            | "html"
+            | "iframe"
+            | "img"
+            | "input"
+            | "keygen"
+            | "li"
+            | "link"
+            | "listing"
+            | "main"
+            | "marquee"
+            | "menu"
+            | "meta"
+            | "nav"
+            | "noembed"
+            | "noframes"
+            | "noscript"
+            | "object"
+            | "ol"
+            | "p"
+            | "param"
+            | "plaintext"
+            | "pre"
+            | "script"
+            | "search"
+            | "section"
+            | "select"
+            | "source"
+            | "style"
+            | "summary"
+            | "table"
+            | "tbody"
+            | "td"
+            | "template"
+            | "textarea"
+            | "tfoot"
+            | "th"
+            | "thead"
+            | "title"
+            | "tr"
+            | "track"
+            | "ul"
+            | "wbr"
+            | "xmp"
+    )
+}
 
-    if len as isize - max(opening_len, closing_len) as isize - 1 < 0 {
-        return from_utf8(s).unwrap().to_string();
+fn is_heading(tag_name: &str) -> bool {
+    matches!(tag_name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+fn has_implied_end_tag(tag_name: &str) -> bool {
+    matches!(
+        tag_name,
+        "dd" | "dt" | "li" | "optgroup" | "option" | "p" | "rb" | "rp" | "rt" | "rtc"
+    )
+}
+
+// Elements that push a marker onto the list of active formatting elements, so that formatting
+// from outside doesn't leak into them.
+fn is_scoping_formatting(tag_name: &str) -> bool {
+    matches!(
+        tag_name,
+        "applet" | "marquee" | "object" | "caption" | "td" | "th"
+    )
+}
+
+// Elements whose children other than table parts are moved in front of the table.
+fn is_table_context(tag_name: &str) -> bool {
+    matches!(tag_name, "table" | "tbody" | "tfoot" | "thead" | "tr")
+}
+
+fn is_table_part(tag_name: &str) -> bool {
+    matches!(
+        tag_name,
+        "caption" | "colgroup" | "col" | "tbody" | "thead" | "tfoot" | "tr" | "td" | "th"
+    )
+}
+
+fn attr_map(attrs: Vec<(String, String)>) -> dom::AttrMap {
+    attrs.into_iter().map(url_conv).collect()
+}
+
+fn split_leading_whitespace(text: String) -> (String, String) {
+    match text.find(|c| !is_html_whitespace(c)) {
+        Some(0) => ("".to_string(), text),
+        Some(pos) => (text[..pos].to_string(), text[pos..].to_string()),
+        None => (text, "".to_string()),
     }
+}
 
-    while pos < len {
-        if pos < len - opening_len - 1 && s[pos..(pos + opening_len)] == *opening.as_bytes() {
-            pos += opening_len;
-            level += 1;
-            continue;
+fn collapse_whitespace(text: &str, keep_leading_whitespace: bool) -> String {
+    let mut last = if keep_leading_whitespace { '*' } else { ' ' };
+    text.chars().fold("".to_string(), |mut s, c| {
+        if !(is_html_whitespace(last) && is_html_whitespace(c)) {
+            s.push(if is_html_whitespace(c) { ' ' } else { c });
         }
-        if pos < len - closing_len && s[pos..(pos + closing_len)] == *closing.as_bytes() {
-            pos += closing_len;
-            if level <= 0 {
-                panic!("not found corresponding \"/*\"")
-            }
-            level -= 1;
-            continue;
+        last = c;
+        s
+    })
+}
+
+impl TreeBuilder {
+    fn new() -> TreeBuilder {
+        TreeBuilder {
+            nodes: vec![ArenaNode {
+                data: ArenaData::Element("".to_string(), HashMap::new()),
+                parent: None,
+                children: vec![],
+            }],
+            mode: InsertionMode::Initial,
+            original_mode: InsertionMode::Initial,
+            open_elements: vec![],
+            active_formatting_elements: vec![],
+            head: None,
+            errors: vec![],
+            location: (1, 1),
         }
-        if level == 0 {
-            ret.push(s[pos] as char);
+    }
+
+    fn run(mut self, mut tokenizer: Tokenizer) -> (dom::Node, Vec<ParseError>) {
+        while let Some(token) = tokenizer.next() {
+            self.location = tokenizer.last_token_location();
+            self.process(token);
         }
-        pos += 1;
+        self.location = tokenizer.end_location();
+        self.process_eof();
+
+        let mut errors = tokenizer.take_errors();
+        errors.append(&mut self.errors);
+        errors.sort_by_key(|error| (error.line, error.column));
+
+        let root = match self.nodes[DOCUMENT].children.first() {
+            Some(&html) => self.to_dom(html),
+            None => dom::Node::elem("html".to_string(), HashMap::new(), vec![]),
+        };
+        (root, errors)
     }
 
-    if level != 0 {
-        panic!("comments are not balanced")
+    fn error<S: Into<String>>(&mut self, message: S) {
+        let (line, column) = self.location;
+        self.errors.push(ParseError::new(line, column, message));
     }
 
-    ret
-}
+    fn unexpected_start_tag(&mut self, name: &str) {
+        self.error(format!("unexpected start tag <{}>", name));
+    }
 
-struct Parser {
-    pos: usize,
-    input: String,
-}
+    fn unexpected_end_tag(&mut self, name: &str) {
+        self.error(format!("unexpected end tag </{}>", name));
+    }
 
-impl Parser {
-    fn new(input: String) -> Parser {
-        Parser {
-            pos: 0,
-            input: input,
+    // Reports an error unless the current node is one of ``tag_names``.
+    fn expect_current_node(&mut self, tag_names: &[&str]) {
+        let current = self.current_tag_name().to_string();
+        if !tag_names.contains(&current.as_str()) {
+            self.error(format!("unclosed element <{}>", current));
         }
     }
 
-    fn parse_nodes(&mut self) -> Result<Vec<dom::Node>, ()> {
-        let mut nodes: Vec<dom::Node> = vec![];
-        loop {
-            // TODO: Is this correct?
-            match nodes.last() {
-                Some(last) if last.is_inline() && last.contains_text() => {}
-                _ => self.consume_whitespace()?,
-            };
+    fn process(&mut self, token: Token) {
+        match self.mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::AfterBody | InsertionMode::AfterAfterBody => self.after_body(token),
+        }
+    }
 
-            // Comments
-            if self.starts_with("<!--") {
-                self.consume_comment()?;
-                continue;
+    fn process_eof(&mut self) {
+        // Take the "anything else" path of each mode until the body exists.
+        loop {
+            match self.mode {
+                InsertionMode::Initial => self.mode = InsertionMode::BeforeHtml,
+                InsertionMode::BeforeHtml => {
+                    self.insert("html", HashMap::new());
+                    self.mode = InsertionMode::BeforeHead;
+                }
+                InsertionMode::BeforeHead => {
+                    self.head = Some(self.insert("head", HashMap::new()));
+                    self.mode = InsertionMode::InHead;
+                }
+                InsertionMode::InHead => {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                }
+                InsertionMode::AfterHead => {
+                    self.insert("body", HashMap::new());
+                    self.mode = InsertionMode::InBody;
+                }
+                InsertionMode::Text => {
+                    let name = self.current_tag_name().to_string();
+                    self.error(format!("end of file in <{}>", name));
+                    self.pop();
+                    self.mode = self.original_mode;
+                }
+                _ => break,
             }
+        }
 
-            // DOCTYPE
-            if self.starts_with("<!") {
-                self.consume_while(|c| c != '>')?;
-                assert_eq!(self.consume_char()?, '>');
-                continue;
-            }
+        let unclosed = self.open_elements.iter().rev().cloned().find(|&id| {
+            !has_implied_end_tag(self.tag_name(id))
+                && !is_table_part(self.tag_name(id))
+                && !["body", "html"].contains(&self.tag_name(id))
+        });
+        if let Some(id) = unclosed {
+            let name = self.tag_name(id).to_string();
+            self.error(format!("end of file with unclosed element <{}>", name));
+        }
+    }
 
-            // TODO: Must support </
-            if self.eof() || self.starts_with("</") {
-                break;
+    fn initial(&mut self, token: Token) {
+        self.mode = InsertionMode::BeforeHtml;
+        match token {
+            Token::Doctype(_) => {}
+            Token::Comment(_) => self.mode = InsertionMode::Initial,
+            Token::Text(text) => {
+                let (_, rest) = split_leading_whitespace(text);
+                if rest.is_empty() {
+                    self.mode = InsertionMode::Initial;
+                } else {
+                    self.error("missing doctype");
+                    self.process(Token::Text(rest));
+                }
             }
+            token => {
+                self.error("missing doctype");
+                self.process(token)
+            }
+        }
+    }
 
-            if let Ok(node) = self.parse_node() {
-                nodes.push(node);
+    fn before_html(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected doctype"),
+            Token::Comment(_) => {}
+            Token::Text(text) => {
+                let (_, rest) = split_leading_whitespace(text);
+                if !rest.is_empty() {
+                    self.insert("html", HashMap::new());
+                    self.mode = InsertionMode::BeforeHead;
+                    self.process(Token::Text(rest));
+                }
+            }
+            Token::StartTag { name, attrs, .. } if name == "html" => {
+                self.insert(&name, attr_map(attrs));
+                self.mode = InsertionMode::BeforeHead;
+            }
+            Token::EndTag { ref name }
+                if !["head", "body", "html", "br"].contains(&name.as_str()) =>
+            {
+                self.unexpected_end_tag(name)
+            }
+            token => {
+                self.insert("html", HashMap::new());
+                self.mode = InsertionMode::BeforeHead;
+                self.process(token);
             }
         }
-        Ok(nodes)
     }
 
-    fn parse_node(&mut self) -> Result<dom::Node, ()> {
-        match self.next_char()? {
-            '<' => self.parse_element(),
-            _ => self.parse_text(),
+    fn before_head(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected doctype"),
+            Token::Comment(_) => {}
+            Token::Text(text) => {
+                let (_, rest) = split_leading_whitespace(text);
+                if !rest.is_empty() {
+                    self.head = Some(self.insert("head", HashMap::new()));
+                    self.mode = InsertionMode::InHead;
+                    self.process(Token::Text(rest));
+                }
+            }
+            Token::StartTag { ref name, .. } if name == "html" => self.in_body(token),
+            Token::StartTag { name, attrs, .. } if name == "head" => {
+                self.head = Some(self.insert(&name, attr_map(attrs)));
+                self.mode = InsertionMode::InHead;
+            }
+            Token::EndTag { ref name }
+                if !["head", "body", "html", "br"].contains(&name.as_str()) =>
+            {
+                self.unexpected_end_tag(name)
+            }
+            token => {
+                self.head = Some(self.insert("head", HashMap::new()));
+                self.mode = InsertionMode::InHead;
+                self.process(token);
+            }
         }
     }
 
-    fn parse_element(&mut self) -> Result<dom::Node, ()> {
-        // Opening tag.
-        assert_eq!(self.consume_char()?, '<');
-        let tag_name = self.parse_tag_name()?;
-        let attrs = self.parse_attributes()?;
-        assert_eq!(self.consume_char()?, '>');
+    fn in_head(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected doctype"),
+            Token::Comment(_) => {}
+            Token::Text(text) => {
+                let (whitespace, rest) = split_leading_whitespace(text);
+                if !whitespace.is_empty() {
+                    self.insert_text(whitespace);
+                }
+                if !rest.is_empty() {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    self.process(Token::Text(rest));
+                }
+            }
+            Token::StartTag { name, attrs, .. } => match name.as_str() {
+                "html" => self.in_body(Token::StartTag {
+                    name: name.clone(),
+                    attrs,
+                    self_closing: false,
+                }),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    self.insert_void(&name, attr_map(attrs))
+                }
+                "title" | "noframes" | "style" | "script" => {
+                    self.insert(&name, attr_map(attrs));
+                    self.original_mode = self.mode;
+                    self.mode = InsertionMode::Text;
+                }
+                "head" => self.unexpected_start_tag(&name),
+                _ => {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    self.process(Token::StartTag {
+                        name: name.clone(),
+                        attrs,
+                        self_closing: false,
+                    });
+                }
+            },
+            Token::EndTag { ref name } if name == "head" => {
+                self.pop();
+                self.mode = InsertionMode::AfterHead;
+            }
+            Token::EndTag { ref name } if !["body", "html", "br"].contains(&name.as_str()) => {
+                self.unexpected_end_tag(name)
+            }
+            token => {
+                self.pop();
+                self.mode = InsertionMode::AfterHead;
+                self.process(token);
+            }
+        }
+    }
 
-        if is_not_to_close_tag(tag_name.as_str()) {
-            return Ok(dom::Node::elem(tag_name, attrs, vec![]));
+    fn after_head(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected doctype"),
+            Token::Comment(_) => {}
+            Token::Text(text) => {
+                let (whitespace, rest) = split_leading_whitespace(text);
+                if !whitespace.is_empty() {
+                    self.insert_text(whitespace);
+                }
+                if !rest.is_empty() {
+                    self.insert("body", HashMap::new());
+                    self.mode = InsertionMode::InBody;
+                    self.process(Token::Text(rest));
+                }
+            }
+            Token::StartTag { ref name, .. } if name == "html" => self.in_body(token),
+            Token::StartTag { name, attrs, .. } if name == "body" => {
+                self.insert(&name, attr_map(attrs));
+                self.mode = InsertionMode::InBody;
+            }
+            Token::StartTag { ref name, .. }
+                if [
+                    "base", "basefont", "bgsound", "link", "meta", "noframes", "script", "style",
+                    "title",
+                ]
+                .contains(&name.as_str()) =>
+            {
+                // Elements that belong in the head but come after it still go into it.
+                self.error(format!("<{}> after </head>", name));
+                let head = self.head.unwrap_or(DOCUMENT);
+                self.open_elements.push(head);
+                self.in_head(token);
+                if let Some(pos) = self.open_elements.iter().rposition(|&id| id == head) {
+                    self.open_elements.remove(pos);
+                }
+            }
+            Token::StartTag { ref name, .. } if name == "head" => self.unexpected_start_tag(name),
+            Token::EndTag { ref name } if !["body", "html", "br"].contains(&name.as_str()) => {
+                self.unexpected_end_tag(name)
+            }
+            token => {
+                self.insert("body", HashMap::new());
+                self.mode = InsertionMode::InBody;
+                self.process(token);
+            }
         }
+    }
 
-        if tag_name == "script" || tag_name == "style" {
-            return Ok(dom::Node::elem(
-                tag_name,
-                attrs,
-                vec![dom::Node::text(self.consume_special_element()?)],
-            ));
+    fn text(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => self.insert_text(text),
+            Token::EndTag { .. } => {
+                self.pop();
+                self.mode = self.original_mode;
+            }
+            token => {
+                self.pop();
+                self.mode = self.original_mode;
+                self.process(token);
+            }
         }
+    }
 
-        // Contents.
-        let children = self.parse_nodes()?;
+    fn after_body(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected doctype"),
+            Token::Comment(_) => {}
+            Token::Text(text) => {
+                let (whitespace, rest) = split_leading_whitespace(text);
+                if !whitespace.is_empty() {
+                    self.in_body(Token::Text(whitespace));
+                }
+                if !rest.is_empty() {
+                    self.error("text after </body>");
+                    self.mode = InsertionMode::InBody;
+                    self.in_body(Token::Text(rest));
+                }
+            }
+            Token::StartTag { ref name, .. } if name == "html" => self.in_body(token),
+            Token::EndTag { ref name } if name == "html" => {
+                self.mode = InsertionMode::AfterAfterBody
+            }
+            token => {
+                match token {
+                    Token::StartTag { ref name, .. } => {
+                        self.error(format!("<{}> after </body>", name))
+                    }
+                    Token::EndTag { ref name } => self.error(format!("</{}> after </body>", name)),
+                    _ => {}
+                }
+                self.mode = InsertionMode::InBody;
+                self.in_body(token);
+            }
+        }
+    }
 
-        // Closing tag.
-        if !self.eof() {
-            assert_eq!(self.consume_char()?, '<');
-            assert_eq!(self.consume_char()?, '/');
-            // assert_eq!(, tag_name);
-            self.parse_tag_name()?;
-            assert_eq!(self.consume_char()?, '>');
+    fn in_body(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected doctype"),
+            Token::Comment(_) => {}
+            Token::Text(text) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_text(text);
+            }
+            Token::StartTag { name, attrs, .. } => self.in_body_start_tag(name, attr_map(attrs)),
+            Token::EndTag { name } => self.in_body_end_tag(name),
         }
+    }
 
-        Ok(dom::Node::elem(tag_name, attrs, children))
+    fn in_body_start_tag(&mut self, name: String, attrs: dom::AttrMap) {
+        match name.as_str() {
+            "html" => {
+                self.unexpected_start_tag(&name);
+                if let Some(&html) = self.open_elements.first() {
+                    self.merge_attrs(html, attrs);
+                }
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "title" => {
+                let attrs = attrs.into_iter().collect();
+                self.in_head(Token::StartTag {
+                    name: name.clone(),
+                    attrs,
+                    self_closing: false,
+                })
+            }
+            "body" => {
+                self.unexpected_start_tag(&name);
+                if let Some(&body) = self.open_elements.get(1) {
+                    if self.tag_name(body) == "body" {
+                        self.merge_attrs(body, attrs);
+                    }
+                }
+            }
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog"
+            | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer" | "form"
+            | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "p"
+            | "plaintext" | "pre" | "search" | "section" | "summary" | "ul" => {
+                self.close_p_element_in_button_scope();
+                self.insert(&name, attrs);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_element_in_button_scope();
+                if is_heading(self.current_tag_name()) {
+                    self.unexpected_start_tag(&name);
+                    self.pop();
+                }
+                self.insert(&name, attrs);
+            }
+            "li" => self.start_list_item(&name, attrs, &["li"]),
+            "dd" | "dt" => self.start_list_item(&name, attrs, &["dd", "dt"]),
+            "button" => {
+                if self.has_in_scope(&["button"], Scope::Default) {
+                    self.unexpected_start_tag(&name);
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["button"]);
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert(&name, attrs);
+            }
+            "a" => {
+                if let Some((_, a)) = self.formatting_element_after_last_marker("a") {
+                    self.unexpected_start_tag(&name);
+                    self.adoption_agency("a");
+                    if let Some(pos) = self.position_in_active_formatting_elements(a) {
+                        self.active_formatting_elements.remove(pos);
+                    }
+                    if let Some(pos) = self.position_in_open_elements(a) {
+                        self.open_elements.remove(pos);
+                    }
+                }
+                self.reconstruct_active_formatting_elements();
+                let id = self.insert(&name, attrs);
+                self.push_active_formatting_element(id);
+            }
+            "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong"
+            | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let id = self.insert(&name, attrs);
+                self.push_active_formatting_element(id);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_in_scope(&["nobr"], Scope::Default) {
+                    self.unexpected_start_tag(&name);
+                    self.adoption_agency("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let id = self.insert(&name, attrs);
+                self.push_active_formatting_element(id);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert(&name, attrs);
+                self.active_formatting_elements
+                    .push(FormattingEntry::Marker);
+            }
+            "table" => {
+                // A table directly inside a table closes the outer one.
+                if is_table_context(self.current_tag_name()) {
+                    self.unexpected_start_tag(&name);
+                    self.pop_until(&["table"]);
+                } else {
+                    self.close_p_element_in_button_scope();
+                }
+                self.insert(&name, attrs);
+            }
+            "caption" | "colgroup" | "col" | "tbody" | "thead" | "tfoot" | "tr" | "td" | "th" => {
+                if self.has_in_scope(&["table"], Scope::Table) {
+                    self.start_table_part(&name, attrs);
+                } else {
+                    self.unexpected_start_tag(&name);
+                }
+            }
+            "area" | "br" | "embed" | "img" | "input" | "keygen" | "wbr" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_void(&name, attrs);
+            }
+            "image" => {
+                self.unexpected_start_tag(&name);
+                self.reconstruct_active_formatting_elements();
+                self.insert_void("img", attrs);
+            }
+            "param" | "source" | "track" => self.insert_void(&name, attrs),
+            "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_void(&name, attrs);
+            }
+            "textarea" | "iframe" | "noembed" | "xmp" => {
+                if name == "xmp" {
+                    self.close_p_element_in_button_scope();
+                    self.reconstruct_active_formatting_elements();
+                }
+                self.insert(&name, attrs);
+                self.original_mode = self.mode;
+                self.mode = InsertionMode::Text;
+            }
+            "optgroup" | "option" => {
+                if self.current_tag_name() == "option" {
+                    self.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert(&name, attrs);
+            }
+            "rb" | "rtc" | "rp" | "rt" => {
+                if self.has_in_scope(&["ruby"], Scope::Default) {
+                    let except = if name == "rp" || name == "rt" {
+                        Some("rtc")
+                    } else {
+                        None
+                    };
+                    self.generate_implied_end_tags(except);
+                }
+                self.insert(&name, attrs);
+            }
+            "frame" | "frameset" | "head" => self.unexpected_start_tag(&name),
+            _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert(&name, attrs);
+            }
+        }
     }
 
-    fn parse_tag_name(&mut self) -> Result<String, ()> {
-        self.consume_while(|c| c.is_alphanumeric())
+    fn in_body_end_tag(&mut self, name: String) {
+        match name.as_str() {
+            "body" | "html" => {
+                if self.has_in_scope(&["body"], Scope::Default) {
+                    self.mode = if name == "body" {
+                        InsertionMode::AfterBody
+                    } else {
+                        InsertionMode::AfterAfterBody
+                    };
+                } else {
+                    self.unexpected_end_tag(&name);
+                }
+            }
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details"
+            | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer"
+            | "form" | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "pre"
+            | "search" | "section" | "summary" | "ul" | "applet" | "marquee" | "object" => {
+                if self.has_in_scope(&[&name], Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.expect_current_node(&[&name]);
+                    self.pop_until(&[&name]);
+                } else {
+                    self.unexpected_end_tag(&name);
+                }
+            }
+            "p" => {
+                if !self.has_in_scope(&["p"], Scope::Button) {
+                    // "</p>" without an open paragraph makes an empty one.
+                    self.unexpected_end_tag(&name);
+                    self.insert("p", HashMap::new());
+                }
+                self.generate_implied_end_tags(Some("p"));
+                self.expect_current_node(&["p"]);
+                self.pop_until(&["p"]);
+            }
+            "li" => {
+                if self.has_in_scope(&["li"], Scope::ListItem) {
+                    self.generate_implied_end_tags(Some("li"));
+                    self.expect_current_node(&["li"]);
+                    self.pop_until(&["li"]);
+                } else {
+                    self.unexpected_end_tag(&name);
+                }
+            }
+            "dd" | "dt" => {
+                if self.has_in_scope(&[&name], Scope::Default) {
+                    self.generate_implied_end_tags(Some(&name));
+                    self.expect_current_node(&[&name]);
+                    self.pop_until(&[&name]);
+                } else {
+                    self.unexpected_end_tag(&name);
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let headings = ["h1", "h2", "h3", "h4", "h5", "h6"];
+                if self.has_in_scope(&headings, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.expect_current_node(&[&name]);
+                    self.pop_until(&headings);
+                } else {
+                    self.unexpected_end_tag(&name);
+                }
+            }
+            "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small"
+            | "strike" | "strong" | "tt" | "u" => self.adoption_agency(&name),
+            "br" => {
+                // "</br>" is treated as "<br>".
+                self.unexpected_end_tag(&name);
+                self.reconstruct_active_formatting_elements();
+                self.insert_void("br", HashMap::new());
+            }
+            "table" | "caption" | "colgroup" | "tbody" | "thead" | "tfoot" | "tr" | "td" | "th" => {
+                if self.has_in_scope(&[&name], Scope::Table) {
+                    self.generate_implied_end_tags(None);
+                    // Open rows and cells are closed silently.
+                    let mut closed_silently = vec![name.as_str()];
+                    if !matches!(name.as_str(), "td" | "th") {
+                        closed_silently.extend(&["tbody", "thead", "tfoot", "tr", "td", "th"]);
+                    }
+                    self.expect_current_node(&closed_silently);
+                    self.pop_until(&[&name]);
+                } else {
+                    self.unexpected_end_tag(&name);
+                }
+            }
+            _ => self.any_other_end_tag(&name),
+        }
     }
 
-    fn parse_attributes(&mut self) -> Result<dom::AttrMap, ()> {
-        let mut attributes = HashMap::with_capacity(16);
-        loop {
-            self.consume_whitespace()?;
-            if self.next_char()? == '>' {
+    fn start_list_item(&mut self, name: &str, attrs: dom::AttrMap, closes: &[&str]) {
+        for i in (0..self.open_elements.len()).rev() {
+            let tag_name = self.tag_name(self.open_elements[i]).to_string();
+            if closes.contains(&tag_name.as_str()) {
+                self.generate_implied_end_tags(Some(&tag_name));
+                self.pop_until(&[&tag_name]);
                 break;
             }
-            match self.parse_attr() {
-                Ok(x) => {
-                    let (name, value) = url_conv(x);
-                    attributes.insert(name, value);
+            if is_special(&tag_name) && !["address", "div", "p"].contains(&tag_name.as_str()) {
+                break;
+            }
+        }
+        self.close_p_element_in_button_scope();
+        self.insert(name, attrs);
+    }
+
+    fn start_table_part(&mut self, name: &str, attrs: dom::AttrMap) {
+        match name {
+            "caption" | "colgroup" | "tbody" | "thead" | "tfoot" => {
+                self.clear_stack_back_to(&["table"]);
+                self.insert(name, attrs);
+                if name == "caption" {
+                    self.active_formatting_elements
+                        .push(FormattingEntry::Marker);
                 }
-                Err(()) => {}
+            }
+            "col" => {
+                if self.current_tag_name() != "colgroup" {
+                    self.clear_stack_back_to(&["table"]);
+                    self.insert("colgroup", HashMap::new());
+                }
+                self.insert_void(name, attrs);
+            }
+            "tr" => {
+                self.clear_stack_back_to(&["tbody", "thead", "tfoot", "table"]);
+                if self.current_tag_name() == "table" {
+                    self.insert("tbody", HashMap::new());
+                }
+                self.insert(name, attrs);
+            }
+            _ => {
+                self.clear_stack_back_to(&["tr", "tbody", "thead", "tfoot", "table"]);
+                if self.current_tag_name() == "table" {
+                    self.insert("tbody", HashMap::new());
+                }
+                if self.current_tag_name() != "tr" {
+                    self.insert("tr", HashMap::new());
+                }
+                self.insert(name, attrs);
+                self.active_formatting_elements
+                    .push(FormattingEntry::Marker);
             }
         }
-        Ok(attributes)
     }
 
-    fn parse_attr(&mut self) -> Result<(String, String), ()> {
-        let name = self.parse_tag_name()?;
-        if self.consume_char()? != '=' {
-            return Err(());
+    fn any_other_end_tag(&mut self, name: &str) {
+        for i in (0..self.open_elements.len()).rev() {
+            let tag_name = self.tag_name(self.open_elements[i]).to_string();
+            if tag_name == name {
+                self.generate_implied_end_tags(Some(name));
+                self.expect_current_node(&[name]);
+                while self.open_elements.len() > i {
+                    self.pop();
+                }
+                return;
+            }
+            if is_special(&tag_name) {
+                self.unexpected_end_tag(name);
+                return;
+            }
         }
-        let value = self.parse_attr_value()?;
-        Ok((name, value))
     }
 
-    fn parse_attr_value(&mut self) -> Result<String, ()> {
-        let open_quote = self.next_char()?;
-        let mut open_quote_appeared = false;
-        if open_quote == '"' || open_quote == '\'' {
-            open_quote_appeared = true;
-            self.consume_char()?; // " or '
+    /// Fixes up misnested formatting elements such as ``<b>1<p>2</b>3</p>``, which becomes
+    /// ``<b>1</b><p><b>2</b>3</p>``.
+    fn adoption_agency(&mut self, subject: &str) {
+        let current = self.current_node();
+        if self.tag_name(current) == subject
+            && self
+                .position_in_active_formatting_elements(current)
+                .is_none()
+        {
+            self.pop();
+            return;
         }
-        let value = self.consume_while(|c| c != open_quote && c != '>')?;
-        if open_quote_appeared {
-            self.consume_char()?; // Maybe " or '
+
+        for _ in 0..8 {
+            let (formatting_pos, formatting_element) =
+                match self.formatting_element_after_last_marker(subject) {
+                    Some(found) => found,
+                    None => return self.any_other_end_tag(subject),
+                };
+            let formatting_stack_pos = match self.position_in_open_elements(formatting_element) {
+                Some(pos) => pos,
+                None => {
+                    self.unexpected_end_tag(subject);
+                    self.active_formatting_elements.remove(formatting_pos);
+                    return;
+                }
+            };
+            if !self.element_in_scope(formatting_element) {
+                self.unexpected_end_tag(subject);
+                return;
+            }
+            if formatting_element != self.current_node() {
+                self.error(format!("misnested end tag </{}>", subject));
+            }
+
+            let furthest_block = self.open_elements[formatting_stack_pos + 1..]
+                .iter()
+                .cloned()
+                .find(|&id| is_special(self.tag_name(id)));
+            let furthest_block = match furthest_block {
+                Some(id) => id,
+                None => {
+                    while self.open_elements.len() > formatting_stack_pos {
+                        self.pop();
+                    }
+                    self.active_formatting_elements.remove(formatting_pos);
+                    return;
+                }
+            };
+
+            let common_ancestor = self.open_elements[formatting_stack_pos - 1];
+            let mut bookmark = formatting_pos;
+            let mut last_node = furthest_block;
+            let mut node_pos = self.position_in_open_elements(furthest_block).unwrap();
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_pos -= 1;
+                let node = self.open_elements[node_pos];
+                if node == formatting_element {
+                    break;
+                }
+
+                let mut node_formatting_pos = self.position_in_active_formatting_elements(node);
+                if inner_loop_counter > 3 {
+                    if let Some(pos) = node_formatting_pos.take() {
+                        self.active_formatting_elements.remove(pos);
+                        if pos < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+                let node_formatting_pos = match node_formatting_pos {
+                    Some(pos) => pos,
+                    None => {
+                        self.open_elements.remove(node_pos);
+                        continue;
+                    }
+                };
+
+                let new_node = self.clone_element(node);
+                self.active_formatting_elements[node_formatting_pos] =
+                    FormattingEntry::Element(new_node);
+                self.open_elements[node_pos] = new_node;
+                if last_node == furthest_block {
+                    bookmark = node_formatting_pos + 1;
+                }
+                self.append_child(new_node, last_node);
+                last_node = new_node;
+            }
+
+            let (parent, before) = self.appropriate_place(common_ancestor, true);
+            self.insert_node(parent, before, last_node);
+
+            let new_element = self.clone_element(formatting_element);
+            let children = mem::take(&mut self.nodes[furthest_block].children);
+            for &child in &children {
+                self.nodes[child].parent = Some(new_element);
+            }
+            self.nodes[new_element].children = children;
+            self.append_child(furthest_block, new_element);
+
+            if let Some(pos) = self.position_in_active_formatting_elements(formatting_element) {
+                self.active_formatting_elements.remove(pos);
+                if pos < bookmark {
+                    bookmark -= 1;
+                }
+            }
+            let bookmark = min(bookmark, self.active_formatting_elements.len());
+            self.active_formatting_elements
+                .insert(bookmark, FormattingEntry::Element(new_element));
+
+            if let Some(pos) = self.position_in_open_elements(formatting_element) {
+                self.open_elements.remove(pos);
+            }
+            let pos = self.position_in_open_elements(furthest_block).unwrap();
+            self.open_elements.insert(pos + 1, new_element);
         }
-        Ok(value)
     }
 
-    fn parse_text(&mut self) -> Result<dom::Node, ()> {
-        let mut last = '*'; // any char except space
-        Ok(dom::Node::text(
-            self.consume_while(|c| c != '<')?
-                .chars()
-                .fold("".to_string(), |mut s, c| {
-                    if !(last.is_whitespace() && c.is_whitespace()) {
-                        s.push(if c.is_whitespace() { ' ' } else { c });
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let mut i = match self.active_formatting_elements.last() {
+            None | Some(&FormattingEntry::Marker) => return,
+            Some(&FormattingEntry::Element(id)) if self.open_elements.contains(&id) => return,
+            Some(_) => self.active_formatting_elements.len() - 1,
+        };
+        while i > 0 {
+            match self.active_formatting_elements[i - 1] {
+                FormattingEntry::Marker => break,
+                FormattingEntry::Element(id) if self.open_elements.contains(&id) => break,
+                _ => i -= 1,
+            }
+        }
+        for j in i..self.active_formatting_elements.len() {
+            if let FormattingEntry::Element(id) = self.active_formatting_elements[j] {
+                let (tag_name, attrs) = match self.nodes[id].data {
+                    ArenaData::Element(ref tag_name, ref attrs) => {
+                        (tag_name.clone(), attrs.clone())
                     }
-                    last = c;
-                    s
-                }),
-        ))
+                    ArenaData::Text(_) => continue,
+                };
+                let new_element = self.insert(&tag_name, attrs);
+                self.active_formatting_elements[j] = FormattingEntry::Element(new_element);
+            }
+        }
     }
 
-    fn consume_comment(&mut self) -> Result<(), ()> {
-        while !self.eof() {
-            if self.starts_with("-->") {
-                assert_eq!('-', self.consume_char()?);
-                assert_eq!('-', self.consume_char()?);
-                assert_eq!('>', self.consume_char()?);
-                break;
+    fn push_active_formatting_element(&mut self, id: NodeId) {
+        // At most three identical elements are kept after the last marker.
+        let mut identical = vec![];
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match *entry {
+                FormattingEntry::Marker => break,
+                FormattingEntry::Element(other) => {
+                    if let (ArenaData::Element(a, a_attrs), ArenaData::Element(b, b_attrs)) =
+                        (&self.nodes[id].data, &self.nodes[other].data)
+                    {
+                        if a == b && a_attrs == b_attrs {
+                            identical.push(i);
+                        }
+                    }
+                }
+            }
+        }
+        if identical.len() >= 3 {
+            self.active_formatting_elements
+                .remove(*identical.last().unwrap());
+        }
+        self.active_formatting_elements
+            .push(FormattingEntry::Element(id));
+    }
+
+    fn formatting_element_after_last_marker(&self, tag_name: &str) -> Option<(usize, NodeId)> {
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match *entry {
+                FormattingEntry::Marker => return None,
+                FormattingEntry::Element(id) if self.tag_name(id) == tag_name => {
+                    return Some((i, id))
+                }
+                _ => {}
             }
-            self.consume_char()?;
         }
-        Ok(())
+        None
+    }
+
+    fn position_in_active_formatting_elements(&self, id: NodeId) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|entry| *entry == FormattingEntry::Element(id))
+    }
+
+    fn position_in_open_elements(&self, id: NodeId) -> Option<usize> {
+        self.open_elements.iter().rposition(|&open| open == id)
+    }
+
+    fn has_in_scope(&self, tag_names: &[&str], scope: Scope) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            let tag_name = self.tag_name(id);
+            if tag_names.contains(&tag_name) {
+                return true;
+            }
+            if scope.is_boundary(tag_name) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn element_in_scope(&self, target: NodeId) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if id == target {
+                return true;
+            }
+            if Scope::Default.is_boundary(self.tag_name(id)) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while has_implied_end_tag(self.current_tag_name())
+            && Some(self.current_tag_name()) != except
+        {
+            self.pop();
+        }
+    }
+
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_in_scope(&["p"], Scope::Button) {
+            self.generate_implied_end_tags(Some("p"));
+            self.pop_until(&["p"]);
+        }
     }
 
-    fn consume_special_element(&mut self) -> Result<String, ()> {
-        let mut body = "".to_string();
-        while !self.eof() {
-            if self.starts_with("</") {
-                self.consume_while(|c| c != '>')?;
-                assert_eq!(self.consume_char()?, '>');
+    fn clear_stack_back_to(&mut self, tag_names: &[&str]) {
+        while !tag_names.contains(&self.current_tag_name()) && self.open_elements.len() > 1 {
+            self.pop();
+        }
+    }
+
+    fn pop_until(&mut self, tag_names: &[&str]) {
+        while let Some(id) = self.pop() {
+            if tag_names.contains(&self.tag_name(id)) {
                 break;
             }
-            body.push(self.consume_char()?);
         }
-        Ok(body)
     }
 
-    fn consume_whitespace(&mut self) -> Result<(), ()> {
-        self.consume_while(char::is_whitespace).and(Ok(()))
+    fn pop(&mut self) -> Option<NodeId> {
+        let id = self.open_elements.pop()?;
+        if is_scoping_formatting(self.tag_name(id)) {
+            while let Some(entry) = self.active_formatting_elements.pop() {
+                if entry == FormattingEntry::Marker {
+                    break;
+                }
+            }
+        }
+        Some(id)
+    }
+
+    fn current_node(&self) -> NodeId {
+        *self.open_elements.last().unwrap_or(&DOCUMENT)
     }
 
-    fn consume_while<F>(&mut self, f: F) -> Result<String, ()>
-    where
-        F: Fn(char) -> bool,
-    {
-        let mut s = "".to_string();
-        while !self.eof() && f(self.next_char()?) {
-            s.push(self.consume_char()?);
+    fn current_tag_name(&self) -> &str {
+        self.tag_name(self.current_node())
+    }
+
+    fn tag_name(&self, id: NodeId) -> &str {
+        match self.nodes[id].data {
+            ArenaData::Element(ref tag_name, _) => tag_name.as_str(),
+            ArenaData::Text(_) => "",
         }
-        Ok(s)
     }
 
-    fn consume_char(&mut self) -> Result<char, ()> {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().ok_or(())?;
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
-        Ok(cur_char)
+    fn merge_attrs(&mut self, id: NodeId, attrs: dom::AttrMap) {
+        if let ArenaData::Element(_, ref mut existing) = self.nodes[id].data {
+            for (name, value) in attrs {
+                existing.entry(name).or_insert(value);
+            }
+        }
     }
 
-    fn next_char(&self) -> Result<char, ()> {
-        self.input[self.pos..].chars().next().ok_or(())
+    fn create_node(&mut self, data: ArenaData) -> NodeId {
+        self.nodes.push(ArenaNode {
+            data,
+            parent: None,
+            children: vec![],
+        });
+        self.nodes.len() - 1
     }
 
-    fn starts_with(&self, s: &str) -> bool {
-        self.input[self.pos..].starts_with(s)
+    fn clone_element(&mut self, id: NodeId) -> NodeId {
+        let data = match self.nodes[id].data {
+            ArenaData::Element(ref tag_name, ref attrs) => {
+                ArenaData::Element(tag_name.clone(), attrs.clone())
+            }
+            ArenaData::Text(ref text) => ArenaData::Text(text.clone()),
+        };
+        self.create_node(data)
     }
 
-    fn eof(&self) -> bool {
-        self.pos >= self.input.len()
+    /// Where a new node goes: normally at the end of ``target``, but content that isn't allowed
+    /// directly in a table is "foster parented" in front of the table.
+    fn appropriate_place(&self, target: NodeId, foster: bool) -> (NodeId, Option<NodeId>) {
+        if foster && is_table_context(self.tag_name(target)) {
+            if let Some(pos) = self
+                .open_elements
+                .iter()
+                .rposition(|&id| self.tag_name(id) == "table")
+            {
+                let table = self.open_elements[pos];
+                return match self.nodes[table].parent {
+                    Some(parent) => (parent, Some(table)),
+                    None => (self.open_elements[pos.saturating_sub(1)], None),
+                };
+            }
+        }
+        (target, None)
+    }
+
+    fn insert_node(&mut self, parent: NodeId, before: Option<NodeId>, child: NodeId) {
+        self.detach(child);
+        let siblings = &mut self.nodes[parent].children;
+        let pos = before
+            .and_then(|before| siblings.iter().position(|&id| id == before))
+            .unwrap_or(siblings.len());
+        siblings.insert(pos, child);
+        self.nodes[child].parent = Some(parent);
+    }
+
+    fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.insert_node(parent, None, child)
+    }
+
+    fn detach(&mut self, child: NodeId) {
+        if let Some(parent) = self.nodes[child].parent.take() {
+            self.nodes[parent].children.retain(|&id| id != child);
+        }
+    }
+
+    fn insert(&mut self, tag_name: &str, attrs: dom::AttrMap) -> NodeId {
+        let current = self.current_node();
+        let foster = !is_table_part(tag_name) && !["table", "script", "style"].contains(&tag_name);
+        let (parent, before) = self.appropriate_place(current, foster);
+        if parent != current {
+            let message = format!("<{}> in <{}>", tag_name, self.tag_name(current));
+            self.error(message);
+        }
+        let id = self.create_node(ArenaData::Element(tag_name.to_string(), attrs));
+        self.insert_node(parent, before, id);
+        self.open_elements.push(id);
+        id
+    }
+
+    fn insert_void(&mut self, tag_name: &str, attrs: dom::AttrMap) {
+        self.insert(tag_name, attrs);
+        self.pop();
+    }
+
+    fn insert_text(&mut self, text: String) {
+        let current = self.current_node();
+        let foster = text.chars().any(|c| !is_html_whitespace(c));
+        let (parent, before) = self.appropriate_place(current, foster);
+        if parent != current {
+            let message = format!("text in <{}>", self.tag_name(current));
+            self.error(message);
+        }
+
+        let siblings = &self.nodes[parent].children;
+        let previous = match before {
+            Some(before) => siblings
+                .iter()
+                .position(|&id| id == before)
+                .and_then(|pos| pos.checked_sub(1))
+                .map(|pos| siblings[pos]),
+            None => siblings.last().cloned(),
+        };
+        if let Some(previous) = previous {
+            if let ArenaData::Text(ref mut existing) = self.nodes[previous].data {
+                existing.push_str(text.as_str());
+                return;
+            }
+        }
+
+        let id = self.create_node(ArenaData::Text(text));
+        self.insert_node(parent, before, id);
+    }
+
+    fn to_dom(&self, id: NodeId) -> dom::Node {
+        let (tag_name, attrs) = match self.nodes[id].data {
+            ArenaData::Element(ref tag_name, ref attrs) => (tag_name, attrs),
+            ArenaData::Text(ref text) => return dom::Node::text(text.clone()),
+        };
+        let raw_text = tag_name == "script" || tag_name == "style";
+
+        let mut children: Vec<dom::Node> = vec![];
+        for &child in &self.nodes[id].children {
+            match self.nodes[child].data {
+                ArenaData::Element(..) => children.push(self.to_dom(child)),
+                ArenaData::Text(ref text) if raw_text => {
+                    children.push(dom::Node::text(text.clone()))
+                }
+                ArenaData::Text(ref text) => {
+                    // TODO: Is this correct?
+                    let keep_leading_whitespace = match children.last() {
+                        Some(last) => last.is_inline() && last.contains_text(),
+                        None => false,
+                    };
+                    let collapsed = collapse_whitespace(text, keep_leading_whitespace);
+                    if !collapsed.is_empty() {
+                        children.push(dom::Node::text(collapsed));
+                    }
+                }
+            }
+        }
+
+        dom::Node::elem(tag_name.clone(), attrs.clone(), children)
     }
 }
 
@@ -371,6 +1491,118 @@ fn test_empty_source() {
     let dom_node = parse(src.to_string(), Path::new("a.html").to_path_buf());
     assert_eq!(
         dom_node,
-        dom::Node::elem("html".to_string(), HashMap::new(), vec![])
+        dom::Node::elem(
+            "html".to_string(),
+            HashMap::new(),
+            vec![
+                dom::Node::elem("head".to_string(), HashMap::new(), vec![]),
+                dom::Node::elem("body".to_string(), HashMap::new(), vec![]),
+            ]
+        )
+    );
+}
+
+#[test]
+fn test_character_references() {
+    use std::path::Path;
+    let src = "<p title=\"&quot;A&amp;B&quot;\">&lt;p&gt; &copy;&nbsp;2018 &#8212; &#x1F600;</p>";
+    let dom_node = parse(src.to_string(), Path::new("a.html").to_path_buf());
+    assert_eq!(
+        dom_node.find_first_node_by_tag_name("p").unwrap(),
+        &dom::Node::elem(
+            "p".to_string(),
+            {
+                let mut h = HashMap::new();
+                h.insert("title".to_string(), "\"A&B\"".to_string());
+                h
+            },
+            vec![dom::Node::text(
+                "<p> \u{a9}\u{a0}2018 \u{2014} \u{1F600}".to_string(),
+            )],
+        )
+    );
+}
+
+#[test]
+fn test_implied_end_tags() {
+    use std::path::Path;
+    let src = "<title>t</title><p>a<p>b</div><ul><li>1<li>2</ul><dl><dt>x<dd>y</dl>";
+    let dom_node = parse(src.to_string(), Path::new("a.html").to_path_buf());
+    let expected = [
+        "<html>",
+        "  <head>",
+        "    <title>",
+        "      #text: t",
+        "  <body>",
+        "    <p>",
+        "      #text: a",
+        "    <p>",
+        "      #text: b",
+        "    <ul>",
+        "      <li>",
+        "        #text: 1",
+        "      <li>",
+        "        #text: 2",
+        "    <dl>",
+        "      <dt>",
+        "        #text: x",
+        "      <dd>",
+        "        #text: y",
+    ];
+    assert_eq!(format!("{}", dom_node), expected.join("\n") + "\n");
+}
+
+#[test]
+fn test_misnested_formatting_elements() {
+    use std::path::Path;
+    let src = "<b>1<p>2</b>3</p><i>4<u>5</i>6</u>";
+    let dom_node = parse(src.to_string(), Path::new("a.html").to_path_buf());
+    let expected = [
+        "<html>",
+        "  <head>",
+        "  <body>",
+        "    <b>",
+        "      #text: 1",
+        "    <p>",
+        "      <b>",
+        "        #text: 2",
+        "      #text: 3",
+        "    <i>",
+        "      #text: 4",
+        "      <u>",
+        "        #text: 5",
+        "    <u>",
+        "      #text: 6",
+    ];
+    assert_eq!(format!("{}", dom_node), expected.join("\n") + "\n");
+}
+
+#[test]
+fn test_parse_errors() {
+    use std::path::Path;
+    let src = "<p>a</b>\n<table><tr>x</table>\n<div>";
+    let (_, errors) = parse_with_errors(src.to_string(), Path::new("a.html").to_path_buf());
+    let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "1:1: missing doctype",
+            "1:5: unexpected end tag </b>",
+            "2:12: text in <tr>",
+            "3:6: end of file with unclosed element <div>",
+        ]
+    );
+}
+
+#[test]
+fn test_remove_comments() {
+    let (s, errors) = remove_comments("a/* b /* c */ */d*/\ne/*é", "/*", "*/");
+    assert_eq!(s, "a               d*/\ne    ");
+    assert_eq!(
+        errors,
+        vec![
+            ParseError::new(1, 18, "\"*/\" outside of a comment"),
+            ParseError::new(2, 2, "unclosed comment"),
+        ]
     );
 }
@@ -0,0 +1,843 @@
+//! HTML tokenizer.
+//! ref. https://html.spec.whatwg.org/multipage/parsing.html#tokenization
+
+use entities::{LONGEST_NAME_LEN, NAMED_CHARACTER_REFERENCES};
+use parse_error::ParseError;
+
+use std::collections::VecDeque;
+use std::mem;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Doctype(String),
+    StartTag {
+        name: String,
+        attrs: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    EndTag {
+        name: String,
+    },
+    Comment(String),
+    Text(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Data,
+    RcData,
+    RawText,
+    PlainText,
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    BeforeAttributeName,
+    AttributeName,
+    AfterAttributeName,
+    BeforeAttributeValue,
+    AttributeValueDoubleQuoted,
+    AttributeValueSingleQuoted,
+    AttributeValueUnquoted,
+    AfterAttributeValueQuoted,
+    SelfClosingStartTag,
+    BogusComment,
+    MarkupDeclarationOpen,
+}
+
+pub struct Tokenizer {
+    input: Vec<char>,
+    pos: usize,
+    state: State,
+    // Tokens with the index of their first character.
+    tokens: VecDeque<(Token, usize)>,
+    finished: bool,
+
+    // Indices of the first character of each line.
+    line_starts: Vec<usize>,
+    errors: Vec<ParseError>,
+    last_token_start: usize,
+    text_start: usize,
+    tag_start: usize,
+    attr_start: usize,
+
+    // The token being built:
+    text: String,
+    comment: String,
+    tag_name: String,
+    is_end_tag: bool,
+    self_closing: bool,
+    attrs: Vec<(String, String)>,
+    attr_name: String,
+    attr_value: String,
+
+    // Used to find the appropriate end tag in RCDATA and RAWTEXT.
+    last_start_tag: String,
+}
+
+pub fn is_html_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n' || c == '\x0C' || c == '\r'
+}
+
+impl Tokenizer {
+    pub fn new(input: &str) -> Tokenizer {
+        // Normalize newlines before tokenization.
+        let input: Vec<char> = input
+            .replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .chars()
+            .collect();
+        let line_starts = ::std::iter::once(0)
+            .chain(
+                input
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &c)| c == '\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        Tokenizer {
+            input,
+            pos: 0,
+            state: State::Data,
+            tokens: VecDeque::new(),
+            finished: false,
+            line_starts,
+            errors: vec![],
+            last_token_start: 0,
+            text_start: 0,
+            tag_start: 0,
+            attr_start: 0,
+            text: String::new(),
+            comment: String::new(),
+            tag_name: String::new(),
+            is_end_tag: false,
+            self_closing: false,
+            attrs: vec![],
+            attr_name: String::new(),
+            attr_value: String::new(),
+            last_start_tag: String::new(),
+        }
+    }
+
+    /// Returns the parse errors found so far.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        mem::take(&mut self.errors)
+    }
+
+    /// The line and column where the token last returned by ``next`` starts.
+    pub fn last_token_location(&self) -> (usize, usize) {
+        self.location(self.last_token_start)
+    }
+
+    /// The line and column just past the end of the input.
+    pub fn end_location(&self) -> (usize, usize) {
+        self.location(self.input.len())
+    }
+
+    fn location(&self, index: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= index);
+        (line, index - self.line_starts[line - 1] + 1)
+    }
+
+    // Reports an error at the character consumed last. The messages are the error codes of the
+    // spec, e.g. "eof-in-tag".
+    fn error(&mut self, message: &str) {
+        let index = self.pos.saturating_sub(1);
+        self.error_at(index, message);
+    }
+
+    fn error_at(&mut self, index: usize, message: &str) {
+        let (line, column) = self.location(index);
+        self.errors.push(ParseError::new(line, column, message));
+    }
+
+    fn step(&mut self) {
+        // A `<` that doesn't start a tag turns out to be text only after the next character.
+        let start = if self.state == State::TagOpen {
+            self.tag_start
+        } else {
+            self.pos
+        };
+        let text_was_empty = self.text.is_empty();
+        self.step_state();
+        if text_was_empty && !self.text.is_empty() {
+            self.text_start = start;
+        }
+    }
+
+    fn step_state(&mut self) {
+        let c = self.consume_char();
+
+        match self.state {
+            State::Data => match c {
+                '&' => {
+                    let s = self.consume_character_reference(false);
+                    self.text.push_str(s.as_str());
+                }
+                '<' => {
+                    self.tag_start = self.pos - 1;
+                    self.state = State::TagOpen
+                }
+                '\0' => {
+                    self.error("unexpected-null-character");
+                    self.text.push('\u{FFFD}')
+                }
+                c => self.text.push(c),
+            },
+            State::RcData | State::RawText => match c {
+                '&' if self.state == State::RcData => {
+                    let s = self.consume_character_reference(false);
+                    self.text.push_str(s.as_str());
+                }
+                '<' if self.appropriate_end_tag_follows() => {
+                    self.tag_start = self.pos - 1;
+                    self.pos += 1 + self.last_start_tag.chars().count(); // '/' and tag name
+                    self.new_tag(true);
+                    self.tag_name = self.last_start_tag.clone();
+                    self.state = State::TagName;
+                }
+                '\0' => {
+                    self.error("unexpected-null-character");
+                    self.text.push('\u{FFFD}')
+                }
+                c => self.text.push(c),
+            },
+            State::PlainText => {
+                if c == '\0' {
+                    self.error("unexpected-null-character");
+                }
+                self.text.push(if c == '\0' { '\u{FFFD}' } else { c })
+            }
+            State::TagOpen => match c {
+                '!' => self.state = State::MarkupDeclarationOpen,
+                '/' => self.state = State::EndTagOpen,
+                c if c.is_ascii_alphabetic() => {
+                    self.new_tag(false);
+                    self.reconsume_in(State::TagName);
+                }
+                '?' => {
+                    self.error("unexpected-question-mark-instead-of-tag-name");
+                    self.comment.clear();
+                    self.reconsume_in(State::BogusComment);
+                }
+                _ => {
+                    self.error("invalid-first-character-of-tag-name");
+                    self.text.push('<');
+                    self.reconsume_in(State::Data);
+                }
+            },
+            State::EndTagOpen => match c {
+                c if c.is_ascii_alphabetic() => {
+                    self.new_tag(true);
+                    self.reconsume_in(State::TagName);
+                }
+                // Missing end tag name; `</>` is ignored.
+                '>' => {
+                    self.error("missing-end-tag-name");
+                    self.state = State::Data
+                }
+                _ => {
+                    self.error("invalid-first-character-of-tag-name");
+                    self.comment.clear();
+                    self.reconsume_in(State::BogusComment);
+                }
+            },
+            State::TagName => match c {
+                c if is_html_whitespace(c) => self.state = State::BeforeAttributeName,
+                '/' => self.state = State::SelfClosingStartTag,
+                '>' => self.emit_tag(),
+                '\0' => {
+                    self.error("unexpected-null-character");
+                    self.tag_name.push('\u{FFFD}')
+                }
+                c => self.tag_name.push(c.to_ascii_lowercase()),
+            },
+            State::BeforeAttributeName => match c {
+                c if is_html_whitespace(c) => {}
+                '/' | '>' => self.reconsume_in(State::AfterAttributeName),
+                '=' => {
+                    self.error("unexpected-equals-sign-before-attribute-name");
+                    self.new_attr();
+                    self.attr_name.push(c);
+                    self.state = State::AttributeName;
+                }
+                _ => {
+                    self.new_attr();
+                    self.reconsume_in(State::AttributeName);
+                }
+            },
+            State::AttributeName => match c {
+                c if is_html_whitespace(c) || c == '/' || c == '>' => {
+                    self.reconsume_in(State::AfterAttributeName)
+                }
+                '=' => self.state = State::BeforeAttributeValue,
+                '\0' => {
+                    self.error("unexpected-null-character");
+                    self.attr_name.push('\u{FFFD}')
+                }
+                c => {
+                    if c == '"' || c == '\'' || c == '<' {
+                        self.error("unexpected-character-in-attribute-name");
+                    }
+                    self.attr_name.push(c.to_ascii_lowercase())
+                }
+            },
+            State::AfterAttributeName => match c {
+                c if is_html_whitespace(c) => {}
+                '/' => self.state = State::SelfClosingStartTag,
+                '=' => self.state = State::BeforeAttributeValue,
+                '>' => self.emit_tag(),
+                _ => {
+                    self.new_attr();
+                    self.reconsume_in(State::AttributeName);
+                }
+            },
+            State::BeforeAttributeValue => match c {
+                c if is_html_whitespace(c) => {}
+                '"' => self.state = State::AttributeValueDoubleQuoted,
+                '\'' => self.state = State::AttributeValueSingleQuoted,
+                '>' => {
+                    self.error("missing-attribute-value");
+                    self.emit_tag()
+                }
+                _ => self.reconsume_in(State::AttributeValueUnquoted),
+            },
+            State::AttributeValueDoubleQuoted | State::AttributeValueSingleQuoted => match c {
+                '"' if self.state == State::AttributeValueDoubleQuoted => {
+                    self.state = State::AfterAttributeValueQuoted
+                }
+                '\'' if self.state == State::AttributeValueSingleQuoted => {
+                    self.state = State::AfterAttributeValueQuoted
+                }
+                '&' => {
+                    let s = self.consume_character_reference(true);
+                    self.attr_value.push_str(s.as_str());
+                }
+                '\0' => {
+                    self.error("unexpected-null-character");
+                    self.attr_value.push('\u{FFFD}')
+                }
+                c => self.attr_value.push(c),
+            },
+            State::AttributeValueUnquoted => match c {
+                c if is_html_whitespace(c) => self.state = State::BeforeAttributeName,
+                '&' => {
+                    let s = self.consume_character_reference(true);
+                    self.attr_value.push_str(s.as_str());
+                }
+                '>' => self.emit_tag(),
+                '\0' => {
+                    self.error("unexpected-null-character");
+                    self.attr_value.push('\u{FFFD}')
+                }
+                c => self.attr_value.push(c),
+            },
+            State::AfterAttributeValueQuoted => match c {
+                c if is_html_whitespace(c) => self.state = State::BeforeAttributeName,
+                '/' => self.state = State::SelfClosingStartTag,
+                '>' => self.emit_tag(),
+                _ => {
+                    self.error("missing-whitespace-between-attributes");
+                    self.reconsume_in(State::BeforeAttributeName)
+                }
+            },
+            State::SelfClosingStartTag => match c {
+                '>' => {
+                    self.self_closing = true;
+                    self.emit_tag()
+                }
+                _ => {
+                    self.error("unexpected-solidus-in-tag");
+                    self.reconsume_in(State::BeforeAttributeName)
+                }
+            },
+            State::BogusComment => match c {
+                '>' => self.emit_comment(),
+                '\0' => {
+                    self.error("unexpected-null-character");
+                    self.comment.push('\u{FFFD}')
+                }
+                c => self.comment.push(c),
+            },
+            State::MarkupDeclarationOpen => {
+                self.pos -= 1;
+                if self.starts_with("--", false) {
+                    self.pos += 2;
+                    self.consume_comment();
+                } else if self.starts_with("doctype", true) {
+                    self.pos += "doctype".len();
+                    self.consume_doctype();
+                } else {
+                    // Including `<![CDATA[`, which is only allowed in foreign content.
+                    self.error("incorrectly-opened-comment");
+                    self.comment.clear();
+                    self.state = State::BogusComment;
+                }
+            }
+        }
+    }
+
+    /// Called once the whole input has been consumed.
+    fn finish(&mut self) {
+        match self.state {
+            State::Data | State::RcData | State::RawText | State::PlainText => {}
+            State::TagOpen | State::EndTagOpen => {
+                self.error("eof-before-tag-name");
+                if self.text.is_empty() {
+                    self.text_start = self.tag_start;
+                }
+                self.text.push_str(if self.state == State::TagOpen {
+                    "<"
+                } else {
+                    "</"
+                });
+            }
+            State::BogusComment | State::MarkupDeclarationOpen => self.emit_comment(),
+            // The tag is dropped.
+            _ => self.error("eof-in-tag"),
+        }
+        self.flush_text();
+        self.finished = true;
+    }
+
+    fn new_tag(&mut self, is_end_tag: bool) {
+        self.tag_name.clear();
+        self.is_end_tag = is_end_tag;
+        self.self_closing = false;
+        self.attrs.clear();
+        self.attr_name.clear();
+        self.attr_value.clear();
+    }
+
+    fn new_attr(&mut self) {
+        self.finish_attr();
+        self.attr_start = self.pos.saturating_sub(1);
+        self.attr_name.clear();
+        self.attr_value.clear();
+    }
+
+    fn finish_attr(&mut self) {
+        if self.attr_name.is_empty() {
+            return;
+        }
+        // When there are duplicate attributes, the first one wins.
+        if self
+            .attrs
+            .iter()
+            .all(|&(ref name, _)| *name != self.attr_name)
+        {
+            self.attrs
+                .push((self.attr_name.clone(), self.attr_value.clone()));
+        } else {
+            let index = self.attr_start;
+            self.error_at(index, "duplicate-attribute");
+        }
+        self.attr_name.clear();
+        self.attr_value.clear();
+    }
+
+    fn emit_tag(&mut self) {
+        self.finish_attr();
+        self.flush_text();
+        self.state = State::Data;
+
+        let name = self.tag_name.clone();
+        if self.is_end_tag {
+            if !self.attrs.is_empty() {
+                self.error("end-tag-with-attributes");
+            }
+            self.tokens
+                .push_back((Token::EndTag { name: name }, self.tag_start));
+            return;
+        }
+
+        // The tree construction stage would switch the tokenizer state for these elements.
+        self.state = match name.as_str() {
+            "title" | "textarea" => State::RcData,
+            "style" | "script" | "xmp" | "iframe" | "noembed" | "noframes" => State::RawText,
+            "plaintext" => State::PlainText,
+            _ => State::Data,
+        };
+        self.last_start_tag = name.clone();
+        self.tokens.push_back((
+            Token::StartTag {
+                name: name,
+                attrs: self.attrs.clone(),
+                self_closing: self.self_closing,
+            },
+            self.tag_start,
+        ));
+    }
+
+    fn emit_comment(&mut self) {
+        self.flush_text();
+        self.tokens
+            .push_back((Token::Comment(self.comment.clone()), self.tag_start));
+        self.comment.clear();
+        self.state = State::Data;
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            self.tokens
+                .push_back((Token::Text(self.text.clone()), self.text_start));
+            self.text.clear();
+        }
+    }
+
+    // `<!--` has been consumed.
+    fn consume_comment(&mut self) {
+        self.comment.clear();
+        // Abruptly closed empty comments: `<!-->` and `<!--->`
+        for abrupt_end in &[">", "->"] {
+            if self.starts_with(abrupt_end, false) {
+                self.pos += abrupt_end.len();
+                self.error("abrupt-closing-of-empty-comment");
+                return self.emit_comment();
+            }
+        }
+        while self.pos < self.input.len() {
+            for end in &["-->", "--!>"] {
+                if self.starts_with(end, false) {
+                    self.pos += end.len();
+                    if *end == "--!>" {
+                        self.error("incorrectly-closed-comment");
+                    }
+                    return self.emit_comment();
+                }
+            }
+            let c = self.consume_char();
+            if c == '\0' {
+                self.error("unexpected-null-character");
+            }
+            self.comment.push(if c == '\0' { '\u{FFFD}' } else { c });
+        }
+        self.error("eof-in-comment");
+        self.emit_comment();
+    }
+
+    // `<!DOCTYPE` has been consumed. Public and system identifiers are not kept.
+    fn consume_doctype(&mut self) {
+        self.flush_text();
+        while self.pos < self.input.len() && is_html_whitespace(self.input[self.pos]) {
+            self.pos += 1;
+        }
+        let mut name = String::new();
+        let mut closed = false;
+        while self.pos < self.input.len() {
+            let c = self.consume_char();
+            if c == '>' {
+                closed = true;
+                break;
+            }
+            if is_html_whitespace(c) {
+                while self.pos < self.input.len() {
+                    if self.consume_char() == '>' {
+                        closed = true;
+                        break;
+                    }
+                }
+                break;
+            }
+            name.push(c.to_ascii_lowercase());
+        }
+        if !closed {
+            self.error("eof-in-doctype");
+        }
+        self.tokens
+            .push_back((Token::Doctype(name), self.tag_start));
+        self.state = State::Data;
+    }
+
+    /// Consumes a character reference after `&` and returns the characters it stands for.
+    /// If there is no character reference, returns `&` and leaves the input as it is.
+    /// ref. https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+    fn consume_character_reference(&mut self, in_attribute: bool) -> String {
+        match self.peek_char(0) {
+            Some('#') => self.consume_numeric_character_reference(),
+            Some(c) if c.is_ascii_alphanumeric() => {
+                self.consume_named_character_reference(in_attribute)
+            }
+            _ => "&".to_string(),
+        }
+    }
+
+    fn consume_named_character_reference(&mut self, in_attribute: bool) -> String {
+        let mut candidate = String::new();
+        while candidate.len() < LONGEST_NAME_LEN {
+            match self.peek_char(candidate.len()) {
+                Some(c) if c.is_ascii_alphanumeric() => candidate.push(c),
+                Some(';') => {
+                    candidate.push(';');
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        // Find the longest name that matches.
+        let mut len = candidate.len();
+        while len > 0 {
+            let name = &candidate[..len];
+            if let Ok(i) = NAMED_CHARACTER_REFERENCES.binary_search_by(|&(n, _)| n.cmp(name)) {
+                let next = self.peek_char(len);
+                // For historical reasons, `&amp=` and `&ampx` in attribute values are left as is.
+                if in_attribute
+                    && !name.ends_with(';')
+                    && next.map_or(false, |c| c == '=' || c.is_ascii_alphanumeric())
+                {
+                    return "&".to_string();
+                }
+                self.pos += len;
+                if !name.ends_with(';') {
+                    self.error("missing-semicolon-after-character-reference");
+                }
+                return NAMED_CHARACTER_REFERENCES[i].1.to_string();
+            }
+            len -= 1;
+        }
+
+        if candidate.ends_with(';') {
+            self.error("unknown-named-character-reference");
+        }
+        "&".to_string()
+    }
+
+    fn consume_numeric_character_reference(&mut self) -> String {
+        let start = self.pos;
+        self.pos += 1; // '#'
+
+        let hex = match self.peek_char(0) {
+            Some('x') | Some('X') => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        };
+        let radix = if hex { 16 } else { 10 };
+
+        let mut code: u32 = 0;
+        let mut digits = 0;
+        while let Some(d) = self.peek_char(0).and_then(|c| c.to_digit(radix)) {
+            code = code.saturating_mul(radix).saturating_add(d);
+            digits += 1;
+            self.pos += 1;
+        }
+
+        if digits == 0 {
+            // Not a character reference: `&#` or `&#x` is left as text.
+            self.error("absence-of-digits-in-numeric-character-reference");
+            self.pos = start;
+            return "&".to_string();
+        }
+
+        if self.peek_char(0) == Some(';') {
+            self.pos += 1;
+        } else {
+            self.error("missing-semicolon-after-character-reference");
+        }
+
+        let c = match code {
+            0 => {
+                self.error("null-character-reference");
+                '\u{FFFD}'
+            }
+            0xD800..=0xDFFF => {
+                self.error("surrogate-character-reference");
+                '\u{FFFD}'
+            }
+            0x80..=0x9F => {
+                self.error("control-character-reference");
+                windows_1252(code)
+            }
+            code => ::std::char::from_u32(code).unwrap_or_else(|| {
+                self.error("character-reference-outside-unicode-range");
+                '\u{FFFD}'
+            }),
+        };
+        c.to_string()
+    }
+
+    fn appropriate_end_tag_follows(&self) -> bool {
+        if self.peek_char(0) != Some('/') || self.last_start_tag.is_empty() {
+            return false;
+        }
+        let len = self.last_start_tag.chars().count();
+        let name_matches = self
+            .last_start_tag
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_char(1 + i).map(|c| c.to_ascii_lowercase()) == Some(c));
+        name_matches
+            && match self.peek_char(1 + len) {
+                Some(c) => is_html_whitespace(c) || c == '/' || c == '>',
+                None => false,
+            }
+    }
+
+    fn reconsume_in(&mut self, state: State) {
+        self.pos -= 1;
+        self.state = state;
+    }
+
+    fn consume_char(&mut self) -> char {
+        let c = self.input[self.pos];
+        self.pos += 1;
+        c
+    }
+
+    /// `peek_char(0)` is the next input character.
+    fn peek_char(&self, offset: usize) -> Option<char> {
+        self.input.get(self.pos + offset).cloned()
+    }
+
+    fn starts_with(&self, s: &str, ignore_case: bool) -> bool {
+        s.chars().enumerate().all(|(i, c)| match self.peek_char(i) {
+            Some(d) if ignore_case => c.eq_ignore_ascii_case(&d),
+            Some(d) => c == d,
+            None => false,
+        })
+    }
+}
+
+impl Iterator for Tokenizer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while self.tokens.is_empty() && !self.finished {
+            if self.pos < self.input.len() {
+                self.step();
+            } else {
+                self.finish();
+            }
+        }
+        let (token, start) = self.tokens.pop_front()?;
+        self.last_token_start = start;
+        Some(token)
+    }
+}
+
+// ref. https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+fn windows_1252(code: u32) -> char {
+    let c = match code {
+        0x80 => 0x20AC,
+        0x82 => 0x201A,
+        0x83 => 0x0192,
+        0x84 => 0x201E,
+        0x85 => 0x2026,
+        0x86 => 0x2020,
+        0x87 => 0x2021,
+        0x88 => 0x02C6,
+        0x89 => 0x2030,
+        0x8A => 0x0160,
+        0x8B => 0x2039,
+        0x8C => 0x0152,
+        0x8E => 0x017D,
+        0x91 => 0x2018,
+        0x92 => 0x2019,
+        0x93 => 0x201C,
+        0x94 => 0x201D,
+        0x95 => 0x2022,
+        0x96 => 0x2013,
+        0x97 => 0x2014,
+        0x98 => 0x02DC,
+        0x99 => 0x2122,
+        0x9A => 0x0161,
+        0x9B => 0x203A,
+        0x9C => 0x0153,
+        0x9E => 0x017E,
+        0x9F => 0x0178,
+        code => code,
+    };
+    ::std::char::from_u32(c).unwrap()
+}
+
+#[test]
+fn test_character_references() {
+    let tokens: Vec<Token> = Tokenizer::new(
+        "&amp;&lt;&nbsp;&#169;&#x2014;&#X41;&notit;&amp &ampx &#0;&#x80;&unknown; &#;",
+    )
+    .collect();
+    assert_eq!(
+        tokens,
+        vec![Token::Text(
+            "&<\u{a0}\u{a9}\u{2014}A\u{ac}it;& &x \u{FFFD}\u{20AC}&unknown; &#;".to_string()
+        )]
+    );
+
+    let tokens: Vec<Token> =
+        Tokenizer::new("<a href=\"?a=1&amp;b=2&copy=3\" title='&lt;&gt'>").collect();
+    assert_eq!(
+        tokens,
+        vec![Token::StartTag {
+            name: "a".to_string(),
+            attrs: vec![
+                ("href".to_string(), "?a=1&b=2&copy=3".to_string()),
+                ("title".to_string(), "<>".to_string()),
+            ],
+            self_closing: false,
+        }]
+    );
+}
+
+#[test]
+fn test_tags() {
+    let tokens: Vec<Token> = Tokenizer::new(
+        "<!DOCTYPE html><!-- c --><DIV Class=a id=\"b\" class=c hidden><br/></div x>\
+         <style>a < b &amp; </b></style></>x<3",
+    )
+    .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Doctype("html".to_string()),
+            Token::Comment(" c ".to_string()),
+            Token::StartTag {
+                name: "div".to_string(),
+                attrs: vec![
+                    ("class".to_string(), "a".to_string()),
+                    ("id".to_string(), "b".to_string()),
+                    ("hidden".to_string(), "".to_string()),
+                ],
+                self_closing: false,
+            },
+            Token::StartTag {
+                name: "br".to_string(),
+                attrs: vec![],
+                self_closing: true,
+            },
+            Token::EndTag {
+                name: "div".to_string(),
+            },
+            Token::StartTag {
+                name: "style".to_string(),
+                attrs: vec![],
+                self_closing: false,
+            },
+            Token::Text("a < b &amp; </b>".to_string()),
+            Token::EndTag {
+                name: "style".to_string(),
+            },
+            Token::Text("x<3".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_error_locations() {
+    let mut tokenizer = Tokenizer::new("<a b=1 b=2>\n&foo; <!-x>");
+    while tokenizer.next().is_some() {}
+    let errors: Vec<String> = tokenizer
+        .take_errors()
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            "1:8: duplicate-attribute",
+            "2:1: unknown-named-character-reference",
+            "2:8: incorrectly-opened-comment",
+        ]
+    );
+}
@@ -1,6 +1,5 @@
 use css::Value;
 use dom::NodeType;
-use font::Font;
 use layout::{BoxType, Dimensions, ImageData, LayoutBox, LayoutInfo, Rect, Text};
 use float::Floats;
 
@@ -469,12 +468,9 @@ impl LineMaker {
 
         let mut new_layoutbox = layoutbox.clone();
 
-        let font_size = new_layoutbox.property.font_size();
         let line_height = new_layoutbox.property.line_height();
-        let font_weight = new_layoutbox.property.font_weight();
-        let font_slant = new_layoutbox.property.font_style();
 
-        let my_font = Font::new(font_size, font_weight, font_slant);
+        let my_font = new_layoutbox.property.font();
         let text_width = Au::from_f64_px(my_font.text_width(text));
         let (ascent, descent) = my_font.get_ascent_descent();
 
@@ -498,7 +494,7 @@ impl LineMaker {
             new_layoutbox.dimensions.content.height = ascent + descent;
 
             new_layoutbox.set_text_info(
-                Font::new(font_size, font_weight, font_slant),
+                my_font.clone(),
                 self.pending.range.start..self.pending.range.start + max_chars,
             );
             self.new_boxes.push(new_layoutbox);
@@ -514,7 +510,7 @@ impl LineMaker {
             new_layoutbox.dimensions.content.height = ascent + descent;
 
             new_layoutbox.set_text_info(
-                Font::new(font_size, font_weight, font_slant),
+                my_font.clone(),
                 self.pending.range.start..text.len() + self.pending.range.start,
             );
             self.new_boxes.push(new_layoutbox);
@@ -559,7 +555,7 @@ impl LayoutBox {
         let height = self.dimensions.content.height;
         match self.get_first_text_node() {
             Some(node) => match node.box_type {
-                BoxType::TextNode(Text { font, .. }) => font.get_ascent_descent().0,
+                BoxType::TextNode(Text { ref font, .. }) => font.get_ascent_descent().0,
                 _ => unreachable!(),
             },
             None => height,
@@ -600,8 +596,8 @@ impl LayoutBox {
     pub fn calculate_inline_block_width(&mut self, _containing_block: Dimensions) {
         // `width` has initial value `auto`.
         // TODO: Implement calculating shrink-to-fit width
-        if let Some(x) = self.property.value("width") {
-            self.dimensions.content.width = Au::from_f64_px(x[0].to_px().unwrap());
+        if let Some(width) = self.property.width() {
+            self.dimensions.content.width = Au::from_f64_px(width.to_px().unwrap());
         } else {
             // TODO
             // width == auto
@@ -662,7 +658,7 @@ pub fn get_pixbuf(node: &Node) -> gdk_pixbuf::Pixbuf {
         c.borrow_mut()
             .entry(image_url.clone())
             .or_insert_with(|| {
-                let (cache_name, _) = download(image_url.as_str());
+                let (cache_name, _) = download(image_url.as_str()).unwrap();
                 gdk_pixbuf::Pixbuf::from_file(cache_name.as_str()).unwrap()
             })
             .clone()
@@ -1,12 +1,15 @@
 use css;
 use dom;
+use font;
 use gtk::traits::WidgetExt;
+use headless;
 use html;
 use layout;
 use painter;
 use window;
 
 use std::fs::OpenOptions;
+use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
@@ -33,30 +36,35 @@ macro_rules! debug_println {
 ///  Returns (downloaded file name, file path(URL without ``http(s)://domain/``)).
 /// If ``url_str`` starts with ``file://``, does nothing especially.
 ///  Just returns (local file name, local file path).
-pub fn download(url_str: &str) -> (String, PathBuf) {
-    let url = HTML_SRC_URL.with(|html_src_url| {
-        let mut html_src_url = html_src_url.borrow_mut();
-        if let Ok(parsed) = Url::parse(url_str) {
-            // If url_str is absolute URL(starts with scheme://)
-            *html_src_url = Some(url_str.to_string());
-            return parsed;
-        } else if let Some(ref mut html_src_url) = *html_src_url {
-            let mut url = Url::parse(html_src_url.as_str()).unwrap();
-            url.set_path(url_str);
-            return url;
-        }
-        *html_src_url = Some(url_str.to_string());
-        Url::parse(url_str).unwrap()
-    });
+/// A relative ``url_str`` is a path on the host of the current document. Unlike
+/// ``update_html_source``, this never changes the document URL.
+pub fn download(url_str: &str) -> io::Result<(String, PathBuf)> {
+    fetch(&resolve_url(url_str))
+}
+
+// Resolves ``url_str`` against the URL of the current document.
+fn resolve_url(url_str: &str) -> Url {
+    if let Ok(url) = Url::parse(url_str) {
+        // If url_str is absolute URL(starts with scheme://)
+        return url;
+    }
+    let mut url = HTML_SRC_URL
+        .with(|html_src_url| html_src_url.borrow().clone())
+        .and_then(|html_src_url| Url::parse(html_src_url.as_str()).ok())
+        .unwrap_or_else(|| panic!("cannot resolve '{}' without a document", url_str));
+    url.set_path(url_str);
+    url
+}
 
+fn fetch(url: &Url) -> io::Result<(String, PathBuf)> {
     match url.scheme().to_ascii_lowercase().as_str() {
-        "file" => (url.path().to_string(), Path::new(url.path()).to_path_buf()),
+        "file" => Ok((url.path().to_string(), Path::new(url.path()).to_path_buf())),
         "http" | "https" => {
             let mut content: Vec<u8> = vec![];
             reqwest::get(url.clone())
-                .unwrap()
-                .copy_to(&mut content)
-                .unwrap();
+                .and_then(|response| response.error_for_status())
+                .and_then(|mut response| response.copy_to(&mut content))
+                .map_err(io::Error::other)?;
 
             let path = Path::new(url.path());
             let tmpfile_name = format!(
@@ -74,12 +82,13 @@ pub fn download(url_str: &str) -> (String, PathBuf) {
 
             debug_println!("downloaded {}", url.as_str());
 
-            let mut f = BufWriter::new(fs::File::create(tmpfile_name.as_str()).unwrap());
-            f.write_all(content.as_slice()).unwrap();
+            let mut f = BufWriter::new(fs::File::create(tmpfile_name.as_str())?);
+            f.write_all(content.as_slice())?;
+            f.flush()?;
 
-            (tmpfile_name, path.to_path_buf())
+            Ok((tmpfile_name, path.to_path_buf()))
         }
-        _ => unimplemented!(),
+        scheme => Err(io::Error::other(format!("unsupported scheme '{}'", scheme))),
     }
 }
 
@@ -91,13 +100,206 @@ thread_local!(
         RefCell::new((Au(0), Au(0), vec![]));
     static HTML_SRC_URL: RefCell<Option<String>> = RefCell::new(None);
     static HTML_TREE: Rc<RefCell<Option<dom::Node>>> = Rc::new(RefCell::new(None));
-    static STYLESHEET: Rc<RefCell<Option<css::Stylesheet>>> = Rc::new(RefCell::new(None));
+    static STYLESHEETS: Rc<RefCell<Option<Vec<css::Stylesheet>>>> = Rc::new(RefCell::new(None));
+    static USER_STYLESHEET_PATH: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    static USER_STYLESHEETS: RefCell<Vec<css::Stylesheet>> = const { RefCell::new(vec![]) };
 );
 
+/// Sets the file of the user stylesheet, e.g. for larger fonts or high contrast. It overrides
+/// the default style, and its ``!important`` declarations override the page's. The file is
+/// read again whenever a page is loaded.
+pub fn set_user_stylesheet_path(path: Option<PathBuf>) {
+    USER_STYLESHEET_PATH.with(|p| *p.borrow_mut() = path);
+}
+
+// Returns the user stylesheet after the stylesheets it imports.
+fn load_user_stylesheets() -> Vec<css::Stylesheet> {
+    let path = match USER_STYLESHEET_PATH.with(|p| p.borrow().clone()) {
+        Some(path) => path,
+        None => return vec![],
+    };
+    let url = match fs::canonicalize(&path) {
+        Ok(path) => Url::from_file_path(path).unwrap(),
+        Err(e) => {
+            println!(
+                "*** Cannot read the user stylesheet {}: {} ***",
+                path.display(),
+                e
+            );
+            return vec![];
+        }
+    };
+    let mut stylesheets = vec![];
+    load_stylesheet_from_url(url, &[], &mut vec![], &mut stylesheets);
+    stylesheets
+}
+
 static mut SRC_UPDATED: bool = false;
 
+/// Loads the stylesheets of every ``<link>`` and ``<style>`` in ``html_tree`` in document order,
+/// each after the stylesheets it imports.
+fn load_stylesheets(html_tree: &dom::Node) -> Vec<css::Stylesheet> {
+    let document_url = HTML_SRC_URL
+        .with(|url| url.borrow().clone())
+        .and_then(|url| Url::parse(url.as_str()).ok());
+    let mut stylesheets = vec![];
+    for stylesheet in html_tree.find_stylesheets() {
+        let media: Vec<css::MediaQueryList> = stylesheet
+            .media
+            .iter()
+            .map(|media| css::parse_media_query_list(media))
+            .collect();
+        match stylesheet.source {
+            dom::StylesheetSource::Link(href) => {
+                match Url::options()
+                    .base_url(document_url.as_ref())
+                    .parse(href.as_str())
+                {
+                    Ok(url) => load_stylesheet_from_url(url, &media, &mut vec![], &mut stylesheets),
+                    Err(e) => println!("*** Cannot load the stylesheet {}: {} ***", href, e),
+                }
+            }
+            dom::StylesheetSource::Style(css_source) => load_stylesheet(
+                css::parse(css_source),
+                document_url.as_ref(),
+                &media,
+                &mut vec![],
+                &mut stylesheets,
+            ),
+        }
+    }
+    stylesheets
+}
+
+// Appends the stylesheet at ``url`` to ``stylesheets``, after the stylesheets it imports.
+// ``media`` holds the media query lists of the ``<link>`` and ``@import`` rules it is loaded
+// through, and ``loading`` the stylesheets that import it, directly or not.
+fn load_stylesheet_from_url(
+    url: Url,
+    media: &[css::MediaQueryList],
+    loading: &mut Vec<Url>,
+    stylesheets: &mut Vec<css::Stylesheet>,
+) {
+    match read_url(&url) {
+        Ok(css_source) => {
+            loading.push(url.clone());
+            load_stylesheet(
+                css::parse(css_source),
+                Some(&url),
+                media,
+                loading,
+                stylesheets,
+            );
+            loading.pop();
+        }
+        Err(e) => println!("*** Cannot load the stylesheet {}: {} ***", url, e),
+    }
+}
+
+// Like ``load_stylesheet_from_url``, for a stylesheet that is already parsed. Its imports are
+// resolved against ``base_url``.
+fn load_stylesheet(
+    mut stylesheet: css::Stylesheet,
+    base_url: Option<&Url>,
+    media: &[css::MediaQueryList],
+    loading: &mut Vec<Url>,
+    stylesheets: &mut Vec<css::Stylesheet>,
+) {
+    // The media queries are evaluated during layout, so that resizing the window re-evaluates them.
+    for rule in &mut stylesheet.rules {
+        rule.media.splice(0..0, media.iter().cloned());
+    }
+    for font_face in &mut stylesheet.font_faces {
+        font_face.media.splice(0..0, media.iter().cloned());
+    }
+    // The font files are relative to the stylesheet too, see ``load_font_faces``.
+    for font_face in &mut stylesheet.font_faces {
+        for source in &mut font_face.sources {
+            if let css::FontFaceSource::Url(ref mut url) = *source {
+                if let Ok(absolute) = Url::options().base_url(base_url).parse(url.as_str()) {
+                    *url = absolute.to_string();
+                }
+            }
+        }
+    }
+    debug_println!("{}", stylesheet);
+    for import in &stylesheet.imports {
+        let mut import_media = media.to_vec();
+        if !import.media.0.is_empty() {
+            import_media.push(import.media.clone());
+        }
+        let url = match Url::options().base_url(base_url).parse(import.url.as_str()) {
+            Ok(url) => url,
+            Err(e) => {
+                println!("*** Cannot load the stylesheet {}: {} ***", import.url, e);
+                continue;
+            }
+        };
+        if loading.contains(&url) {
+            println!("*** Skipped the cyclic @import of {} ***", url);
+            continue;
+        }
+        load_stylesheet_from_url(url, &import_media, loading, stylesheets);
+    }
+    stylesheets.push(stylesheet);
+}
+
+// Registers the fonts of the ``@font-face`` rules in ``stylesheets``, each from the first of its
+// sources that can be loaded. Like those of rules, their media queries are evaluated during
+// layout.
+fn load_font_faces<'a, I>(stylesheets: I)
+where
+    I: IntoIterator<Item = &'a css::Stylesheet>,
+{
+    font::clear_font_faces();
+    for font_face in stylesheets
+        .into_iter()
+        .flat_map(|stylesheet| &stylesheet.font_faces)
+    {
+        let weight = font_face.weight.to_font_weight();
+        let slant = font_face.style.to_font_slant();
+        let media = &font_face.media;
+        let loaded = font_face.sources.iter().any(|source| match *source {
+            css::FontFaceSource::Local(ref name) => {
+                font::add_local_font(&font_face.family, name, weight, slant, media)
+            }
+            css::FontFaceSource::Url(ref url) => {
+                match Url::parse(url)
+                    .map_err(io::Error::other)
+                    .and_then(|url| fetch(&url))
+                {
+                    Ok((cache_name, _)) => font::add_font_file(
+                        &font_face.family,
+                        Path::new(&cache_name),
+                        weight,
+                        slant,
+                        media,
+                    ),
+                    Err(e) => {
+                        println!("*** Cannot load the font file {}: {} ***", url, e);
+                        false
+                    }
+                }
+            }
+        });
+        if !loaded {
+            println!("*** Cannot load the font {:?} ***", font_face.family);
+        }
+    }
+    font::reload_fonts();
+}
+
+fn read_url(url: &Url) -> io::Result<String> {
+    let (cache_name, _) = fetch(url)?;
+    fs::read_to_string(cache_name)
+}
+
 pub fn update_html_source(html_src: String) {
-    let (html_src_cache_name, html_src_path) = download(html_src.as_str());
+    // Relative to the current document, as with links.
+    let html_src_url = resolve_url(html_src.as_str());
+    HTML_SRC_URL.with(|url| *url.borrow_mut() = Some(html_src_url.to_string()));
+    let (html_src_cache_name, html_src_path) =
+        fetch(&html_src_url).unwrap_or_else(|e| panic!("cannot load {}: {}", html_src_url, e));
 
     debug_println!("HTML:");
     let mut html_source = "".to_string();
@@ -112,28 +314,15 @@ pub fn update_html_source(html_src: String) {
     debug_println!("{}", html_tree);
 
     debug_println!("CSS:");
-    let mut css_source = "".to_string();
-    if let Some(stylesheet_path) = html_tree.find_stylesheet_path() {
-        let (css_cache_name, _) = download(stylesheet_path.to_str().unwrap());
-        OpenOptions::new()
-            .read(true)
-            .open(css_cache_name)
-            .unwrap()
-            .read_to_string(&mut css_source)
-            .ok()
-            .expect("cannot read file");
-    } else if let Some(stylesheet_str) = html_tree.find_stylesheet_in_style_tag() {
-        css_source = stylesheet_str;
-    } else {
-        debug_println!("*** Not found any stylesheet but continue ***");
-    }
-    let stylesheet = css::parse(css_source);
-    debug_println!("{}", stylesheet);
+    let stylesheets = load_stylesheets(&html_tree);
+    let user_stylesheets = load_user_stylesheets();
+    load_font_faces(user_stylesheets.iter().chain(&stylesheets));
 
     HTML_TREE.with(|h| {
         *h.borrow_mut() = Some(html_tree);
     });
-    STYLESHEET.with(|s| *s.borrow_mut() = Some(stylesheet));
+    STYLESHEETS.with(|s| *s.borrow_mut() = Some(stylesheets));
+    USER_STYLESHEETS.with(|s| *s.borrow_mut() = user_stylesheets);
 
     layout::LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
 
@@ -142,10 +331,27 @@ pub fn update_html_source(html_src: String) {
     }
 }
 
+/// Lays out the current document against ``viewport`` and returns what should be painted.
+pub fn build_display_list(viewport: layout::Dimensions) -> painter::DisplayList {
+    let html_tree = HTML_TREE.with(|h| (*h.borrow()).clone().unwrap());
+    let stylesheets = STYLESHEETS.with(|s| (*s.borrow()).clone().unwrap());
+    let user_stylesheets = USER_STYLESHEETS.with(|s| s.borrow().clone());
+    let mut layout_tree =
+        layout::layout_tree(&html_tree, &user_stylesheets, &stylesheets, viewport);
+    // debug_println!("LAYOUT:\n{:#?}", layout_tree);
+
+    let display_command = painter::build_display_list(&mut layout_tree);
+    // debug_println!("DISPLAY:\n{:#?}", display_command);
+
+    display_command
+}
+
 pub fn run_with_url(html_src: String) {
+    let user_stylesheet_path = USER_STYLESHEET_PATH.with(|p| p.borrow().clone());
     let main_browser_process = ::std::thread::spawn(|| {
+        set_user_stylesheet_path(user_stylesheet_path);
         update_html_source(html_src);
-        
+
         window::render(move |widget| {
             let mut viewport: layout::Dimensions = ::std::default::Default::default();
             viewport.content.width = Au::from_f64_px(widget.allocated_width() as f64);
@@ -167,14 +373,7 @@ pub fn run_with_url(html_src: String) {
                     *last_width = viewport.content.width;
                     *last_height = viewport.content.height;
 
-                    let html_tree = HTML_TREE.with(|h| (*h.borrow()).clone().unwrap());
-                    let stylesheet = STYLESHEET.with(|s| (*s.borrow()).clone().unwrap());
-                    let mut layout_tree = layout::layout_tree(&html_tree, &stylesheet, viewport);
-                    // debug_println!("LAYOUT:\n{:#?}", layout_tree);
-
-                    let display_command = painter::build_display_list(&mut layout_tree);
-                    // debug_println!("DISPLAY:\n{:#?}", display_command);
-
+                    let display_command = build_display_list(viewport);
                     *last_displays = display_command.clone();
 
                     display_command
@@ -188,7 +387,23 @@ pub fn run_with_url(html_src: String) {
         println!("*** Sorry, Naglfar has been crushed. ***");
     }
 
-    // Delete downloaded files
+    remove_downloaded_files();
+}
+
+/// Renders ``html_src`` without opening any window and writes a ``width`` x ``height`` PNG
+/// snapshot of the viewport to ``png_path``.
+pub fn run_headless(html_src: String, width: i32, height: i32, png_path: &Path) {
+    let result = headless::render_to_png(html_src, width, height, png_path);
+
+    remove_downloaded_files();
+
+    if let Err(e) = result {
+        println!("*** Failed to write {}: {:?} ***", png_path.display(), e);
+    }
+}
+
+// Delete downloaded files
+fn remove_downloaded_files() {
     if let Ok(dir) = fs::read_dir("./cache") {
         for entry in dir {
             if let Ok(entry) = entry {
@@ -203,3 +418,106 @@ pub fn run_with_url(html_src: String) {
         }
     }
 }
+
+#[test]
+fn test_document_url() {
+    let dir = ::std::env::temp_dir().join(format!("document-url-{}", ::std::process::id()));
+    fs::create_dir_all(dir.join("css")).unwrap();
+    fs::write(dir.join("css/a.css"), "p { color: red }").unwrap();
+    let page = Url::from_file_path(dir.join("a.html")).unwrap();
+    fs::write(
+        dir.join("a.html"),
+        format!(
+            "<link rel=stylesheet href='{}'>",
+            page.join("css/a.css").unwrap()
+        ),
+    )
+    .unwrap();
+
+    // Loading the stylesheet leaves the document URL alone, so images are still relative to
+    // the page.
+    update_html_source(page.to_string());
+    assert_eq!(STYLESHEETS.with(|s| s.borrow().as_ref().unwrap().len()), 1);
+    assert_eq!(
+        HTML_SRC_URL.with(|url| url.borrow().clone()),
+        Some(page.to_string())
+    );
+    assert_eq!(
+        resolve_url(dir.join("b.png").to_str().unwrap()),
+        page.join("b.png").unwrap()
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_user_stylesheet() {
+    let path = ::std::env::temp_dir().join(format!("user-{}.css", ::std::process::id()));
+    let url = format!(
+        "file://{}",
+        fs::canonicalize("reftests/block-padding.html")
+            .unwrap()
+            .display()
+    );
+    set_user_stylesheet_path(Some(path.clone()));
+
+    // Every page load reads the file again.
+    let rules =
+        || USER_STYLESHEETS.with(|s| s.borrow().iter().map(|s| s.rules.len()).sum::<usize>());
+    fs::write(&path, "p { color: red }").unwrap();
+    update_html_source(url.clone());
+    assert_eq!(rules(), 1);
+    // It is loaded after the page, but relative URLs still resolve against the page.
+    assert_eq!(HTML_SRC_URL.with(|u| u.borrow().clone()), Some(url.clone()));
+    fs::write(&path, "p { color: red } h1 { color: blue !important }").unwrap();
+    update_html_source(url.clone());
+    assert_eq!(rules(), 2);
+
+    fs::remove_file(&path).unwrap();
+    update_html_source(url);
+    assert_eq!(rules(), 0);
+    set_user_stylesheet_path(None);
+}
+
+#[test]
+fn test_import() {
+    let dir = ::std::env::temp_dir().join(format!("import-{}", ::std::process::id()));
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(
+        dir.join("a.css"),
+        "@import 'sub/b.css'; @import url(c.css) print; a {}",
+    )
+    .unwrap();
+    // Relative to b.css, and back to a.css.
+    fs::write(
+        dir.join("sub/b.css"),
+        "@import '../c.css' screen; @import '../a.css'; b {}",
+    )
+    .unwrap();
+    // An unreachable stylesheet is skipped.
+    fs::write(
+        dir.join("c.css"),
+        "@import url(sub/b.css); @import 'http://127.0.0.1:1/d.css'; c {}",
+    )
+    .unwrap();
+
+    let mut stylesheets = vec![];
+    load_stylesheet_from_url(
+        Url::from_file_path(dir.join("a.css")).unwrap(),
+        &[],
+        &mut vec![],
+        &mut stylesheets,
+    );
+    // Each stylesheet with the media queries it is imported under.
+    let order: Vec<String> = stylesheets
+        .iter()
+        .map(|s| {
+            let rule = &s.rules[0];
+            let media: Vec<String> = rule.media.iter().map(|m| m.to_string()).collect();
+            format!("{} {}", rule.selectors[0], media.join(" "))
+        })
+        .collect();
+    assert_eq!(order, vec!["c screen", "b ", "b print", "c print", "a "]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
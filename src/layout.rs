@@ -1,18 +1,26 @@
+use bloom::AncestorFilter;
+use css;
 use css::{
-    parse_attr_style, Declaration, Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value,
+    parse_attr_style, AttrOperator, AttrSelector, Combinator, LengthContext, Origin, PseudoClass,
+    Rule, SimpleSelector, Specificity, Stylesheet, Unit, Value,
 };
+use css_tokenizer::Token;
 use default_style;
 use dom::{ElementData, LayoutType, Node, NodeType};
 use float::Floats;
-use font::{Font, FontSlant, FontWeight};
+use font;
+use font::{Font, FontSlant, FontStretch, FontWeight};
 use inline::LineMaker;
+use properties;
+use rule_index::RuleIndex;
 use style;
 use style::{Display, Style};
 
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use std::fmt;
 use std::ops::Range;
+use std::{fmt, iter, slice};
 
 use gtk::{cairo, gdk_pixbuf, pango};
 // use gdk_pixbuf;
@@ -151,32 +159,207 @@ impl LayoutBox {
     }
 }
 
+/// A DOM node with its parent and siblings, which selectors such as ``:first-child`` and the
+/// combinators look at.
+#[derive(Clone, Copy)]
+struct NodeContext<'a> {
+    node: &'a Node,
+    parent: Option<&'a NodeContext<'a>>,
+    // The children of the parent, with ``node`` at ``index``.
+    siblings: &'a [Node],
+    index: usize,
+}
+
+impl<'a> NodeContext<'a> {
+    fn root(node: &'a Node) -> NodeContext<'a> {
+        NodeContext {
+            node,
+            parent: None,
+            siblings: slice::from_ref(node),
+            index: 0,
+        }
+    }
+
+    fn element(&self) -> Option<&'a ElementData> {
+        match self.node.data {
+            NodeType::Element(ref elem) => Some(elem),
+            NodeType::Text(_) => None,
+        }
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &'a NodeContext<'a>> {
+        iter::successors(self.parent, |context| context.parent)
+    }
+
+    // Returns the element siblings before this node, nearest first.
+    fn previous_siblings(&self) -> impl Iterator<Item = NodeContext<'a>> {
+        let (parent, siblings) = (self.parent, self.siblings);
+        (0..self.index)
+            .rev()
+            .map(move |index| NodeContext {
+                node: &siblings[index],
+                parent,
+                siblings,
+                index,
+            })
+            .filter(|sibling| sibling.element().is_some())
+    }
+
+    // Returns the position of the element among its element siblings, counting from 1, and the
+    // number of those siblings including itself. With ``of_type``, only the siblings with the
+    // same tag name are counted.
+    fn position(&self, of_type: bool) -> (usize, usize) {
+        let tag_name = self.node.tag_name();
+        let counted = |node: &&Node| match node.data {
+            NodeType::Element(ref elem) => !of_type || Some(elem.tag_name.as_str()) == tag_name,
+            NodeType::Text(_) => false,
+        };
+        let before = self.siblings[..self.index].iter().filter(counted).count();
+        let after = self.siblings[self.index + 1..]
+            .iter()
+            .filter(counted)
+            .count();
+        (before + 1, before + after + 1)
+    }
+}
+
+/// How many elements the last ``style_tree`` styled, and how many of them reused the style of a
+/// sibling instead of going through the cascade.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StyleSharingCounts {
+    pub elements: usize,
+    pub shared: usize,
+}
+
+// The number of styles of earlier siblings that an element can share.
+const STYLE_SHARING_CACHE_SIZE: usize = 8;
+
+// The styles of the last siblings styled. A sibling with the same tag name, classes and inline
+// style, and no id, matches the same rules and so gets the same style, provided it matches the
+// selectors that need revalidation alike.
+struct StyleSharingCache<'a> {
+    entries: Vec<SharedStyle<'a>>,
+}
+
+struct SharedStyle<'a> {
+    elem: &'a ElementData,
+    // See ``revalidation_matches``.
+    revalidation: Vec<(usize, usize, Specificity)>,
+    style: Style,
+    length_context: LengthContext,
+}
+
+impl<'a> StyleSharingCache<'a> {
+    fn new() -> StyleSharingCache<'a> {
+        StyleSharingCache { entries: vec![] }
+    }
+
+    fn find(
+        &self,
+        elem: &ElementData,
+        revalidation: &[(usize, usize, Specificity)],
+    ) -> Option<&SharedStyle<'a>> {
+        self.entries.iter().find(|entry| {
+            entry.elem.tag_name == elem.tag_name
+                && entry.elem.attrs.get("style") == elem.attrs.get("style")
+                && entry.elem.classes() == elem.classes()
+                && entry.revalidation == revalidation
+        })
+    }
+
+    fn insert(&mut self, entry: SharedStyle<'a>) {
+        if self.entries.len() == STYLE_SHARING_CACHE_SIZE {
+            self.entries.remove(0);
+        }
+        self.entries.push(entry);
+    }
+}
+
+// Returns the selectors that need revalidation and match the element, as their stylesheet,
+// source order and specificity, in order. Siblings have the same ancestors, so the selectors
+// that ``ancestors`` rejects can be left out.
+fn revalidation_matches(
+    context: &NodeContext,
+    elem: &ElementData,
+    stylesheets: &[(Origin, RuleIndex)],
+    ancestors: &AncestorFilter,
+) -> Vec<(usize, usize, Specificity)> {
+    let mut matched = vec![];
+    for (index, (_, rules)) in stylesheets.iter().enumerate() {
+        for selector in rules.candidates(elem) {
+            if selector.needs_revalidation
+                && !selector.rejected_by(ancestors)
+                && matches_compounds(context, &selector.compounds)
+            {
+                matched.push((index, selector.source_order, selector.specificity));
+            }
+        }
+    }
+    matched.sort();
+    matched
+}
+
 /// Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
-fn build_layout_tree(
-    node: &Node,
-    stylesheet: &Stylesheet,
-    default_style: &Stylesheet,
+fn build_layout_tree<'a>(
+    context: &NodeContext<'a>,
+    stylesheets: &[(Origin, RuleIndex)],
+    // The ancestors of ``context.node``.
+    ancestors: &mut AncestorFilter,
+    // The styles of the siblings before ``context.node``.
+    sharing_cache: &mut StyleSharingCache<'a>,
     inherited_property: &Style,
     parent_specified_values: &Style,
-    appeared_elements: &Vec<SimpleSelector>,
+    parent_length_context: &LengthContext,
     // id: &mut usize,
 ) -> LayoutBox {
-    let mut appeared_elements = appeared_elements.clone();
+    let node = context.node;
+    let mut length_context = *parent_length_context;
     let specified_values = match node.data {
         NodeType::Element(ref elem) => {
-            let values = specified_values(
-                elem,
-                default_style,
-                stylesheet,
-                inherited_property,
-                &appeared_elements,
-            );
-            appeared_elements.push(SimpleSelector {
-                tag_name: Some(elem.tag_name.clone()),
-                id: elem.id().and_then(|id| Some(id.clone())),
-                class: elem.classes().iter().map(|x| x.to_string()).collect(),
+            // An id is usually unique, so an element with one is not worth caching.
+            let revalidation = match elem.id() {
+                Some(_) => None,
+                None => Some(revalidation_matches(context, elem, stylesheets, ancestors)),
+            };
+            let shared = revalidation
+                .as_ref()
+                .and_then(|revalidation| sharing_cache.find(elem, revalidation));
+            STYLE_SHARING_COUNTS.with(|counts| {
+                let mut new_counts = counts.get();
+                new_counts.elements += 1;
+                new_counts.shared += shared.is_some() as usize;
+                counts.set(new_counts)
             });
-            values
+            match shared {
+                Some(shared) => {
+                    length_context = shared.length_context;
+                    shared.style.clone()
+                }
+                None => {
+                    let mut values = specified_values(
+                        elem,
+                        context,
+                        stylesheets,
+                        ancestors,
+                        inherited_property,
+                        parent_specified_values,
+                    );
+                    length_context = resolve_lengths(
+                        &mut values,
+                        parent_length_context,
+                        context.parent.is_none(),
+                    );
+                    if let Some(revalidation) = revalidation {
+                        sharing_cache.insert(SharedStyle {
+                            elem,
+                            revalidation,
+                            style: values.clone(),
+                            length_context,
+                        });
+                    }
+                    values
+                }
+            }
         }
         NodeType::Text(_) => {
             Style::new_with(
@@ -234,28 +417,29 @@ fn build_layout_tree(
         style::FloatType::Left | style::FloatType::Right => root.box_type = BoxType::Float,
     }
 
-    let inherited_property = inherit_peoperties(
-        &specified_values,
-        vec![
-            "font-size",
-            "line-height",
-            "font-weight",
-            "font-style",
-            "text-align",
-            "color",
-        ],
-    );
+    let inherited_property = inherit_peoperties(&specified_values);
+
+    if let Some(elem) = context.element() {
+        ancestors.push(elem);
+    }
 
     // Create the descendant boxes.
-    for child in node.children.iter() {
+    let mut sharing_cache = StyleSharingCache::new();
+    for (index, child) in node.children.iter().enumerate() {
         // *id += 1;
         let child = build_layout_tree(
-            child,
-            stylesheet,
-            default_style,
+            &NodeContext {
+                node: child,
+                parent: Some(context),
+                siblings: &node.children,
+                index,
+            },
+            stylesheets,
+            ancestors,
+            &mut sharing_cache,
             &inherited_property,
             &specified_values,
-            &appeared_elements,
+            &length_context,
             // id,
         );
 
@@ -274,32 +458,84 @@ fn build_layout_tree(
         }
     }
 
+    if let Some(elem) = context.element() {
+        ancestors.pop(elem);
+    }
+
     root
 }
 
-fn inherit_peoperties(specified_values: &Style, property_list: Vec<&str>) -> Style {
-    let mut inherited_property = HashMap::new();
-    let specified_values = &specified_values.property;
-    for property in property_list {
-        if let Some(value) = specified_values.get(property) {
-            inherited_property.insert(property.to_string(), value.clone());
+// Converts lengths into px, except percentages, and returns what the lengths of the children are
+// relative to. ``font-size`` is relative to the parent's font. The percentages of ``font-size``
+// and ``line-height`` are converted too, so that the children inherit lengths.
+fn resolve_lengths(
+    style: &mut Style,
+    parent_context: &LengthContext,
+    is_root: bool,
+) -> LengthContext {
+    let resolve = |values: &mut Vec<Value>, context: &LengthContext| {
+        for value in values.iter_mut() {
+            if value.has_unit(Unit::is_viewport_relative) {
+                USES_VIEWPORT_UNITS.with(|uses| uses.set(true));
+            }
+            *value = value.resolve_lengths(context);
+        }
+    };
+
+    if let Some(font_size) = style.property.get_mut("font-size") {
+        resolve(font_size, parent_context);
+        for value in font_size.iter_mut() {
+            *value = value.resolve_percentages(parent_context.font_size);
+        }
+    }
+    let font_size = style.font_size();
+    let (x_height, zero_width) = style.font().ex_and_ch();
+    let context = LengthContext {
+        root_font_size: if is_root {
+            font_size.to_f64_px()
+        } else {
+            parent_context.root_font_size
+        },
+        font_size: font_size.to_f64_px(),
+        x_height,
+        zero_width,
+        ..*parent_context
+    };
+
+    for (name, values) in style.property.iter_mut() {
+        if name != "font-size" {
+            resolve(values, &context);
+        }
+    }
+    if let Some(line_height) = style.property.get_mut("line-height") {
+        for value in line_height.iter_mut() {
+            *value = value.resolve_percentages(context.font_size);
         }
     }
-    Style::new_with(inherited_property)
+    context
+}
+
+fn inherit_peoperties(specified_values: &Style) -> Style {
+    Style::new_with(
+        specified_values
+            .property
+            .iter()
+            .filter(|&(name, _)| properties::is_inherited(name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+    )
 }
 
 fn specified_values(
     elem: &ElementData,
-    default_style: &Stylesheet,
-    stylesheet: &Stylesheet,
+    context: &NodeContext,
+    stylesheets: &[(Origin, RuleIndex)],
+    ancestors: &AncestorFilter,
     inherited_property: &Style,
-    appeared_elements: &Vec<SimpleSelector>,
+    parent_style: &Style,
 ) -> Style {
     let mut values = HashMap::with_capacity(16);
 
-    let mut rules = matching_rules(elem, &default_style, appeared_elements);
-    rules.append(&mut matching_rules(elem, stylesheet, appeared_elements));
-
     // Insert inherited properties
     inherited_property
         .property
@@ -308,104 +544,219 @@ fn specified_values(
             values.insert(name.clone(), value.clone());
         });
 
-    // Go through the rules from lowest to highest specificity.
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    rules.iter().for_each(|&(_, rule)| {
-        rule.declarations.iter().for_each(|declaration| {
-            values.insert(declaration.name.clone(), declaration.values.clone());
-        })
-    });
-
-    if let Some(attr_style) = elem.attrs.get("style") {
-        let decls = parse_attr_style(attr_style.clone());
-        for Declaration { name, values: vals } in decls {
-            values.insert(name, vals);
+    let attr_style = elem
+        .attrs
+        .get("style")
+        .map_or(vec![], |style| parse_attr_style(style.clone()));
+
+    let mut declarations = vec![];
+    for &(origin, ref rules) in stylesheets {
+        for (specificity, rule) in matching_rules(context, rules, ancestors) {
+            for declaration in &rule.declarations {
+                let level = cascade_level(origin, declaration.important);
+                declarations.push(((level, false, specificity), declaration));
+            }
         }
     }
+    for declaration in &attr_style {
+        let level = cascade_level(Origin::Author, declaration.important);
+        declarations.push(((level, true, (0, 0, 0)), declaration));
+    }
+
+    // Apply the declarations from lowest to highest precedence. The sort is stable, so
+    // declarations with equal precedence stay in source order and the last one wins.
+    declarations.sort_by_key(|&(precedence, _)| precedence);
+    for (_, declaration) in declarations {
+        values.insert(declaration.name.clone(), declaration.values.clone());
+    }
+
+    substitute_vars(&mut values);
+    properties::compute(&mut values, parent_style);
 
     Style::new_with(values)
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+// Substitutes the ``var()`` references in the cascaded values. A custom property that can't be
+// substituted, because of a missing variable or a cycle, is removed. Any other property becomes
+// ``unset``, and so does one whose value turns out to be invalid for the property.
+fn substitute_vars(values: &mut HashMap<String, Vec<Value>>) {
+    let specified = values
+        .iter()
+        .filter_map(|(name, value)| match &value[..] {
+            [Value::Unparsed(tokens)] if name.starts_with("--") => {
+                Some((name.clone(), tokens.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    let mut custom_properties = CustomProperties {
+        specified: &specified,
+        computed: HashMap::new(),
+        resolving: vec![],
+        cyclic: HashSet::new(),
+    };
 
-fn matching_rules<'a>(
-    elem: &ElementData,
-    stylesheet: &'a Stylesheet,
-    appeared_elements: &Vec<SimpleSelector>,
-) -> Vec<MatchedRule<'a>> {
-    // For now, we just do a linear scan of all the rules.  For large
-    // documents, it would be more efficient to store the rules in hash tables
-    // based on tag name, id, class, etc.
-    stylesheet
-        .rules
+    for name in specified.keys() {
+        match custom_properties.computed_value(name) {
+            Some(tokens) => values.insert(name.clone(), vec![Value::Unparsed(tokens)]),
+            None => values.remove(name),
+        };
+    }
+
+    let unparsed: Vec<(String, Value)> = values
         .iter()
-        .filter_map(|rule| match_rule(elem, rule, appeared_elements))
-        .collect()
+        .filter_map(|(name, value)| match &value[..] {
+            [value @ Value::Unparsed(_)] if !name.starts_with("--") => {
+                Some((name.clone(), value.clone()))
+            }
+            [value @ Value::PendingSubstitution(..)] => Some((name.clone(), value.clone())),
+            _ => None,
+        })
+        .collect();
+    for (name, value) in unparsed {
+        // A shorthand is parsed as a whole, and this longhand takes its part.
+        let (declared_name, tokens) = match value {
+            Value::Unparsed(tokens) => (name.clone(), tokens),
+            Value::PendingSubstitution(shorthand, tokens) => (shorthand, tokens),
+            _ => unreachable!(),
+        };
+        let substituted =
+            css::substitute_vars(&tokens, &mut |var| custom_properties.computed_value(var))
+                .and_then(css::parse_substituted_values)
+                .and_then(|values| properties::parse(&declared_name, &values))
+                .and_then(|longhands| {
+                    longhands
+                        .into_iter()
+                        .find(|(longhand, _)| *longhand == name)
+                        .map(|(_, values)| values)
+                });
+        let unset = vec![Value::Keyword("unset".to_string())];
+        values.insert(name, substituted.unwrap_or(unset));
+    }
 }
 
-fn match_rule<'a>(
-    elem: &ElementData,
-    rule: &'a Rule,
-    appeared_elements: &Vec<SimpleSelector>,
-) -> Option<MatchedRule<'a>> {
-    // Find the first (most specific) matching selector.
-    rule.selectors
-        .iter()
-        .find(|selector| matches(elem, *selector, appeared_elements))
-        .map(|selector| (selector.specificity(), rule))
+// The custom properties of an element, whose values are computed on demand.
+struct CustomProperties<'a> {
+    specified: &'a HashMap<String, Vec<Token>>,
+    computed: HashMap<String, Option<Vec<Token>>>,
+    // The properties whose values are being computed, innermost last.
+    resolving: Vec<String>,
+    cyclic: HashSet<String>,
 }
 
-fn matches(
-    elem: &ElementData,
-    selector: &Selector,
-    appeared_elements: &Vec<SimpleSelector>,
-) -> bool {
-    match *selector {
-        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
-        Selector::Descendant(ref a, ref b) => {
-            matches_descendant_combinator(elem, &*a, &**b, appeared_elements)
+impl<'a> CustomProperties<'a> {
+    // Returns None if the property doesn't exist, or if its value is invalid.
+    fn computed_value(&mut self, name: &str) -> Option<Vec<Token>> {
+        if let Some(value) = self.computed.get(name) {
+            return value.clone();
         }
-        Selector::Child(ref a, ref b) => {
-            matches_child_combinator(elem, &*a, &**b, appeared_elements)
+        if let Some(i) = self.resolving.iter().position(|n| n == name) {
+            // Every property from ``name`` onwards refers to the next one, and the last one
+            // refers back to ``name``.
+            self.cyclic.extend(self.resolving[i..].iter().cloned());
+            return None;
         }
+        let specified = self.specified;
+        let tokens = specified.get(name)?;
+        self.resolving.push(name.to_string());
+        let value = css::substitute_vars(tokens, &mut |var| self.computed_value(var));
+        self.resolving.pop();
+        let value = value.filter(|_| !self.cyclic.contains(name));
+        self.computed.insert(name.to_string(), value.clone());
+        value
     }
 }
 
-fn matches_descendant_combinator(
-    elem: &ElementData,
-    simple: &SimpleSelector,
-    selector_b: &Selector,
-    appeared_elements: &Vec<SimpleSelector>,
-) -> bool {
-    appeared_elements.iter().any(|e| {
-        !((simple.tag_name.is_some() && e.tag_name != simple.tag_name)
-            || (simple.id.is_some() && e.id != simple.id)
-            || (!simple.class.iter().all(|class| e.class.contains(class))))
-    }) && matches(elem, selector_b, appeared_elements)
+// Ranks the origin and importance of a declaration in the cascade, from lowest to highest.
+fn cascade_level(origin: Origin, important: bool) -> u8 {
+    match (important, origin) {
+        (false, Origin::UserAgent) => 0,
+        (false, Origin::User) => 1,
+        (false, Origin::Author) => 2,
+        (true, Origin::Author) => 3,
+        (true, Origin::User) => 4,
+        (true, Origin::UserAgent) => 5,
+    }
 }
 
-fn matches_child_combinator(
-    elem: &ElementData,
-    simple: &SimpleSelector,
-    selector_b: &Selector,
-    appeared_elements: &Vec<SimpleSelector>,
+type MatchedRule<'a> = (Specificity, &'a Rule);
+
+fn matching_rules<'a>(
+    context: &NodeContext,
+    rules: &RuleIndex<'a>,
+    ancestors: &AncestorFilter,
+) -> Vec<MatchedRule<'a>> {
+    let elem = match context.element() {
+        Some(elem) => elem,
+        None => return vec![],
+    };
+    let mut matched: Vec<_> = rules
+        .candidates(elem)
+        .into_iter()
+        .filter(|selector| !selector.rejected_by(ancestors))
+        .filter(|selector| matches_compounds(context, &selector.compounds))
+        .collect();
+    // In source order, and when several selectors of a rule match, the most specific one counts.
+    matched.sort_by_key(|selector| (selector.source_order, Reverse(selector.specificity)));
+    matched.dedup_by_key(|selector| selector.source_order);
+    matched
+        .into_iter()
+        .map(|selector| (selector.specificity, selector.rule))
+        .collect()
+}
+
+// Matches right to left: the last compound against the element itself, then each compound to its
+// left against the elements its combinator leads to. A descendant or subsequent-sibling
+// combinator leads to several elements, so each is tried in turn until the rest of the selector
+// matches too.
+fn matches_compounds(
+    context: &NodeContext,
+    compounds: &[(&SimpleSelector, Option<Combinator>)],
 ) -> bool {
-    if let Some(ref last_elem) = appeared_elements.last() {
-        !((simple.tag_name.is_some() && last_elem.tag_name != simple.tag_name)
-            || (simple.id.is_some() && last_elem.id != simple.id)
-            || (!simple
-                .class
-                .iter()
-                .all(|class| last_elem.class.contains(class))))
-            && matches(elem, selector_b, appeared_elements)
-    } else {
-        false
+    let (&(subject, _), rest) = match compounds.split_last() {
+        Some(split) => split,
+        None => return true,
+    };
+    if !matches_simple_selector(context, subject) {
+        return false;
+    }
+    match rest.last() {
+        None => true,
+        Some(&(_, Some(Combinator::Descendant))) => context
+            .ancestors()
+            .any(|ancestor| matches_compounds(ancestor, rest)),
+        Some(&(_, Some(Combinator::Child))) => context
+            .parent
+            .is_some_and(|parent| matches_compounds(parent, rest)),
+        Some(&(_, Some(Combinator::NextSibling))) => context
+            .previous_siblings()
+            .next()
+            .is_some_and(|sibling| matches_compounds(&sibling, rest)),
+        Some(&(_, Some(Combinator::SubsequentSibling))) => context
+            .previous_siblings()
+            .any(|sibling| matches_compounds(&sibling, rest)),
+        Some(&(_, None)) => unreachable!(),
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+fn matches_simple_selector(context: &NodeContext, selector: &SimpleSelector) -> bool {
+    let elem = match context.element() {
+        Some(elem) => elem,
+        None => return false,
+    };
+
+    // Pseudo-elements are not generated.
+    if selector.pseudo_element.is_some() {
+        return false;
+    }
+
     // Universal selector
-    if selector.tag_name.is_none() && selector.id.is_none() && selector.class.is_empty() {
+    if selector.tag_name.is_none()
+        && selector.id.is_none()
+        && selector.class.is_empty()
+        && selector.attributes.is_empty()
+        && selector.pseudo_classes.is_empty()
+    {
         return true;
     }
 
@@ -429,38 +780,249 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
         return false;
     }
 
+    // Check attribute selectors
+    if !selector
+        .attributes
+        .iter()
+        .all(|attribute| matches_attr_selector(elem, attribute))
+    {
+        return false;
+    }
+
+    // Check pseudo-classes
+    if !selector
+        .pseudo_classes
+        .iter()
+        .all(|pseudo_class| matches_pseudo_class(context, elem, pseudo_class))
+    {
+        return false;
+    }
+
     // We didn't find any non-matching selector components.
     true
 }
 
-use std::cell::RefCell;
+fn matches_pseudo_class(
+    context: &NodeContext,
+    elem: &ElementData,
+    pseudo_class: &PseudoClass,
+) -> bool {
+    // Whether ``position`` (counting from 1) is ``a * n + b`` for some n >= 0.
+    fn nth(a: i32, b: i32, position: usize) -> bool {
+        let offset = position as i32 - b;
+        if a == 0 {
+            offset == 0
+        } else {
+            offset % a == 0 && offset / a >= 0
+        }
+    }
+
+    match *pseudo_class {
+        PseudoClass::Root => context.parent.is_none(),
+        PseudoClass::Empty => context.node.children.iter().all(|child| match child.data {
+            NodeType::Text(ref text) => text.is_empty(),
+            NodeType::Element(_) => false,
+        }),
+        PseudoClass::FirstChild => context.position(false).0 == 1,
+        PseudoClass::LastChild => {
+            let (position, count) = context.position(false);
+            position == count
+        }
+        PseudoClass::OnlyChild => context.position(false).1 == 1,
+        PseudoClass::FirstOfType => context.position(true).0 == 1,
+        PseudoClass::LastOfType => {
+            let (position, count) = context.position(true);
+            position == count
+        }
+        PseudoClass::OnlyOfType => context.position(true).1 == 1,
+        PseudoClass::NthChild(a, b) => nth(a, b, context.position(false).0),
+        PseudoClass::NthLastChild(a, b) => {
+            let (position, count) = context.position(false);
+            nth(a, b, count - position + 1)
+        }
+        PseudoClass::NthOfType(a, b) => nth(a, b, context.position(true).0),
+        PseudoClass::NthLastOfType(a, b) => {
+            let (position, count) = context.position(true);
+            nth(a, b, count - position + 1)
+        }
+        PseudoClass::Not(ref selectors) => !selectors
+            .iter()
+            .any(|selector| matches_simple_selector(context, selector)),
+        PseudoClass::Link => {
+            matches!(elem.tag_name.as_str(), "a" | "area" | "link")
+                && elem.attrs.contains_key("href")
+        }
+        PseudoClass::Unsupported(_) => false,
+    }
+}
+
+fn matches_attr_selector(elem: &ElementData, selector: &AttrSelector) -> bool {
+    let value = match elem.attrs.get(&selector.name) {
+        Some(value) => value,
+        None => return false,
+    };
+    let (value, expected) = if selector.case_insensitive {
+        (
+            value.to_ascii_lowercase(),
+            selector.value.to_ascii_lowercase(),
+        )
+    } else {
+        (value.clone(), selector.value.clone())
+    };
+    match selector.operator {
+        AttrOperator::Exists => true,
+        AttrOperator::Equals => value == expected,
+        AttrOperator::Includes => value.split_whitespace().any(|word| word == expected),
+        AttrOperator::DashMatch => {
+            value == expected || value.starts_with(&format!("{}-", expected))
+        }
+        // An empty value matches nothing.
+        AttrOperator::Prefix => !expected.is_empty() && value.starts_with(&expected),
+        AttrOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+        AttrOperator::Substring => !expected.is_empty() && value.contains(&expected),
+    }
+}
+
+use std::cell::{Cell, RefCell};
 thread_local!(
     pub static LAYOUTBOX: RefCell<Option<LayoutBox>> = { RefCell::new(None) };
+    // Which media query lists of the stylesheets matched when LAYOUTBOX was built.
+    static MATCHED_MEDIA: RefCell<Vec<bool>> = const { RefCell::new(vec![]) };
+    // The viewport size that LAYOUTBOX was built for, if it has viewport units.
+    static VIEWPORT_UNITS_SIZE: Cell<Option<(f64, f64)>> = const { Cell::new(None) };
+    static USES_VIEWPORT_UNITS: Cell<bool> = const { Cell::new(false) };
+    static STYLE_SHARING_COUNTS: Cell<StyleSharingCounts> = const {
+        Cell::new(StyleSharingCounts {
+            elements: 0,
+            shared: 0,
+        })
+    };
 );
 
+pub fn style_sharing_counts() -> StyleSharingCounts {
+    STYLE_SHARING_COUNTS.with(|counts| counts.get())
+}
+
+fn media_applies(rule: &Rule, width: f64, height: f64) -> bool {
+    rule.media.iter().all(|media| media.matches(width, height))
+}
+
+// Returns a copy of ``stylesheet`` without the rules whose media queries don't match.
+fn applicable_rules(stylesheet: &Stylesheet, width: f64, height: f64) -> Stylesheet {
+    Stylesheet {
+        imports: vec![],
+        font_faces: vec![],
+        rules: stylesheet
+            .rules
+            .iter()
+            .filter(|rule| media_applies(rule, width, height))
+            .cloned()
+            .collect(),
+    }
+}
+
+// What the root element's font-size is relative to.
+fn initial_length_context(viewport_width: f64, viewport_height: f64) -> LengthContext {
+    let (x_height, zero_width) = Style::new().font().ex_and_ch();
+    LengthContext {
+        viewport_width,
+        viewport_height,
+        root_font_size: style::DEFAULT_FONT_SIZE,
+        font_size: style::DEFAULT_FONT_SIZE,
+        x_height,
+        zero_width,
+    }
+}
+
+/// Builds the tree of boxes with the computed style of every node for a viewport of ``width``
+/// by ``height`` px, without laying it out.
+pub fn style_tree(
+    root: &Node,
+    user_stylesheets: &[Stylesheet],
+    author_stylesheets: &[Stylesheet],
+    width: f64,
+    height: f64,
+) -> LayoutBox {
+    // let mut id = 0;
+    let default_style = default_style::default_style();
+    let user_stylesheets: Vec<Stylesheet> = user_stylesheets
+        .iter()
+        .map(|s| applicable_rules(s, width, height))
+        .collect();
+    let author_stylesheets: Vec<Stylesheet> = author_stylesheets
+        .iter()
+        .map(|s| applicable_rules(s, width, height))
+        .collect();
+    let mut stylesheets = vec![(Origin::UserAgent, RuleIndex::new(&default_style))];
+    stylesheets.extend(
+        user_stylesheets
+            .iter()
+            .map(|s| (Origin::User, RuleIndex::new(s))),
+    );
+    stylesheets.extend(
+        author_stylesheets
+            .iter()
+            .map(|s| (Origin::Author, RuleIndex::new(s))),
+    );
+    STYLE_SHARING_COUNTS.with(|counts| counts.set(StyleSharingCounts::default()));
+    build_layout_tree(
+        &NodeContext::root(root),
+        &stylesheets,
+        &mut AncestorFilter::new(),
+        &mut StyleSharingCache::new(),
+        &style::Style::new(),
+        &style::Style::new(),
+        &initial_length_context(width, height),
+        // &mut id,
+    )
+}
+
 /// Transform a style tree into a layout tree.
 pub fn layout_tree(
     root: &Node,
-    stylesheet: &Stylesheet,
+    user_stylesheets: &[Stylesheet],
+    author_stylesheets: &[Stylesheet],
     mut containing_block: Dimensions,
 ) -> LayoutBox {
+    let width = containing_block.content.width.to_f64_px();
+    let height = containing_block.content.height.to_f64_px();
+
+    // Cascade again if resizing the viewport changed which media queries match, or the size
+    // that viewport units were resolved against.
+    let matched_media: Vec<bool> = user_stylesheets
+        .iter()
+        .chain(author_stylesheets)
+        .flat_map(|stylesheet| &stylesheet.rules)
+        .flat_map(|rule| &rule.media)
+        .map(|media| media.matches(width, height))
+        .collect();
+    MATCHED_MEDIA.with(|last_matched_media| {
+        let viewport_units_size = VIEWPORT_UNITS_SIZE.with(|size| size.get());
+        if *last_matched_media.borrow() != matched_media
+            || viewport_units_size.is_some_and(|size| size != (width, height))
+        {
+            LAYOUTBOX.with(|layoutbox| *layoutbox.borrow_mut() = None);
+            *last_matched_media.borrow_mut() = matched_media;
+        }
+    });
+    // Likewise if it changed which ``@font-face`` rules apply.
+    if font::match_font_face_media(width, height) {
+        LAYOUTBOX.with(|layoutbox| *layoutbox.borrow_mut() = None);
+    }
+
     let mut first_construction_of_layout_tree = false;
     let mut root_box = LAYOUTBOX.with(|layoutbox| {
         layoutbox
             .borrow_mut()
             .get_or_insert_with(|| {
                 first_construction_of_layout_tree = true;
-                // let mut id = 0;
-                let default_style = default_style::default_style();
-                build_layout_tree(
-                    root,
-                    &stylesheet,
-                    &default_style,
-                    &style::Style::new(),
-                    &style::Style::new(),
-                    &vec![],
-                    // &mut id,
-                )
+                USES_VIEWPORT_UNITS.with(|uses| uses.set(false));
+                let root_box =
+                    style_tree(root, user_stylesheets, author_stylesheets, width, height);
+                let uses_viewport_units = USES_VIEWPORT_UNITS.with(|uses| uses.get());
+                VIEWPORT_UNITS_SIZE
+                    .with(|size| size.set(Some((width, height)).filter(|_| uses_viewport_units)));
+                root_box
             })
             .clone()
     });
@@ -655,15 +1217,23 @@ impl LayoutBox {
 
 impl FontWeight {
     pub fn to_cairo_font_weight(&self) -> cairo::FontWeight {
-        match self {
-            &FontWeight::Normal => cairo::FontWeight::Normal,
-            &FontWeight::Bold => cairo::FontWeight::Bold,
+        if *self >= FontWeight(600) {
+            cairo::FontWeight::Bold
+        } else {
+            cairo::FontWeight::Normal
         }
     }
     pub fn to_pango_font_weight(&self) -> pango::Weight {
-        match self {
-            &FontWeight::Normal => pango::Weight::Normal,
-            &FontWeight::Bold => pango::Weight::Bold,
+        match self.0 {
+            0..=149 => pango::Weight::Thin,
+            150..=249 => pango::Weight::Ultralight,
+            250..=349 => pango::Weight::Light,
+            350..=449 => pango::Weight::Normal,
+            450..=549 => pango::Weight::Medium,
+            550..=649 => pango::Weight::Semibold,
+            650..=749 => pango::Weight::Bold,
+            750..=849 => pango::Weight::Ultrabold,
+            _ => pango::Weight::Heavy,
         }
     }
 }
@@ -673,12 +1243,30 @@ impl FontSlant {
         match self {
             &FontSlant::Normal => cairo::FontSlant::Normal,
             &FontSlant::Italic => cairo::FontSlant::Italic,
+            &FontSlant::Oblique => cairo::FontSlant::Oblique,
         }
     }
     pub fn to_pango_font_slant(&self) -> pango::Style {
         match self {
             &FontSlant::Normal => pango::Style::Normal,
             &FontSlant::Italic => pango::Style::Italic,
+            &FontSlant::Oblique => pango::Style::Oblique,
+        }
+    }
+}
+
+impl FontStretch {
+    pub fn to_pango_stretch(&self) -> pango::Stretch {
+        match self {
+            &FontStretch::UltraCondensed => pango::Stretch::UltraCondensed,
+            &FontStretch::ExtraCondensed => pango::Stretch::ExtraCondensed,
+            &FontStretch::Condensed => pango::Stretch::Condensed,
+            &FontStretch::SemiCondensed => pango::Stretch::SemiCondensed,
+            &FontStretch::Normal => pango::Stretch::Normal,
+            &FontStretch::SemiExpanded => pango::Stretch::SemiExpanded,
+            &FontStretch::Expanded => pango::Stretch::Expanded,
+            &FontStretch::ExtraExpanded => pango::Stretch::ExtraExpanded,
+            &FontStretch::UltraExpanded => pango::Stretch::UltraExpanded,
         }
     }
 }
@@ -759,3 +1347,502 @@ impl fmt::Display for LayoutBox {
         Ok(())
     }
 }
+
+// Returns the text, or the tag name if there is none, of the elements in ``html`` that match
+// ``selectors``.
+#[cfg(test)]
+fn matching_elements(html: &str, selectors: &str) -> Vec<String> {
+    // Goes through ``matching_rules`` as styling does, so the rule index and the ancestor filter
+    // are tested too.
+    fn collect(
+        context: &NodeContext,
+        rules: &RuleIndex,
+        ancestors: &mut AncestorFilter,
+        found: &mut Vec<String>,
+    ) {
+        if !matching_rules(context, rules, ancestors).is_empty() {
+            found.push(
+                match context.node.children.first().map(|child| &child.data) {
+                    Some(NodeType::Text(text)) => text.clone(),
+                    _ => format!("<{}>", context.node.tag_name().unwrap()),
+                },
+            );
+        }
+        if let Some(elem) = context.element() {
+            ancestors.push(elem);
+        }
+        for (index, child) in context.node.children.iter().enumerate() {
+            let child = NodeContext {
+                node: child,
+                parent: Some(context),
+                siblings: &context.node.children,
+                index,
+            };
+            collect(&child, rules, ancestors, found);
+        }
+        if let Some(elem) = context.element() {
+            ancestors.pop(elem);
+        }
+    }
+
+    let (dom, stylesheet) = parse_document(html, &format!("{} {{}}", selectors));
+    let mut found = vec![];
+    collect(
+        &NodeContext::root(&dom),
+        &RuleIndex::new(&stylesheet),
+        &mut AncestorFilter::new(),
+        &mut found,
+    );
+    found
+}
+
+// The DOM of ``html`` and the stylesheet of ``css``.
+#[cfg(test)]
+fn parse_document(html: &str, css: &str) -> (Node, Stylesheet) {
+    use css;
+    use html;
+    use std::path::PathBuf;
+
+    (
+        html::parse(html.to_string(), PathBuf::from("a.html")),
+        css::parse(css.to_string()),
+    )
+}
+
+// The style tree of ``html`` with ``css`` as the author stylesheet, in an 800x600 viewport.
+#[cfg(test)]
+fn styled(html: &str, css: &str) -> LayoutBox {
+    let (dom, stylesheet) = parse_document(html, css);
+    style_tree(&dom, &[], &[stylesheet], 800.0, 600.0)
+}
+
+// The style of the element whose id is ``id``.
+#[cfg(test)]
+fn find_style<'a>(layout_box: &'a LayoutBox, id: &str) -> Option<&'a Style> {
+    match layout_box.node.data {
+        NodeType::Element(ref elem) if elem.id().is_some_and(|i| i == id) => {
+            Some(&layout_box.property)
+        }
+        _ => layout_box
+            .children
+            .iter()
+            .filter_map(|child| find_style(child, id))
+            .next(),
+    }
+}
+
+#[test]
+fn test_attribute_selectors() {
+    let matching = |selector| {
+        matching_elements(
+            "<p lang=en-US title='a b'>1</p><p lang=EN class=''>2</p><p title=ab>3</p>",
+            selector,
+        )
+    };
+    let none: Vec<String> = vec![];
+
+    assert_eq!(matching("p[title]"), ["1", "3"]);
+    assert_eq!(matching("[class]"), ["2"]);
+    assert_eq!(matching("[title=ab]"), ["3"]);
+    assert_eq!(matching("[title~=b]"), ["1"]);
+    assert_eq!(matching("[lang|=en]"), ["1"]);
+    assert_eq!(matching("[title^=a]"), ["1", "3"]);
+    assert_eq!(matching("[title$='b']"), ["1", "3"]);
+    assert_eq!(matching("[title*=' ']"), ["1"]);
+    // Only the value is compared case-insensitively with ``i``.
+    assert_eq!(matching("[lang=en]"), none);
+    assert_eq!(matching("[lang=en i]"), ["2"]);
+    assert_eq!(matching("[lang|=EN i]"), ["1", "2"]);
+    assert_eq!(matching("[LANG=EN]"), ["2"]);
+    // An empty value matches nothing with ``^=``, ``$=`` and ``*=``, but does with ``=``.
+    assert_eq!(matching("[class^=''], [class$=''], [class*='']"), none);
+    assert_eq!(matching("[class='']"), ["2"]);
+}
+
+#[test]
+fn test_structural_pseudo_classes() {
+    let matching = |selector| {
+        matching_elements(
+            "<ul><li>1</li><li>2</li><p></p>text<li>3</li></ul>",
+            selector,
+        )
+    };
+
+    assert_eq!(matching(":root"), ["<html>"]);
+    assert_eq!(matching(":empty"), ["<head>", "<p>"]);
+    assert_eq!(matching("li:first-child"), ["1"]);
+    assert_eq!(matching("li:last-child"), ["3"]);
+    assert_eq!(matching("p:only-of-type"), ["<p>"]);
+    assert_eq!(matching("li:nth-child(even)"), ["2", "3"]);
+    assert_eq!(matching("li:nth-of-type(2n+1)"), ["1", "3"]);
+    assert_eq!(matching("ul > :nth-last-child(-n+2)"), ["<p>", "3"]);
+    assert_eq!(matching("li:not(:first-child, :last-of-type)"), ["2"]);
+    assert_eq!(matching("li:hover, li::before"), Vec::<String>::new());
+}
+
+#[test]
+fn test_sibling_combinators() {
+    let matching = |selector| {
+        matching_elements(
+            "<div><h1>1</h1><p>2</p>text<p>3</p><h2>4</h2><p>5</p></div><p>6</p>",
+            selector,
+        )
+    };
+
+    assert_eq!(matching("h1 + p"), ["2"]);
+    assert_eq!(matching("p + p"), ["3"]);
+    assert_eq!(matching("h1 ~ p"), ["2", "3", "5"]);
+    assert_eq!(matching("h2 ~ *"), ["5"]);
+    assert_eq!(matching("p ~ h2, div + p"), ["4", "6"]);
+}
+
+#[test]
+fn test_combinator_chains() {
+    // Each compound has to match an element further up or left than the one to its right.
+    assert_eq!(
+        matching_elements("<div><p><b><span>1</span></b></p></div>", "div > p span"),
+        ["1"]
+    );
+    assert_eq!(
+        matching_elements("<div><b><p><span>1</span></p></b></div>", "div > p span"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        matching_elements("<div class='a b'><p class='c'>1</p></div>", ".a .b > .c"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        matching_elements("<p class='a'>1</p><div class='b'><p>2</p></div>", ".a .b p"),
+        Vec::<String>::new()
+    );
+
+    // The nearest ``.b`` is not a child of ``.a``, so the outer one has to be tried as well.
+    assert_eq!(
+        matching_elements(
+            "<div class='a'><div class='b'><div class='b'><p class='c'>1</p></div></div></div>",
+            ".a > .b .c"
+        ),
+        ["1"]
+    );
+
+    let matching = |selector| {
+        matching_elements(
+            "<div><h1>1</h1><p>2</p><p>3</p><h2>4</h2><p>5</p></div><p>6</p><p><b>7</b></p>",
+            selector,
+        )
+    };
+    assert_eq!(matching("h1 + p ~ p"), ["3", "5"]);
+    assert_eq!(matching("div > h1 ~ h2 + p"), ["5"]);
+    assert_eq!(matching("div ~ p b, div + p"), ["6", "7"]);
+    assert_eq!(matching("h2 ~ p + p, h1 + h2"), Vec::<String>::new());
+}
+
+#[test]
+fn test_cascade() {
+    use css;
+    use html;
+    use std::path::PathBuf;
+
+    // Returns the specified value of ``x`` on ``<p id="a" class="b" style="...">``.
+    let cascade = |user_agent: &str, user: &str, author: &str, style: &str| {
+        let dom = html::parse(
+            format!("<p id='a' class='b' style='{}'></p>", style),
+            PathBuf::from("a.html"),
+        );
+        let p = dom.find_first_node_by_tag_name("p").unwrap();
+        let elem = match p.data {
+            NodeType::Element(ref elem) => elem,
+            NodeType::Text(_) => unreachable!(),
+        };
+        let (user_agent, user, author) = (
+            css::parse(user_agent.to_string()),
+            css::parse(user.to_string()),
+            css::parse(author.to_string()),
+        );
+        let stylesheets = [
+            (Origin::UserAgent, RuleIndex::new(&user_agent)),
+            (Origin::User, RuleIndex::new(&user)),
+            (Origin::Author, RuleIndex::new(&author)),
+        ];
+        let root = NodeContext::root(p);
+        let ancestors = AncestorFilter::new();
+        let values = specified_values(
+            elem,
+            &root,
+            &stylesheets,
+            &ancestors,
+            &Style::new(),
+            &Style::new(),
+        );
+        values.property.get("x").cloned()
+    };
+    let x = |n| Some(vec![Value::Num(n)]);
+
+    // Later origins win regardless of specificity, and later rules win among equals.
+    assert_eq!(cascade("#a { x: 1 }", "", "* { x: 2 }", ""), x(2.0));
+    assert_eq!(cascade("", "#a { x: 1 }", "* { x: 2 }", ""), x(2.0));
+    assert_eq!(
+        cascade("", "", ".b { x: 1 } .b { x: 2 } p { x: 3 }", ""),
+        x(2.0)
+    );
+
+    // Important declarations win over normal ones and reverse the order of the origins.
+    assert_eq!(
+        cascade("", "", "#a { x: 1 } p { x: 2 !important }", ""),
+        x(2.0)
+    );
+    assert_eq!(
+        cascade("", "* { x: 1 !important }", "#a { x: 2 !important }", ""),
+        x(1.0)
+    );
+    assert_eq!(
+        cascade("* { x: 1 !important }", "* { x: 2 !important }", "", ""),
+        x(1.0)
+    );
+
+    // The style attribute beats any selector in the author origin.
+    assert_eq!(cascade("", "", "#a.b { x: 1 }", "x: 2"), x(2.0));
+    assert_eq!(cascade("", "", "* { x: 1 !important }", "x: 2"), x(1.0));
+    assert_eq!(
+        cascade("", "", "#a { x: 1 !important }", "x: 2 !important"),
+        x(2.0)
+    );
+    assert_eq!(
+        cascade("", "* { x: 1 !important }", "", "x: 2 !important"),
+        x(1.0)
+    );
+}
+
+#[test]
+fn test_media_resize() {
+    let (dom, stylesheet) = parse_document(
+        "<div id=a>x</div>",
+        "div { color: #000001 } @media (max-width: 500px) { div { color: #000002 } }",
+    );
+    let color = |width: i32| {
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = Au::from_px(width);
+        viewport.content.height = Au::from_px(600);
+        let root_box = layout_tree(&dom, &[], slice::from_ref(&stylesheet), viewport);
+        match find_style(&root_box, "a").unwrap().property["color"][0] {
+            Value::Color(ref color) => color.b,
+            _ => unreachable!(),
+        }
+    };
+
+    assert_eq!(color(800), 1);
+    // Past the breakpoint, the cached style is cascaded again.
+    assert_eq!(color(400), 2);
+    assert_eq!(color(450), 2);
+    assert_eq!(color(800), 1);
+}
+
+#[test]
+fn test_custom_properties() {
+    let root_box = styled(
+        "<div id=a style='--x: 1px; --cycle: var(--y, 1); --y: var(--cycle)'>\
+         <p id=b style='--x: 2px; --z: var(--x) 3px'><span id=c>c</span></p>\
+         <p id=d></p></div>",
+        "p { padding: var(--z, 4px); color: #000001 }\
+         #b { margin: var(--x); width: var(--cycle, 5px) }\
+         #c { --x: var(--missing); padding: 0 var(--x, 6px); color: var(--missing) }\
+         #d { margin: var(--y, 7px); width: var(--cycle) }",
+    );
+    let value = |id: &str, name: &str| {
+        let style = find_style(&root_box, id).unwrap();
+        style.property.get(name).map(|values| {
+            let values: Vec<String> = values
+                .iter()
+                .map(|value| match *value {
+                    Value::Length(n, _) | Value::Num(n) => n.to_string(),
+                    Value::Color(ref color) => color.b.to_string(),
+                    Value::Unparsed(ref tokens) | Value::PendingSubstitution(_, ref tokens) => {
+                        tokens.iter().map(|token| token.to_string()).collect()
+                    }
+                    Value::Keyword(ref keyword)
+                    | Value::String(ref keyword)
+                    | Value::Url(ref keyword) => keyword.clone(),
+                    Value::Calc(ref calc) => calc.to_string(),
+                    Value::Comma => ",".to_string(),
+                    Value::Slash => "/".to_string(),
+                })
+                .collect();
+            values.join(" ")
+        })
+    };
+    let some = |s: &str| Some(s.to_string());
+
+    // Custom properties inherit, and are substituted in the element that declares them.
+    assert_eq!(value("a", "--x"), some("1px"));
+    assert_eq!(value("b", "--z"), some("2px 3px"));
+    assert_eq!(value("b", "padding-top"), some("2"));
+    assert_eq!(value("b", "padding-right"), some("3"));
+    assert_eq!(value("b", "margin-left"), some("2"));
+    assert_eq!(value("c", "--z"), some("2px 3px"));
+    assert_eq!(value("d", "padding-bottom"), some("4"));
+    assert_eq!(value("d", "--x"), some("1px"));
+
+    // Properties in a cycle are invalid, even with a fallback, and fallbacks replace them.
+    assert_eq!(value("a", "--cycle"), None);
+    assert_eq!(value("a", "--y"), None);
+    assert_eq!(value("b", "width"), some("5"));
+    assert_eq!(value("d", "margin-top"), some("7"));
+
+    // Invalid at computed-value time: unset, so inherited or initial.
+    assert_eq!(value("c", "--x"), None);
+    assert_eq!(value("c", "padding-top"), some("0"));
+    assert_eq!(value("c", "padding-left"), some("6"));
+    assert_eq!(value("c", "color"), some("1"));
+    assert_eq!(value("d", "width"), None);
+}
+
+#[test]
+fn test_relative_units() {
+    let (dom, stylesheet) = parse_document(
+        "<div id=a>x</div>",
+        "html { font-size: 2rem } div { width: calc(50vw - 1rem); height: 2rem; padding: 1ex 1ch }",
+    );
+    let style = |width: i32| {
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = Au::from_px(width);
+        viewport.content.height = Au::from_px(600);
+        let root_box = layout_tree(&dom, &[], slice::from_ref(&stylesheet), viewport);
+        find_style(&root_box, "a").unwrap().property.clone()
+    };
+
+    let px = |px| vec![Value::Length(px, Unit::Px)];
+    // rem is relative to the root element's font-size, which is relative to the initial one.
+    assert_eq!(style(800)["width"], px(368.0));
+    assert_eq!(style(800)["height"], px(64.0));
+    assert!(["padding-top", "padding-left"]
+        .iter()
+        .all(|name| style(800)[*name][0].to_px().is_some_and(|px| px > 0.0)));
+    // Viewport units are resolved again when the viewport is resized.
+    assert_eq!(style(400)["width"], px(168.0));
+}
+
+#[test]
+fn test_css_wide_keywords() {
+    let root_box = styled(
+        "<div id=a><p id=b><span id=c>c</span></p></div>",
+        "#a { color: #000001; padding: 1px; border: 2px solid; font-size: large }\
+         #b { color: unset; padding: inherit; border-top: inherit; font-size: smaller }\
+         #c { color: initial; padding: unset; border-style: solid none; font-size: inherit }",
+    );
+    let style = |id: &str| find_style(&root_box, id).unwrap();
+    let px = |px| Value::Length(px, Unit::Px);
+
+    assert_eq!(style("a").font_size(), Au::from_px(18));
+    assert_eq!(style("a").border_width().3, px(2.0));
+    // ``color`` inherits and ``padding`` doesn't, unless asked to.
+    assert_eq!(style("b").color().b, 1);
+    assert_eq!(style("b").padding().1, px(1.0));
+    assert_eq!(style("c").color(), css::BLACK);
+    assert_eq!(style("c").padding().1, px(0.0));
+    // A border without a style has no width, and ``medium`` is 3px.
+    assert_eq!(style("b").border_width().0, px(2.0));
+    assert_eq!(style("b").border_width().3, px(0.0));
+    assert_eq!(
+        style("c").border_width(),
+        (px(3.0), px(0.0), px(3.0), px(0.0))
+    );
+    assert_eq!(style("b").font_size(), Au::from_px(15));
+    assert_eq!(style("c").font_size(), Au::from_px(15));
+}
+
+#[test]
+fn test_inheritance() {
+    let root_box = styled(
+        "<div id=a><div id=b><p id=c>x</p><p id=d>y</p></div></div>",
+        "#a { font-size: 2em; line-height: 150%; letter-spacing: 0.5em; white-space: pre;\
+              list-style: square inside; visibility: hidden; margin-left: 1em }\
+         #b { font-size: 50%; text-indent: 1em; cursor: pointer }\
+         #d { visibility: visible; line-height: 2 }",
+    );
+    let style = |id: &str| find_style(&root_box, id).unwrap();
+    let px = |px| Some(vec![Value::Length(px, Unit::Px)]);
+    let keyword = |k: &str| Some(vec![Value::Keyword(k.to_string())]);
+
+    // Relative lengths compute to px, which the children inherit instead of applying them again.
+    assert_eq!(style("a").font_size(), Au::from_px(32));
+    assert_eq!(style("a").value("margin-left"), px(32.0));
+    assert_eq!(style("b").font_size(), Au::from_px(16));
+    assert_eq!(style("c").font_size(), Au::from_px(16));
+    assert_eq!(style("c").line_height(), Au::from_px(48));
+    assert_eq!(style("c").value("letter-spacing"), px(16.0));
+    assert_eq!(style("c").value("text-indent"), px(16.0));
+    assert_eq!(style("d").line_height(), Au::from_px(32));
+    // Inherited keywords, and ``margin-left`` which doesn't inherit.
+    assert_eq!(style("c").value("white-space"), keyword("pre"));
+    assert_eq!(style("c").value("list-style-type"), keyword("square"));
+    assert_eq!(style("c").value("list-style-position"), keyword("inside"));
+    assert_eq!(style("c").value("cursor"), keyword("pointer"));
+    assert_eq!(style("c").value("visibility"), keyword("hidden"));
+    assert_eq!(style("d").value("visibility"), keyword("visible"));
+    assert_eq!(style("c").value("margin-left"), px(0.0));
+}
+
+#[test]
+fn test_font() {
+    let root_box = styled(
+        "<div id=a><p id=b>x</p><p id=c>y</p><p id=d>z</p></div>",
+        "#a { font: oblique 600 condensed 20px/2 'DejaVu Serif', Liberation Serif, serif }\n\
+         #b { font-weight: bolder; font-family: monospace }\n\
+         #c { font-weight: lighter; font-stretch: 130% }\n\
+         #d { font: 1.5em system-ui }",
+    );
+    let font = |id: &str| find_style(&root_box, id).unwrap().font();
+
+    assert_eq!(
+        font("a"),
+        Font {
+            families: vec![
+                "DejaVu Serif".to_string(),
+                "liberation serif".to_string(),
+                "serif".to_string(),
+            ],
+            size: Au::from_px(20),
+            weight: FontWeight(600),
+            slant: FontSlant::Oblique,
+            stretch: FontStretch::Condensed,
+        }
+    );
+    // ``bolder`` and ``lighter`` are relative to the weight of the parent.
+    assert_eq!(font("b").weight, FontWeight(900));
+    assert_eq!(font("b").families, ["monospace"]);
+    assert_eq!(font("c").weight, FontWeight(400));
+    assert_eq!(font("c").stretch, FontStretch::Expanded);
+    // The shorthand resets what it leaves out.
+    assert_eq!(font("d").size, Au::from_px(30));
+    assert_eq!(font("d").weight, FontWeight::NORMAL);
+    assert_eq!(font("d").slant, FontSlant::Normal);
+    assert_eq!(font("d").stretch, FontStretch::Normal);
+}
+
+#[test]
+fn test_style_sharing() {
+    fn list_items(layout_box: &LayoutBox, colors: &mut Vec<u8>) {
+        if layout_box.node.tag_name() == Some("li")
+            && layout_box.box_type != BoxType::AnonymousBlock
+        {
+            colors.push(layout_box.property.color().b);
+        }
+        for child in &layout_box.children {
+            list_items(child, colors);
+        }
+    }
+
+    let root_box = styled(
+        "<ul><li class=a>1</li><li class=a>2</li><li class=a>3</li><li class=b>4</li>\
+         <li class=a title=t>5</li><li class=a style='color: #000005'>6</li><li class=a id=x>7</li>\
+         </ul>",
+        "li { color: #000001 } li:first-child { color: #000002 } .a + .a { color: #000003 }\
+         [title] { color: #000004 }",
+    );
+    let mut colors = vec![];
+    list_items(&root_box, &mut colors);
+
+    assert_eq!(colors, [2, 3, 3, 1, 4, 5, 3]);
+    // Only the third item can share a style, the second one's.
+    assert_eq!(style_sharing_counts().shared, 1);
+}
@@ -1,8 +1,15 @@
 pub mod css;
+pub mod css_tokenizer;
 pub mod style;
+pub mod properties;
 pub mod default_style;
 pub mod html;
+pub mod html_tokenizer;
+pub mod parse_error;
+pub mod entities;
 pub mod dom;
+pub mod bloom;
+pub mod rule_index;
 pub mod font;
 pub mod inline;
 pub mod block;
@@ -10,6 +17,8 @@ pub mod float;
 pub mod layout;
 pub mod painter;
 pub mod window;
+pub mod headless;
+pub mod reftest;
 pub mod interface;
 
 extern crate app_units;
@@ -1,9 +1,11 @@
 extern crate naglfar;
-use naglfar::interface;
+use naglfar::{interface, reftest};
 
 extern crate clap;
 use clap::{App, Arg};
 
+use std::path::{Path, PathBuf};
+
 const VERSION_STR: &'static str = env!("CARGO_PKG_VERSION");
 
 fn main() {
@@ -15,15 +17,79 @@ fn main() {
             Arg::with_name("URL")
                 .help("Set URL (starts with http(s):// or file://)")
                 .index(1),
+        )
+        .arg(
+            Arg::with_name("png")
+                .help("Render the page without opening a window and write it to the PNG file")
+                .long("png")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("width")
+                .help("Set the viewport width for --png")
+                .long("width")
+                .value_name("PX")
+                .default_value("800"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .help("Set the viewport height for --png")
+                .long("height")
+                .value_name("PX")
+                .default_value("520"),
+        )
+        .arg(
+            Arg::with_name("user-stylesheet")
+                .help("Apply the CSS file as the user stylesheet, which can override the page's")
+                .long("user-stylesheet")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reftest")
+                .help("Run the reference tests listed in the manifest file and exit")
+                .long("reftest")
+                .value_name("MANIFEST")
+                .takes_value(true)
+                .conflicts_with_all(&["URL", "png", "user-stylesheet"]),
         );
     let app_matches = app.clone().get_matches();
 
-    interface::run_with_url(if let Some(url) = app_matches.value_of("URL") {
+    if let Some(manifest) = app_matches.value_of("reftest") {
+        let results = reftest::run_manifest(Path::new(manifest), Path::new("reftest-output"))
+            .unwrap_or_else(|e| panic!("cannot run reftests: {}", e));
+        std::process::exit(if results.iter().all(|r| r.passed) {
+            0
+        } else {
+            1
+        });
+    }
+
+    let url = if let Some(url) = app_matches.value_of("URL") {
         url.to_string()
     } else {
         let mut cur_dir = std::env::current_dir().unwrap();
         cur_dir.push("example");
         cur_dir.push("top.html");
         format!("file://{}", cur_dir.to_str().unwrap())
-    });
+    };
+
+    interface::set_user_stylesheet_path(app_matches.value_of("user-stylesheet").map(PathBuf::from));
+
+    if let Some(png_path) = app_matches.value_of("png") {
+        let width = app_matches
+            .value_of("width")
+            .and_then(|w| w.parse().ok())
+            .filter(|&w| w > 0)
+            .expect("--width must be a positive integer");
+        let height = app_matches
+            .value_of("height")
+            .and_then(|h| h.parse().ok())
+            .filter(|&h| h > 0)
+            .expect("--height must be a positive integer");
+        interface::run_headless(url, width, height, Path::new(png_path));
+    } else {
+        interface::run_with_url(url);
+    }
 }
@@ -1,7 +1,7 @@
 use layout::{BoxType, ImageMetaData, LayoutBox, LayoutInfo, Rect};
 use font::Font;
 use dom::{ElementData, LayoutType, NodeType};
-use css::{Color, TextDecoration, BLACK};
+use css::{Color, TextDecoration};
 use app_units::Au;
 
 use gtk;
@@ -9,7 +9,7 @@ use gtk::{gdk_pixbuf};
 
 use window::{AnkerKind, ANKERS, URL_FRAGMENTS};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
     Image(gdk_pixbuf::Pixbuf, ImageMetaData, Rect),
@@ -17,7 +17,7 @@ pub enum DisplayCommand {
     Button(gtk::Button, Rect),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DisplayCommandInfo {
     pub command: DisplayCommand,
 }
@@ -116,12 +116,9 @@ fn render_text(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox)
         list.push(DisplayCommandInfo::new(DisplayCommand::Text(
             text.to_string(),
             layout_box.dimensions.content.add_parent_coordinate(x, y),
-            match layout_box.property.value("color") {
-                Some(maybe_color) => maybe_color[0].to_color(),
-                _ => None,
-            }.unwrap_or(BLACK),
+            layout_box.property.color(),
             layout_box.property.text_decoration(),
-            text_info.font,
+            text_info.font.clone(),
         )));
     }
 }
@@ -182,7 +179,8 @@ fn register_url_fragment(x: Au, y: Au, layout_box: &mut LayoutBox) {
 }
 
 fn render_background(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
-    lookup_color(layout_box, "background-color", "background").map(|color| {
+    let color = layout_box.property.background_color();
+    if color.a > 0 {
         list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
             color,
             layout_box
@@ -190,7 +188,7 @@ fn render_background(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut Layo
                 .border_box()
                 .add_parent_coordinate(x, y),
         )))
-    });
+    }
 }
 
 fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
@@ -200,7 +198,7 @@ fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutB
     let (top_color, right_color, bottom_color, left_color) = layout_box.property.border_color();
 
     // Left border
-    if let Some(left_color) = left_color {
+    if d.border.left > Au(0) {
         list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
             left_color,
             Rect {
@@ -213,7 +211,7 @@ fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutB
     }
 
     // Right border
-    if let Some(right_color) = right_color {
+    if d.border.right > Au(0) {
         list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
             right_color,
             Rect {
@@ -226,7 +224,7 @@ fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutB
     }
 
     // Top border
-    if let Some(top_color) = top_color {
+    if d.border.top > Au(0) {
         list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
             top_color,
             Rect {
@@ -239,7 +237,7 @@ fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutB
     }
 
     // Bottom border
-    if let Some(bottom_color) = bottom_color {
+    if d.border.bottom > Au(0) {
         list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
             bottom_color,
             Rect {
@@ -251,14 +249,3 @@ fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutB
         )));
     }
 }
-
-/// Return the specified color for CSS property `name` or `fallback_name`, or None if no color was specified.
-fn lookup_color(layout_box: &mut LayoutBox, name: &str, fallback_name: &str) -> Option<Color> {
-    match layout_box
-        .property
-        .lookup_without_default(name, fallback_name)
-    {
-        Some(maybe_color) => maybe_color[0].to_color(),
-        _ => None,
-    }
-}
@@ -0,0 +1,60 @@
+//! Diagnostics reported by the HTML and CSS parsers.
+//!
+//! Neither parser stops at an error: like a browser, they recover and carry on, so the errors
+//! are collected and returned next to the document (see ``html::parse_with_errors`` and
+//! ``css::parse_with_errors``) for tools that want to report them.
+
+use std::{error, fmt};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(line: usize, column: usize, message: S) -> ParseError {
+        ParseError {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    /// Creates an error located at the byte ``offset`` of ``source``.
+    pub fn at<S: Into<String>>(source: &str, offset: usize, message: S) -> ParseError {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+        ParseError::new(
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+            message,
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[test]
+fn test_at() {
+    let source = "ab\ncdé\nf";
+    assert_eq!(ParseError::at(source, 0, "x"), ParseError::new(1, 1, "x"));
+    assert_eq!(ParseError::at(source, 3, "x"), ParseError::new(2, 1, "x"));
+    // The offset after 'é', which takes two bytes.
+    assert_eq!(ParseError::at(source, 7, "x"), ParseError::new(2, 4, "x"));
+    assert_eq!(ParseError::at(source, 100, "x"), ParseError::new(3, 2, "x"));
+    assert_eq!(ParseError::at(source, 3, "x").to_string(), "2:1: x");
+}
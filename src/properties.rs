@@ -0,0 +1,1173 @@
+//! The CSS properties the engine knows about.
+//!
+//! Every longhand has an entry in ``LONGHANDS`` with its initial value, whether it inherits and
+//! the type of its value, which decides how declarations are parsed and how cascaded values are
+//! computed. Shorthands are expanded into their longhands when a declaration is parsed, so the
+//! cascade and ``style::Style`` only ever see longhands.
+//!
+//! Properties that aren't listed are kept as they were declared, and don't inherit.
+
+use css::{self, Unit, Value};
+use style::{Style, DEFAULT_FONT_SIZE, DEFAULT_FONT_WEIGHT};
+
+use std::collections::HashMap;
+use std::slice;
+
+macro_rules! longhand {
+    ($name:expr, $initial:expr, $inherited:expr, $value_type:expr) => {
+        Longhand {
+            name: $name,
+            initial: $initial,
+            inherited: $inherited,
+            value_type: $value_type,
+        }
+    };
+}
+
+pub struct Longhand {
+    pub name: &'static str,
+    /// The initial value, in CSS syntax.
+    pub initial: &'static str,
+    pub inherited: bool,
+    pub value_type: ValueType,
+}
+
+pub struct Shorthand {
+    pub name: &'static str,
+    pub longhands: &'static [&'static str],
+    // Splits the declared values into the values of each longhand. Longhands that are left out
+    // get ``initial``.
+    expand: fn(&[Value]) -> Option<Vec<Vec<Value>>>,
+}
+
+/// The type of a longhand's value, which provides both the parser of declared values and the
+/// type they compute to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueType {
+    /// One of the keywords.
+    Keyword(&'static [&'static str]),
+    /// A length or a percentage, or ``auto`` if ``auto`` is set. Unitless numbers are taken as
+    /// px, as in quirks mode.
+    Length { auto: bool, negative: bool },
+    /// A non-negative length, or ``thin``, ``medium`` or ``thick``, which compute to px.
+    BorderWidth,
+    /// A color or ``currentcolor``.
+    Color,
+    /// A non-negative length or percentage, or a keyword such as ``large`` or ``smaller``,
+    /// which computes to px.
+    FontSize,
+    /// ``normal``, ``bold``, ``bolder``, ``lighter`` or a number from 1 to 1000, which all
+    /// compute to a number.
+    FontWeight,
+    /// A keyword such as ``condensed``, or a non-negative percentage, which the keywords compute
+    /// to.
+    FontStretch,
+    /// ``normal``, or a non-negative number, length or percentage.
+    LineHeight,
+    /// ``none``, or any of ``underline``, ``overline`` and ``line-through``. A text decoration
+    /// style or color may be given too, but is dropped.
+    TextDecoration,
+    /// ``auto`` or an integer.
+    Integer,
+    /// ``normal``, or a length, which may be negative.
+    Spacing,
+    /// A comma-separated list of families, each a generic family such as ``serif``, a string or
+    /// a sequence of identifiers. Generic families are kept as keywords and the other names as
+    /// strings, without the commas.
+    FontFamily,
+    /// ``none``, or an image given as a URL.
+    Image,
+}
+
+pub const MARGIN: [&str; 4] = ["margin-top", "margin-right", "margin-bottom", "margin-left"];
+pub const PADDING: [&str; 4] = [
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+];
+pub const BORDER_WIDTH: [&str; 4] = [
+    "border-top-width",
+    "border-right-width",
+    "border-bottom-width",
+    "border-left-width",
+];
+pub const BORDER_STYLE: [&str; 4] = [
+    "border-top-style",
+    "border-right-style",
+    "border-bottom-style",
+    "border-left-style",
+];
+pub const BORDER_COLOR: [&str; 4] = [
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+];
+
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+const LIST_STYLE_TYPES: &[&str] = &[
+    "disc",
+    "circle",
+    "square",
+    "decimal",
+    "decimal-leading-zero",
+    "lower-roman",
+    "upper-roman",
+    "lower-greek",
+    "lower-alpha",
+    "lower-latin",
+    "upper-alpha",
+    "upper-latin",
+    "armenian",
+    "georgian",
+    "none",
+];
+
+const CURSORS: &[&str] = &[
+    "auto",
+    "default",
+    "none",
+    "context-menu",
+    "help",
+    "pointer",
+    "progress",
+    "wait",
+    "cell",
+    "crosshair",
+    "text",
+    "vertical-text",
+    "alias",
+    "copy",
+    "move",
+    "no-drop",
+    "not-allowed",
+    "grab",
+    "grabbing",
+    "all-scroll",
+    "col-resize",
+    "row-resize",
+    "n-resize",
+    "e-resize",
+    "s-resize",
+    "w-resize",
+    "ne-resize",
+    "nw-resize",
+    "se-resize",
+    "sw-resize",
+    "ew-resize",
+    "ns-resize",
+    "nesw-resize",
+    "nwse-resize",
+    "zoom-in",
+    "zoom-out",
+];
+
+// Absolute font sizes in px.
+const FONT_SIZES: &[(&str, f64)] = &[
+    ("xx-small", 9.0),
+    ("x-small", 10.0),
+    ("small", 13.0),
+    ("medium", 16.0),
+    ("large", 18.0),
+    ("x-large", 24.0),
+    ("xx-large", 32.0),
+    ("xxx-large", 48.0),
+];
+
+// The widths of the ``font-stretch`` keywords, in percent.
+const FONT_STRETCHES: &[(&str, f64)] = &[
+    ("ultra-condensed", 50.0),
+    ("extra-condensed", 62.5),
+    ("condensed", 75.0),
+    ("semi-condensed", 87.5),
+    ("normal", 100.0),
+    ("semi-expanded", 112.5),
+    ("expanded", 125.0),
+    ("extra-expanded", 150.0),
+    ("ultra-expanded", 200.0),
+];
+
+const GENERIC_FAMILIES: &[&str] = &[
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+];
+
+const FONT_STYLES: &[&str] = &["normal", "italic", "oblique"];
+const FONT_VARIANTS: &[&str] = &["normal", "small-caps"];
+
+// Sorted by name.
+pub const LONGHANDS: &[Longhand] = &[
+    longhand!("background-color", "transparent", false, ValueType::Color),
+    longhand!(
+        "border-bottom-color",
+        "currentcolor",
+        false,
+        ValueType::Color
+    ),
+    longhand!(
+        "border-bottom-style",
+        "none",
+        false,
+        ValueType::Keyword(BORDER_STYLES)
+    ),
+    longhand!(
+        "border-bottom-width",
+        "medium",
+        false,
+        ValueType::BorderWidth
+    ),
+    longhand!(
+        "border-collapse",
+        "separate",
+        true,
+        ValueType::Keyword(&["separate", "collapse"])
+    ),
+    longhand!("border-left-color", "currentcolor", false, ValueType::Color),
+    longhand!(
+        "border-left-style",
+        "none",
+        false,
+        ValueType::Keyword(BORDER_STYLES)
+    ),
+    longhand!("border-left-width", "medium", false, ValueType::BorderWidth),
+    longhand!(
+        "border-right-color",
+        "currentcolor",
+        false,
+        ValueType::Color
+    ),
+    longhand!(
+        "border-right-style",
+        "none",
+        false,
+        ValueType::Keyword(BORDER_STYLES)
+    ),
+    longhand!(
+        "border-right-width",
+        "medium",
+        false,
+        ValueType::BorderWidth
+    ),
+    longhand!("border-top-color", "currentcolor", false, ValueType::Color),
+    longhand!(
+        "border-top-style",
+        "none",
+        false,
+        ValueType::Keyword(BORDER_STYLES)
+    ),
+    longhand!("border-top-width", "medium", false, ValueType::BorderWidth),
+    longhand!(
+        "caption-side",
+        "top",
+        true,
+        ValueType::Keyword(&["top", "bottom"])
+    ),
+    longhand!(
+        "clear",
+        "none",
+        false,
+        ValueType::Keyword(&["none", "left", "right", "both"])
+    ),
+    longhand!("color", "black", true, ValueType::Color),
+    longhand!("cursor", "auto", true, ValueType::Keyword(CURSORS)),
+    longhand!(
+        "direction",
+        "ltr",
+        true,
+        ValueType::Keyword(&["ltr", "rtl"])
+    ),
+    longhand!(
+        "display",
+        "inline",
+        false,
+        ValueType::Keyword(&[
+            "inline",
+            "block",
+            "inline-block",
+            "none",
+            "list-item",
+            "flow-root",
+            "contents",
+            "flex",
+            "inline-flex",
+            "grid",
+            "inline-grid",
+            "table",
+            "inline-table",
+            "table-row",
+            "table-cell",
+        ])
+    ),
+    longhand!(
+        "empty-cells",
+        "show",
+        true,
+        ValueType::Keyword(&["show", "hide"])
+    ),
+    longhand!(
+        "float",
+        "none",
+        false,
+        ValueType::Keyword(&["none", "left", "right"])
+    ),
+    longhand!("font-family", "sans-serif", true, ValueType::FontFamily),
+    longhand!("font-size", "medium", true, ValueType::FontSize),
+    longhand!("font-stretch", "normal", true, ValueType::FontStretch),
+    longhand!(
+        "font-style",
+        "normal",
+        true,
+        ValueType::Keyword(FONT_STYLES)
+    ),
+    longhand!(
+        "font-variant",
+        "normal",
+        true,
+        ValueType::Keyword(FONT_VARIANTS)
+    ),
+    longhand!("font-weight", "normal", true, ValueType::FontWeight),
+    longhand!("height", "auto", false, LENGTH_OR_AUTO),
+    longhand!(
+        "hyphens",
+        "manual",
+        true,
+        ValueType::Keyword(&["none", "manual", "auto"])
+    ),
+    longhand!("letter-spacing", "normal", true, ValueType::Spacing),
+    longhand!("line-height", "normal", true, ValueType::LineHeight),
+    longhand!("list-style-image", "none", true, ValueType::Image),
+    longhand!(
+        "list-style-position",
+        "outside",
+        true,
+        ValueType::Keyword(&["inside", "outside"])
+    ),
+    longhand!(
+        "list-style-type",
+        "disc",
+        true,
+        ValueType::Keyword(LIST_STYLE_TYPES)
+    ),
+    longhand!("margin-bottom", "0", false, MARGIN_TYPE),
+    longhand!("margin-left", "0", false, MARGIN_TYPE),
+    longhand!("margin-right", "0", false, MARGIN_TYPE),
+    longhand!("margin-top", "0", false, MARGIN_TYPE),
+    longhand!(
+        "overflow-wrap",
+        "normal",
+        true,
+        ValueType::Keyword(&["normal", "break-word", "anywhere"])
+    ),
+    longhand!("padding-bottom", "0", false, PADDING_TYPE),
+    longhand!("padding-left", "0", false, PADDING_TYPE),
+    longhand!("padding-right", "0", false, PADDING_TYPE),
+    longhand!("padding-top", "0", false, PADDING_TYPE),
+    longhand!(
+        "text-align",
+        "start",
+        true,
+        ValueType::Keyword(&["start", "end", "left", "right", "center", "justify"])
+    ),
+    longhand!("text-decoration", "none", false, ValueType::TextDecoration),
+    longhand!("text-indent", "0", true, TEXT_INDENT_TYPE),
+    longhand!(
+        "text-transform",
+        "none",
+        true,
+        ValueType::Keyword(&["none", "capitalize", "uppercase", "lowercase", "full-width"])
+    ),
+    longhand!(
+        "visibility",
+        "visible",
+        true,
+        ValueType::Keyword(&["visible", "hidden", "collapse"])
+    ),
+    longhand!(
+        "white-space",
+        "normal",
+        true,
+        ValueType::Keyword(&[
+            "normal",
+            "pre",
+            "nowrap",
+            "pre-wrap",
+            "pre-line",
+            "break-spaces"
+        ])
+    ),
+    longhand!("width", "auto", false, LENGTH_OR_AUTO),
+    longhand!(
+        "word-break",
+        "normal",
+        true,
+        ValueType::Keyword(&["normal", "break-all", "keep-all", "break-word"])
+    ),
+    longhand!("word-spacing", "normal", true, ValueType::Spacing),
+    longhand!("z-index", "auto", false, ValueType::Integer),
+];
+
+// Sorted by name.
+pub const SHORTHANDS: &[Shorthand] = &[
+    Shorthand {
+        name: "background",
+        longhands: &["background-color"],
+        expand: expand_background,
+    },
+    Shorthand {
+        name: "border",
+        longhands: &[
+            "border-top-width",
+            "border-top-style",
+            "border-top-color",
+            "border-right-width",
+            "border-right-style",
+            "border-right-color",
+            "border-bottom-width",
+            "border-bottom-style",
+            "border-bottom-color",
+            "border-left-width",
+            "border-left-style",
+            "border-left-color",
+        ],
+        expand: expand_border,
+    },
+    Shorthand {
+        name: "border-bottom",
+        longhands: &[
+            "border-bottom-width",
+            "border-bottom-style",
+            "border-bottom-color",
+        ],
+        expand: expand_border_side,
+    },
+    Shorthand {
+        name: "border-color",
+        longhands: &BORDER_COLOR,
+        expand: expand_box,
+    },
+    Shorthand {
+        name: "border-left",
+        longhands: &[
+            "border-left-width",
+            "border-left-style",
+            "border-left-color",
+        ],
+        expand: expand_border_side,
+    },
+    Shorthand {
+        name: "border-right",
+        longhands: &[
+            "border-right-width",
+            "border-right-style",
+            "border-right-color",
+        ],
+        expand: expand_border_side,
+    },
+    Shorthand {
+        name: "border-style",
+        longhands: &BORDER_STYLE,
+        expand: expand_box,
+    },
+    Shorthand {
+        name: "border-top",
+        longhands: &["border-top-width", "border-top-style", "border-top-color"],
+        expand: expand_border_side,
+    },
+    Shorthand {
+        name: "border-width",
+        longhands: &BORDER_WIDTH,
+        expand: expand_box,
+    },
+    Shorthand {
+        name: "font",
+        longhands: &[
+            "font-style",
+            "font-variant",
+            "font-weight",
+            "font-stretch",
+            "font-size",
+            "line-height",
+            "font-family",
+        ],
+        expand: expand_font,
+    },
+    Shorthand {
+        name: "list-style",
+        longhands: &["list-style-type", "list-style-position", "list-style-image"],
+        expand: expand_list_style,
+    },
+    Shorthand {
+        name: "margin",
+        longhands: &MARGIN,
+        expand: expand_box,
+    },
+    Shorthand {
+        name: "padding",
+        longhands: &PADDING,
+        expand: expand_box,
+    },
+];
+
+const LENGTH_OR_AUTO: ValueType = ValueType::Length {
+    auto: true,
+    negative: false,
+};
+const MARGIN_TYPE: ValueType = ValueType::Length {
+    auto: true,
+    negative: true,
+};
+const TEXT_INDENT_TYPE: ValueType = ValueType::Length {
+    auto: false,
+    negative: true,
+};
+const PADDING_TYPE: ValueType = ValueType::Length {
+    auto: false,
+    negative: false,
+};
+
+thread_local!(
+    // The computed initial values, in the order of ``LONGHANDS``.
+    static INITIAL_VALUES: Vec<Vec<Value>> = LONGHANDS
+        .iter()
+        .map(|longhand| {
+            let value = css::parse_value(longhand.initial.to_string());
+            let values = longhand.value_type.parse(slice::from_ref(&value)).unwrap();
+            longhand
+                .value_type
+                .compute(&values, DEFAULT_FONT_SIZE, DEFAULT_FONT_WEIGHT)
+        })
+        .collect();
+);
+
+pub fn longhand(name: &str) -> Option<&'static Longhand> {
+    LONGHANDS
+        .binary_search_by_key(&name, |longhand| longhand.name)
+        .ok()
+        .map(|i| &LONGHANDS[i])
+}
+
+pub fn shorthand(name: &str) -> Option<&'static Shorthand> {
+    SHORTHANDS
+        .binary_search_by_key(&name, |shorthand| shorthand.name)
+        .ok()
+        .map(|i| &SHORTHANDS[i])
+}
+
+/// Custom properties inherit too.
+pub fn is_inherited(name: &str) -> bool {
+    name.starts_with("--") || longhand(name).is_some_and(|longhand| longhand.inherited)
+}
+
+pub fn initial_value(name: &str) -> Option<Vec<Value>> {
+    let i = LONGHANDS
+        .binary_search_by_key(&name, |longhand| longhand.name)
+        .ok()?;
+    Some(INITIAL_VALUES.with(|initial_values| initial_values[i].clone()))
+}
+
+fn is_css_wide_keyword(value: &Value) -> bool {
+    matches!(value, Value::Keyword(k) if k == "inherit" || k == "initial" || k == "unset")
+}
+
+/// Parses the declared values of a property into the values of its longhands. Returns None if
+/// the values are invalid for the property.
+pub fn parse(name: &str, values: &[Value]) -> Option<Vec<(String, Vec<Value>)>> {
+    if values.iter().any(is_css_wide_keyword) {
+        // ``inherit``, ``initial`` and ``unset`` can't be combined with other values.
+        if values.len() != 1 {
+            return None;
+        }
+        let longhands =
+            shorthand(name).map_or(vec![name], |shorthand| shorthand.longhands.to_vec());
+        return Some(
+            longhands
+                .into_iter()
+                .map(|longhand| (longhand.to_string(), values.to_vec()))
+                .collect(),
+        );
+    }
+
+    if let Some(shorthand) = shorthand(name) {
+        let values = (shorthand.expand)(values)?;
+        return shorthand
+            .longhands
+            .iter()
+            .zip(values)
+            .map(|(&name, values)| {
+                if values.iter().any(is_css_wide_keyword) {
+                    return Some((name.to_string(), values));
+                }
+                let value_type = longhand(name).unwrap().value_type;
+                Some((name.to_string(), value_type.parse(&values)?))
+            })
+            .collect();
+    }
+
+    match longhand(name) {
+        Some(longhand) => Some(vec![(name.to_string(), longhand.value_type.parse(values)?)]),
+        None => Some(vec![(name.to_string(), values.to_vec())]),
+    }
+}
+
+/// Computes the cascaded ``values`` of an element whose parent has the computed values
+/// ``parent``. ``inherit``, ``initial`` and ``unset`` are resolved, and keywords that stand for
+/// lengths are converted. Lengths in other units than px are left to the layout, see
+/// ``layout::resolve_lengths``.
+pub fn compute(values: &mut HashMap<String, Vec<Value>>, parent: &Style) {
+    let keywords: Vec<(String, bool)> = values
+        .iter()
+        .filter_map(|(name, value)| match &value[..] {
+            [Value::Keyword(k)] if k == "inherit" => Some((name.clone(), true)),
+            [Value::Keyword(k)] if k == "unset" => Some((name.clone(), is_inherited(name))),
+            [Value::Keyword(k)] if k == "initial" => Some((name.clone(), false)),
+            // ``color: currentcolor`` is the same as ``color: inherit``.
+            [Value::Keyword(k)] if k == "currentcolor" && name == "color" => {
+                Some((name.clone(), true))
+            }
+            _ => None,
+        })
+        .collect();
+    // The values that are left out are the initial ones.
+    for (name, inherit) in keywords {
+        match parent.property.get(&name) {
+            Some(value) if inherit => values.insert(name, value.clone()),
+            _ => values.remove(&name),
+        };
+    }
+
+    let parent_font_size = parent.font_size().to_f64_px();
+    let parent_font_weight = parent.font_weight().0 as f64;
+    for (name, value) in values.iter_mut() {
+        if let Some(longhand) = longhand(name) {
+            *value = longhand
+                .value_type
+                .compute(value, parent_font_size, parent_font_weight);
+        }
+    }
+
+    // Borders without a style have no width.
+    for (style, width) in BORDER_STYLE.iter().zip(BORDER_WIDTH.iter()) {
+        let has_style = values.get(*style).is_some_and(
+            |style| !matches!(&style[0], Value::Keyword(k) if k == "none" || k == "hidden"),
+        );
+        if !has_style {
+            values.insert(width.to_string(), vec![Value::Length(0.0, Unit::Px)]);
+        }
+    }
+}
+
+impl ValueType {
+    /// Checks the declared values and brings them into a canonical form. Returns None if they
+    /// are invalid.
+    pub fn parse(&self, values: &[Value]) -> Option<Vec<Value>> {
+        match *self {
+            ValueType::TextDecoration => return parse_text_decoration(values),
+            ValueType::FontFamily => return parse_font_family(values),
+            _ => {}
+        }
+        let value = match values {
+            [value] => value,
+            _ => return None,
+        };
+        let is_keyword = |keywords: &[&str]| matches!(value, Value::Keyword(k) if keywords.contains(&k.as_str()));
+        let value = match *self {
+            ValueType::Keyword(keywords) if is_keyword(keywords) => value.clone(),
+            ValueType::Keyword(_) => return None,
+            ValueType::Length { auto: true, .. } if is_keyword(&["auto"]) => value.clone(),
+            ValueType::Length { negative, .. } => length_percentage(value, negative)?,
+            ValueType::BorderWidth if is_keyword(&["thin", "medium", "thick"]) => value.clone(),
+            ValueType::BorderWidth => match length_percentage(value, false)? {
+                Value::Length(_, Unit::Percent) => return None,
+                length => length,
+            },
+            ValueType::Color if is_keyword(&["currentcolor"]) || value.to_color().is_some() => {
+                value.clone()
+            }
+            ValueType::Color => return None,
+            ValueType::FontSize if is_keyword(&["larger", "smaller"]) => value.clone(),
+            ValueType::FontSize => match *value {
+                Value::Keyword(ref k) if FONT_SIZES.iter().any(|&(name, _)| name == k.as_str()) => {
+                    value.clone()
+                }
+                _ => length_percentage(value, false)?,
+            },
+            ValueType::FontWeight if is_keyword(&["normal", "bold", "bolder", "lighter"]) => {
+                value.clone()
+            }
+            ValueType::FontWeight => match number(value)? {
+                weight if (1.0..=1000.0).contains(&weight) => value.clone(),
+                _ => return None,
+            },
+            ValueType::FontStretch => match *value {
+                Value::Keyword(ref k) if FONT_STRETCHES.iter().any(|&(name, _)| name == k) => {
+                    value.clone()
+                }
+                Value::Length(n, Unit::Percent) if n >= 0.0 => value.clone(),
+                _ => return None,
+            },
+            ValueType::LineHeight if is_keyword(&["normal"]) => value.clone(),
+            ValueType::LineHeight => match number(value) {
+                Some(n) if n >= 0.0 => value.clone(),
+                Some(_) => return None,
+                None => length_percentage(value, false)?,
+            },
+            ValueType::Integer if is_keyword(&["auto"]) => value.clone(),
+            ValueType::Integer => match number(value)? {
+                n if n.fract() == 0.0 => value.clone(),
+                _ => return None,
+            },
+            ValueType::Spacing if is_keyword(&["normal"]) => value.clone(),
+            ValueType::Spacing => match length_percentage(value, true)? {
+                Value::Length(_, Unit::Percent) => return None,
+                length => length,
+            },
+            ValueType::Image if is_keyword(&["none"]) => value.clone(),
+            ValueType::Image => match *value {
+                Value::Url(_) => value.clone(),
+                _ => return None,
+            },
+            ValueType::TextDecoration | ValueType::FontFamily => unreachable!(),
+        };
+        Some(vec![value])
+    }
+
+    /// Converts keywords into the values they compute to.
+    pub fn compute(
+        &self,
+        values: &[Value],
+        parent_font_size: f64,
+        parent_font_weight: f64,
+    ) -> Vec<Value> {
+        let px = |px| vec![Value::Length(px, Unit::Px)];
+        match (*self, values) {
+            (ValueType::BorderWidth, [Value::Keyword(k)]) => match k.as_str() {
+                "thin" => px(1.0),
+                "thick" => px(5.0),
+                _ => px(3.0),
+            },
+            (ValueType::FontSize, [Value::Keyword(k)]) => match k.as_str() {
+                "larger" => px(parent_font_size * 1.2),
+                "smaller" => px(parent_font_size / 1.2),
+                k => px(FONT_SIZES
+                    .iter()
+                    .find(|&&(name, _)| name == k)
+                    .map_or(DEFAULT_FONT_SIZE, |&(_, size)| size)),
+            },
+            (ValueType::FontWeight, [value]) => vec![Value::Num(match *value {
+                Value::Keyword(ref k) if k == "bold" => 700.0,
+                Value::Keyword(ref k) if k == "bolder" => bolder(parent_font_weight),
+                Value::Keyword(ref k) if k == "lighter" => lighter(parent_font_weight),
+                _ => number(value).unwrap_or(DEFAULT_FONT_WEIGHT),
+            })],
+            (ValueType::FontStretch, [Value::Keyword(k)]) => vec![Value::Length(
+                FONT_STRETCHES
+                    .iter()
+                    .find(|&&(name, _)| name == k.as_str())
+                    .map_or(100.0, |&(_, width)| width),
+                Unit::Percent,
+            )],
+            _ => values.to_vec(),
+        }
+    }
+}
+
+fn length_percentage(value: &Value, negative: bool) -> Option<Value> {
+    match *value {
+        Value::Length(n, _) if negative || n >= 0.0 => Some(value.clone()),
+        Value::Num(n) if negative || n >= 0.0 => Some(Value::Length(n, Unit::Px)),
+        Value::Calc(ref calc) if !calc.is_number() => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Num(n) => Some(n),
+        Value::Calc(ref calc) if calc.is_number() => calc.to_px(None),
+        _ => None,
+    }
+}
+
+// The weights of ``bolder`` and ``lighter`` relative to the weight of the parent.
+fn bolder(weight: f64) -> f64 {
+    if weight < 350.0 {
+        400.0
+    } else if weight < 550.0 {
+        700.0
+    } else {
+        weight.max(900.0)
+    }
+}
+
+fn lighter(weight: f64) -> f64 {
+    if weight < 100.0 {
+        weight
+    } else if weight < 550.0 {
+        100.0
+    } else if weight < 750.0 {
+        400.0
+    } else {
+        700.0
+    }
+}
+
+fn parse_font_family(values: &[Value]) -> Option<Vec<Value>> {
+    values
+        .split(|value| *value == Value::Comma)
+        .map(|family| match family {
+            [Value::Keyword(k)] if GENERIC_FAMILIES.contains(&k.as_str()) => {
+                Some(family[0].clone())
+            }
+            [Value::String(_)] => Some(family[0].clone()),
+            [] => None,
+            // Unquoted names are identifiers separated by spaces.
+            _ => family
+                .iter()
+                .map(|value| match *value {
+                    Value::Keyword(ref k) if k != "default" => Some(k.as_str()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|words| Value::String(words.join(" "))),
+        })
+        .collect()
+}
+
+fn parse_text_decoration(values: &[Value]) -> Option<Vec<Value>> {
+    const LINES: &[&str] = &["underline", "overline", "line-through"];
+    const STYLES: &[&str] = &["solid", "double", "dotted", "dashed", "wavy"];
+    if let [Value::Keyword(k)] = values {
+        if k == "none" {
+            return Some(values.to_vec());
+        }
+    }
+    let mut lines = vec![];
+    for value in values {
+        match value {
+            Value::Keyword(k) if LINES.contains(&k.as_str()) && !lines.contains(value) => {
+                lines.push(value.clone())
+            }
+            Value::Keyword(k) if STYLES.contains(&k.as_str()) => {}
+            _ if ValueType::Color.parse(slice::from_ref(value)).is_some() => {}
+            _ => return None,
+        }
+    }
+    if lines.is_empty() {
+        lines.push(Value::Keyword("none".to_string()));
+    }
+    Some(lines)
+}
+
+fn initial() -> Value {
+    Value::Keyword("initial".to_string())
+}
+
+// The values of a property for each side, in the order top, right, bottom and left.
+fn expand_box(values: &[Value]) -> Option<Vec<Vec<Value>>> {
+    let (top, right, bottom, left) = match values {
+        [all] => (all, all, all, all),
+        [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+        [top, horizontal, bottom] => (top, horizontal, bottom, horizontal),
+        [top, right, bottom, left] => (top, right, bottom, left),
+        _ => return None,
+    };
+    Some(vec![
+        vec![top.clone()],
+        vec![right.clone()],
+        vec![bottom.clone()],
+        vec![left.clone()],
+    ])
+}
+
+// A border width, style and color, in any order.
+fn expand_border_side(values: &[Value]) -> Option<Vec<Vec<Value>>> {
+    let types = [
+        ValueType::BorderWidth,
+        ValueType::Keyword(BORDER_STYLES),
+        ValueType::Color,
+    ];
+    let mut parts = [None, None, None];
+    if values.is_empty() {
+        return None;
+    }
+    for value in values {
+        let i = (0..3)
+            .find(|&i| parts[i].is_none() && types[i].parse(slice::from_ref(value)).is_some())?;
+        parts[i] = Some(value.clone());
+    }
+    Some(
+        parts
+            .iter()
+            .map(|part| vec![part.clone().unwrap_or_else(initial)])
+            .collect(),
+    )
+}
+
+fn expand_border(values: &[Value]) -> Option<Vec<Vec<Value>>> {
+    let side = expand_border_side(values)?;
+    Some(side.iter().cycle().take(12).cloned().collect())
+}
+
+// A list style type, position and image, in any order. ``none`` is the type and the image, or
+// whichever of them isn't given otherwise.
+fn expand_list_style(values: &[Value]) -> Option<Vec<Vec<Value>>> {
+    let types = [
+        ValueType::Keyword(LIST_STYLE_TYPES),
+        ValueType::Keyword(&["inside", "outside"]),
+        ValueType::Image,
+    ];
+    let none = Value::Keyword("none".to_string());
+    let mut parts = [None, None, None];
+    let mut nones = 0;
+    if values.is_empty() {
+        return None;
+    }
+    for value in values {
+        if *value == none {
+            nones += 1;
+            continue;
+        }
+        let i = (0..3)
+            .find(|&i| parts[i].is_none() && types[i].parse(slice::from_ref(value)).is_some())?;
+        parts[i] = Some(value.clone());
+    }
+    let unset: Vec<usize> = [0, 2]
+        .iter()
+        .cloned()
+        .filter(|&i| parts[i].is_none())
+        .collect();
+    if nones > unset.len() {
+        return None;
+    }
+    if nones > 0 {
+        for i in unset {
+            parts[i] = Some(none.clone());
+        }
+    }
+    Some(
+        parts
+            .iter()
+            .map(|part| vec![part.clone().unwrap_or_else(initial)])
+            .collect(),
+    )
+}
+
+// ``[style || variant || weight || stretch]? size [/ line-height]? family``, where only the
+// CSS 2 keywords of the variant and stretch are allowed. ``normal`` may stand for any of the
+// first four.
+fn expand_font(values: &[Value]) -> Option<Vec<Vec<Value>>> {
+    let types = [
+        ValueType::Keyword(FONT_STYLES),
+        ValueType::Keyword(FONT_VARIANTS),
+        ValueType::FontWeight,
+        ValueType::Keyword(&[
+            "ultra-condensed",
+            "extra-condensed",
+            "condensed",
+            "semi-condensed",
+            "semi-expanded",
+            "expanded",
+            "extra-expanded",
+            "ultra-expanded",
+        ]),
+    ];
+    let normal = Value::Keyword("normal".to_string());
+    let mut parts = vec![None; 7];
+    let mut values = values;
+    let mut prefix = 0;
+    loop {
+        let value = values.first()?;
+        // A number is a weight rather than a size in px.
+        let is_size = match *value {
+            Value::Num(_) => false,
+            _ => ValueType::FontSize.parse(slice::from_ref(value)).is_some(),
+        };
+        if is_size {
+            break;
+        }
+        if prefix == 4 {
+            return None;
+        }
+        if *value != normal {
+            let i = (0..4).find(|&i| {
+                parts[i].is_none() && types[i].parse(slice::from_ref(value)).is_some()
+            })?;
+            parts[i] = Some(vec![value.clone()]);
+        }
+        prefix += 1;
+        values = &values[1..];
+    }
+    parts[4] = Some(vec![values[0].clone()]);
+    values = &values[1..];
+    if values.first() == Some(&Value::Slash) {
+        parts[5] = Some(vec![values.get(1)?.clone()]);
+        values = &values[2..];
+    }
+    // The family is checked along with the other longhands.
+    parts[6] = Some(values.to_vec());
+    Some(
+        parts
+            .into_iter()
+            .map(|part| part.unwrap_or_else(|| vec![initial()]))
+            .collect(),
+    )
+}
+
+// Only the color of a background is supported, the rest is ignored.
+fn expand_background(values: &[Value]) -> Option<Vec<Vec<Value>>> {
+    let color = values
+        .iter()
+        .find(|value| ValueType::Color.parse(slice::from_ref(value)).is_some())
+        .cloned();
+    Some(vec![vec![color.unwrap_or_else(initial)]])
+}
+
+#[test]
+fn test_tables_are_sorted() {
+    assert!(LONGHANDS.windows(2).all(|w| w[0].name < w[1].name));
+    assert!(SHORTHANDS.windows(2).all(|w| w[0].name < w[1].name));
+    for shorthand in SHORTHANDS {
+        assert!(shorthand
+            .longhands
+            .iter()
+            .all(|name| longhand(name).is_some()));
+    }
+}
+
+#[test]
+fn test_parse() {
+    let expand = |name: &str, source: &str| {
+        let values: Vec<Value> = css::parse_attr_style(format!("x: {}", source))
+            .pop()
+            .map_or(vec![], |declaration| declaration.values);
+        parse(name, &values).map(|longhands| {
+            longhands
+                .into_iter()
+                .map(|(name, values)| {
+                    let values: Vec<String> = values
+                        .iter()
+                        .map(|value| match *value {
+                            Value::Length(n, ref unit) => format!("{}{}", n, unit),
+                            Value::Num(n) => n.to_string(),
+                            Value::Keyword(ref k) => k.clone(),
+                            Value::String(ref s) => format!("{:?}", s),
+                            Value::Color(c) => format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b),
+                            _ => format!("{:?}", value),
+                        })
+                        .collect();
+                    format!("{}: {}", name, values.join(" "))
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+    let some = |longhands: &[&str]| Some(longhands.iter().map(|s| s.to_string()).collect());
+
+    assert_eq!(
+        expand("margin", "1px auto 0"),
+        some(&[
+            "margin-top: 1px",
+            "margin-right: auto",
+            "margin-bottom: 0px",
+            "margin-left: auto"
+        ])
+    );
+    assert_eq!(expand("padding", "1px -2px"), None);
+    assert_eq!(expand("padding", "1px 2px 3px 4px 5px"), None);
+    assert_eq!(
+        expand("border-left", "red thick"),
+        some(&[
+            "border-left-width: thick",
+            "border-left-style: initial",
+            "border-left-color: red",
+        ])
+    );
+    assert_eq!(expand("border-left", "1px 2px"), None);
+    assert_eq!(
+        expand("border", "solid").map(|longhands| longhands.len()),
+        Some(12)
+    );
+    assert_eq!(
+        expand("background", "url(a.png) #fff"),
+        some(&["background-color: #ffffff"])
+    );
+    assert_eq!(
+        expand("border-style", "inherit").map(|longhands| longhands.len()),
+        Some(4)
+    );
+    assert_eq!(expand("width", "auto inherit"), None);
+    assert_eq!(expand("width", "-1px"), None);
+    assert_eq!(expand("display", "blocky"), None);
+    assert_eq!(expand("line-height", "1.5"), some(&["line-height: 1.5"]));
+    assert_eq!(expand("font-weight", "1001"), None);
+    assert_eq!(expand("z-index", "1.5"), None);
+    assert_eq!(
+        expand("text-decoration", "underline red wavy"),
+        some(&["text-decoration: underline"])
+    );
+    assert_eq!(
+        expand("list-style", "inside none"),
+        some(&[
+            "list-style-type: none",
+            "list-style-position: inside",
+            "list-style-image: none",
+        ])
+    );
+    assert_eq!(
+        expand("list-style", "none square"),
+        some(&[
+            "list-style-type: square",
+            "list-style-position: initial",
+            "list-style-image: none",
+        ])
+    );
+    assert_eq!(expand("list-style", "none disc none"), None);
+    assert_eq!(
+        expand("list-style-image", "url(a.png)"),
+        some(&[r#"list-style-image: Url("a.png")"#])
+    );
+    assert_eq!(expand("list-style-image", "5px"), None);
+    assert_eq!(expand("list-style", "square 5px"), None);
+    assert_eq!(
+        expand("letter-spacing", "-0.1em"),
+        some(&["letter-spacing: -0.1em"])
+    );
+    assert_eq!(expand("word-spacing", "10%"), None);
+    assert_eq!(
+        expand("font-family", "Helvetica Neue, 'Arial', sans-serif"),
+        some(&[r#"font-family: "helvetica neue" "Arial" sans-serif"#])
+    );
+    assert_eq!(expand("font-family", "serif,"), None);
+    assert_eq!(expand("font-family", "a 1px"), None);
+    assert_eq!(expand("font-stretch", "-10%"), None);
+    assert_eq!(
+        expand("font", "italic bold 12px/30px Georgia, serif"),
+        some(&[
+            "font-style: italic",
+            "font-variant: initial",
+            "font-weight: bold",
+            "font-stretch: initial",
+            "font-size: 12px",
+            "line-height: 30px",
+            r#"font-family: "georgia" serif"#,
+        ])
+    );
+    assert_eq!(
+        expand("font", "normal normal 300 80% monospace").map(|longhands| longhands[2].clone()),
+        Some("font-weight: 300".to_string())
+    );
+    assert_eq!(expand("font", "bold 12px"), None);
+    assert_eq!(expand("font", "bold serif"), None);
+    assert_eq!(
+        expand("font", "normal normal normal normal normal 12px serif"),
+        None
+    );
+    assert_eq!(expand("unknown", "1 2"), some(&["unknown: 1 2"]));
+}
@@ -0,0 +1,245 @@
+//! Reference tests: render a test page and a reference page headlessly and check that they
+//! look the same (or, for ``!=`` tests, that they don't).
+//!
+//! A manifest lists one test per line, with paths relative to the manifest:
+//!
+//! ```text
+//! # comment
+//! == block-padding.html block-padding-ref.html
+//! != text-color.html text-color-notref.html
+//! ```
+
+use headless;
+use painter::DisplayList;
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use gtk::cairo;
+
+pub const VIEWPORT_WIDTH: i32 = 800;
+pub const VIEWPORT_HEIGHT: i32 = 600;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Relation {
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefTest {
+    pub relation: Relation,
+    pub test: PathBuf,
+    pub reference: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct RefTestResult {
+    pub reftest: RefTest,
+    pub passed: bool,
+    /// Number of pixels that differ between the two renderings.
+    pub differing_pixels: usize,
+    /// Written only when an ``==`` test fails.
+    pub diff_image: Option<PathBuf>,
+}
+
+pub fn parse_manifest(manifest_path: &Path) -> io::Result<Vec<RefTest>> {
+    let base_dir = manifest_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let mut reftests = vec![];
+
+    for (i, line) in BufReader::new(File::open(manifest_path)?)
+        .lines()
+        .enumerate()
+    {
+        let line = line?;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let relation = match fields[0] {
+            "==" if fields.len() == 3 => Relation::Equal,
+            "!=" if fields.len() == 3 => Relation::NotEqual,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}:{}: expected '== test reference' or '!= test reference'",
+                        manifest_path.display(),
+                        i + 1
+                    ),
+                ))
+            }
+        };
+        reftests.push(RefTest {
+            relation,
+            test: base_dir.join(fields[1]),
+            reference: base_dir.join(fields[2]),
+        });
+    }
+
+    Ok(reftests)
+}
+
+/// Runs every test in the manifest, printing one line per test, and writes diff images for
+/// failures into ``output_dir``.
+pub fn run_manifest(manifest_path: &Path, output_dir: &Path) -> io::Result<Vec<RefTestResult>> {
+    let reftests = parse_manifest(manifest_path)?;
+    fs::create_dir_all(output_dir)?;
+
+    let mut results = vec![];
+    for reftest in reftests {
+        let result = run(&reftest, output_dir)?;
+        println!(
+            "{} | {} {} {}{}",
+            if result.passed { "PASS" } else { "FAIL" },
+            reftest.test.display(),
+            match reftest.relation {
+                Relation::Equal => "==",
+                Relation::NotEqual => "!=",
+            },
+            reftest.reference.display(),
+            match result.diff_image {
+                Some(ref diff) => format!(
+                    " ({} pixels differ, see {})",
+                    result.differing_pixels,
+                    diff.display()
+                ),
+                None => "".to_string(),
+            }
+        );
+        results.push(result);
+    }
+
+    let passed = results.iter().filter(|r| r.passed).count();
+    println!("{} passed, {} failed", passed, results.len() - passed);
+
+    Ok(results)
+}
+
+pub fn run(reftest: &RefTest, output_dir: &Path) -> io::Result<RefTestResult> {
+    let test_items = headless::render(file_url(&reftest.test)?, VIEWPORT_WIDTH, VIEWPORT_HEIGHT);
+    let ref_items = headless::render(
+        file_url(&reftest.reference)?,
+        VIEWPORT_WIDTH,
+        VIEWPORT_HEIGHT,
+    );
+
+    // Identical display lists always paint identical pixels, so there is no need to rasterize.
+    if test_items == ref_items {
+        return Ok(RefTestResult {
+            passed: reftest.relation == Relation::Equal,
+            reftest: reftest.clone(),
+            differing_pixels: 0,
+            diff_image: None,
+        });
+    }
+
+    let mut test_surface = paint(&test_items)?;
+    let mut ref_surface = paint(&ref_items)?;
+    let (differing_pixels, diff_surface) = compare(&mut test_surface, &mut ref_surface);
+
+    let passed = match reftest.relation {
+        Relation::Equal => differing_pixels == 0,
+        Relation::NotEqual => differing_pixels > 0,
+    };
+
+    let diff_image = if !passed && reftest.relation == Relation::Equal {
+        let stem = reftest
+            .test
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("reftest")
+            .to_string();
+        write_png(&test_surface, &output_dir.join(format!("{}.png", stem)))?;
+        write_png(&ref_surface, &output_dir.join(format!("{}-ref.png", stem)))?;
+        let diff_path = output_dir.join(format!("{}-diff.png", stem));
+        write_png(&diff_surface, &diff_path)?;
+        Some(diff_path)
+    } else {
+        None
+    };
+
+    Ok(RefTestResult {
+        reftest: reftest.clone(),
+        passed,
+        differing_pixels,
+        diff_image,
+    })
+}
+
+fn paint(items: &DisplayList) -> io::Result<cairo::ImageSurface> {
+    headless::paint(items, VIEWPORT_WIDTH, VIEWPORT_HEIGHT).map_err(io::Error::other)
+}
+
+/// Counts the differing pixels and builds an image that shows the test rendering faded out,
+/// with every differing pixel in red.
+fn compare(
+    test: &mut cairo::ImageSurface,
+    reference: &mut cairo::ImageSurface,
+) -> (usize, cairo::ImageSurface) {
+    let (width, height, stride) = (test.width(), test.height(), test.stride() as usize);
+    let mut diff = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height).unwrap();
+    let mut differing_pixels = 0;
+    {
+        let test_data = test.data().unwrap();
+        let ref_data = reference.data().unwrap();
+        let mut diff_data = diff.data().unwrap();
+
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let i = y * stride + x * 4;
+                // ARGB32 is stored as native-endian u32, so the byte order doesn't matter for
+                // comparison; only alpha has a fixed position and we keep it opaque.
+                let pixel = if test_data[i..i + 4] == ref_data[i..i + 4] {
+                    let faded = |c: u8| 0xff - (0xff - c) / 4;
+                    u32::from_ne_bytes([
+                        faded(test_data[i]),
+                        faded(test_data[i + 1]),
+                        faded(test_data[i + 2]),
+                        faded(test_data[i + 3]),
+                    ]) | 0xff00_0000
+                } else {
+                    differing_pixels += 1;
+                    0xffff_0000
+                };
+                diff_data[i..i + 4].copy_from_slice(&pixel.to_ne_bytes());
+            }
+        }
+    }
+    (differing_pixels, diff)
+}
+
+fn write_png(surface: &cairo::ImageSurface, path: &Path) -> io::Result<()> {
+    surface
+        .write_to_png(&mut File::create(path)?)
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+fn file_url(path: &Path) -> io::Result<String> {
+    Ok(format!("file://{}", fs::canonicalize(path)?.display()))
+}
+
+#[test]
+fn test_parse_manifest() {
+    let reftests = parse_manifest(Path::new("reftests/reftest.list")).unwrap();
+    assert!(!reftests.is_empty());
+    assert!(reftests
+        .iter()
+        .all(|r| r.test.exists() && r.reference.exists()));
+}
+
+#[test]
+fn test_reftests() {
+    // The images of failures are kept out of the working tree; their paths are printed.
+    let output_dir =
+        ::std::env::temp_dir().join(format!("reftest-output-{}", ::std::process::id()));
+    let results = run_manifest(Path::new("reftests/reftest.list"), &output_dir).unwrap();
+    assert!(results.iter().all(|r| r.passed));
+    fs::remove_dir(output_dir).unwrap();
+}
@@ -0,0 +1,155 @@
+//! The selectors of a stylesheet, bucketed by the rightmost compound so that styling an element
+//! only looks at the rules that could match it.
+//!
+//! A selector goes into a single bucket, picked from its subject compound: its id if it has one,
+//! otherwise one of its classes, otherwise its tag name. The rest, like ``*``, ``[href]`` or
+//! ``:first-child``, are universal and tried on every element.
+
+use bloom::{self, AncestorFilter};
+use css::{Combinator, Rule, SimpleSelector, Specificity, Stylesheet};
+use dom::ElementData;
+
+use std::collections::HashMap;
+
+pub struct IndexedSelector<'a> {
+    pub rule: &'a Rule,
+    /// The position of the rule in the stylesheet.
+    pub source_order: usize,
+    pub specificity: Specificity,
+    /// See ``Selector::compounds``.
+    pub compounds: Vec<(&'a SimpleSelector, Option<Combinator>)>,
+    /// Whether the selector looks at more than the tag names, ids and classes of the element and
+    /// its ancestors, e.g. at attributes or at the siblings. Siblings that share a style have to
+    /// match these selectors alike.
+    pub needs_revalidation: bool,
+    // The hashes of the names the ancestors of a matching element must have, see ``bloom``.
+    ancestor_hashes: Vec<u32>,
+}
+
+#[derive(Default)]
+pub struct RuleIndex<'a> {
+    ids: HashMap<&'a str, Vec<IndexedSelector<'a>>>,
+    classes: HashMap<&'a str, Vec<IndexedSelector<'a>>>,
+    tags: HashMap<&'a str, Vec<IndexedSelector<'a>>>,
+    universal: Vec<IndexedSelector<'a>>,
+}
+
+impl<'a> IndexedSelector<'a> {
+    /// Whether ``filter`` shows that the element can't have the ancestors the selector needs.
+    pub fn rejected_by(&self, filter: &AncestorFilter) -> bool {
+        !self
+            .ancestor_hashes
+            .iter()
+            .all(|&hash| filter.might_contain(hash))
+    }
+}
+
+impl<'a> RuleIndex<'a> {
+    pub fn new(stylesheet: &'a Stylesheet) -> RuleIndex<'a> {
+        let mut index = RuleIndex::default();
+        for (source_order, rule) in stylesheet.rules.iter().enumerate() {
+            for selector in &rule.selectors {
+                let compounds = selector.compounds();
+                // A compound on the left of a descendant or child combinator is matched against
+                // an ancestor: even after a sibling combinator, the siblings share the parents.
+                let ancestor_hashes = compounds
+                    .iter()
+                    .filter(|&&(_, combinator)| {
+                        matches!(
+                            combinator,
+                            Some(Combinator::Descendant) | Some(Combinator::Child)
+                        )
+                    })
+                    .flat_map(|&(compound, _)| {
+                        compound
+                            .tag_name
+                            .iter()
+                            .chain(&compound.id)
+                            .chain(&compound.class)
+                            .map(|name| bloom::hash(name))
+                    })
+                    .collect();
+                let needs_revalidation = compounds.iter().any(|&(compound, combinator)| {
+                    !compound.attributes.is_empty()
+                        || !compound.pseudo_classes.is_empty()
+                        || matches!(
+                            combinator,
+                            Some(Combinator::NextSibling) | Some(Combinator::SubsequentSibling)
+                        )
+                });
+                let subject = compounds.last().unwrap().0;
+                let bucket = if let Some(ref id) = subject.id {
+                    index.ids.entry(id.as_str()).or_default()
+                } else if let Some(class) = subject.class.iter().min() {
+                    index.classes.entry(class.as_str()).or_default()
+                } else if let Some(ref tag_name) = subject.tag_name {
+                    index.tags.entry(tag_name.as_str()).or_default()
+                } else {
+                    &mut index.universal
+                };
+                bucket.push(IndexedSelector {
+                    rule,
+                    source_order,
+                    specificity: selector.specificity(),
+                    compounds,
+                    needs_revalidation,
+                    ancestor_hashes,
+                });
+            }
+        }
+        index
+    }
+
+    /// Returns the selectors whose subject might match ``elem``, in no particular order.
+    pub fn candidates(&self, elem: &ElementData) -> Vec<&IndexedSelector<'a>> {
+        let id = elem.id().and_then(|id| self.ids.get(id.as_str()));
+        let classes = elem.classes();
+        let classes = classes.iter().filter_map(|&class| self.classes.get(class));
+        let tag = self.tags.get(elem.tag_name.as_str());
+        self.universal
+            .iter()
+            .chain(id.into_iter().flatten())
+            .chain(classes.flatten())
+            .chain(tag.into_iter().flatten())
+            .collect()
+    }
+}
+
+#[test]
+fn test_candidates() {
+    use css;
+    use dom::{Node, NodeType};
+
+    let stylesheet = css::parse(
+        "* {} #a {} .b {} .c.d {} p {} p#a.b {} div > .b {} [x] {} #z, q {}".to_string(),
+    );
+    let index = RuleIndex::new(&stylesheet);
+    let candidates = |tag_name: &str, attrs: &[(&str, &str)]| {
+        let node = Node::elem(
+            tag_name.to_string(),
+            attrs
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            vec![],
+        );
+        let elem = match node.data {
+            NodeType::Element(ref elem) => elem,
+            NodeType::Text(_) => unreachable!(),
+        };
+        let mut rules: Vec<usize> = index
+            .candidates(elem)
+            .iter()
+            .map(|selector| selector.source_order)
+            .collect();
+        rules.sort();
+        rules
+    };
+
+    assert_eq!(candidates("span", &[]), [0, 7]);
+    assert_eq!(candidates("p", &[]), [0, 4, 7]);
+    assert_eq!(candidates("q", &[("id", "a")]), [0, 1, 5, 7, 8]);
+    assert_eq!(candidates("span", &[("class", "b c")]), [0, 2, 3, 6, 7]);
+    // ``.c.d`` is only in the bucket of one of its classes.
+    assert_eq!(candidates("span", &[("class", "d")]), [0, 7]);
+}
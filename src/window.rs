@@ -14,8 +14,7 @@ use gtk::{
 
 use std::{cell::RefCell, collections::HashMap};
 
-use css::{px2pt, TextDecoration};
-use font::FONT_DESC;
+use css::TextDecoration;
 use interface::update_html_source;
 use layout::Rect;
 use painter::{DisplayCommand, DisplayList};
@@ -223,6 +222,8 @@ impl RenderingWindow {
                 let surface = SURFACE_CACHE.with(|sc| {
                     if let Some(ref surface) = *sc.borrow_mut() {
                         unsafe {
+                            // Lay out again. ``layout::layout_tree`` also cascades again
+                            // if the new size changes which media queries match.
                             if RESIZED {
                                 RESIZED = false;
                             } else {
@@ -297,7 +298,7 @@ impl RenderingWindow {
     }
 }
 
-fn render_item(
+pub fn render_item(
     ctx: &Context,
     pango_layout: &pango::Layout,
     // layout: &gtk::Layout,
@@ -334,31 +335,23 @@ fn render_item(
             ctx.paint();
         }
         &DisplayCommand::Text(ref text, rect, ref color, ref decorations, ref font) => {
-            FONT_DESC.with(|font_desc| {
-                let mut font_desc = font_desc.borrow_mut();
-                font_desc.set_size(pango::units_from_double(px2pt(font.size.to_f64_px())));
-                font_desc.set_style(font.slant.to_pango_font_slant());
-                font_desc.set_weight(font.weight.to_pango_font_weight());
-
-                let attr_list = pango::AttrList::new();
-                for decoration in decorations {
-                    match decoration {
-                        &TextDecoration::Underline => {
-                            attr_list
-                                .insert(pango::AttrInt::new_underline(pango::Underline::Single));
-                        }
-                        &TextDecoration::Overline => unimplemented!(),
-                        &TextDecoration::LineThrough => {
-                            attr_list.insert(pango::AttrInt::new_strikethrough(true))
-                        }
-                        &TextDecoration::None => {}
+            let attr_list = pango::AttrList::new();
+            for decoration in decorations {
+                match decoration {
+                    &TextDecoration::Underline => {
+                        attr_list.insert(pango::AttrInt::new_underline(pango::Underline::Single));
+                    }
+                    &TextDecoration::Overline => unimplemented!(),
+                    &TextDecoration::LineThrough => {
+                        attr_list.insert(pango::AttrInt::new_strikethrough(true))
                     }
+                    &TextDecoration::None => {}
                 }
+            }
 
-                pango_layout.set_text(text.as_str());
-                pango_layout.set_attributes(Some(&attr_list));
-                pango_layout.set_font_description(Some(&font_desc));
-            });
+            pango_layout.set_text(text.as_str());
+            pango_layout.set_attributes(Some(&attr_list));
+            pango_layout.set_font_description(Some(&font.description()));
 
             ctx.set_source_rgba(
                 color.r as f64 / 255.0,